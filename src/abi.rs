@@ -0,0 +1,1007 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{self, Write};
+
+use gimli::{AttributeValue, EndianSlice, LittleEndian, Reader, SectionId, Unit, UnitOffset};
+
+use crate::bind;
+use crate::elf::{self, Elf};
+
+/// Exit status when the interface changed in ways existing objects cope
+/// with, such as added symbols.
+pub const ABI_CHANGE: i32 = 4;
+/// Exit status when objects built against the old library may break with
+/// the new one. Like abidiff, it includes the bit of `ABI_CHANGE`.
+pub const ABI_INCOMPATIBLE: i32 = ABI_CHANGE | 8;
+
+// Types nested deeper than this are assumed to refer to themselves.
+const MAX_DEPTH: usize = 16;
+
+type Slice<'a> = EndianSlice<'a, LittleEndian>;
+
+// An exported symbol is known by its name and version.
+type Key = (String, Option<String>);
+
+/// A symbol the library exports through .dynsym.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Export {
+    pub kind: u8,
+    pub bind: u8,
+    pub size: u64,
+    /// Unversioned references bind to it.
+    pub default: bool,
+}
+
+/// A data member or base class of a struct, class or union.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    pub type_name: String,
+    /// The offset in bits.
+    pub offset: Option<u64>,
+    pub bit_size: Option<u64>,
+}
+
+/// The layout of a struct, class or union.
+#[derive(Debug)]
+pub struct Layout {
+    pub size: Option<u64>,
+    pub members: Vec<Member>,
+}
+
+/// What a shared library offers the objects linked against it.
+pub struct Interface {
+    pub soname: Option<String>,
+    pub exports: BTreeMap<Key, Export>,
+    /// The version nodes the library defines.
+    pub versions: BTreeSet<String>,
+    /// Whether the library has the DWARF the declarations and types are
+    /// taken from.
+    pub dwarf: bool,
+    /// The declarations of exported functions and variables by symbol.
+    pub declarations: BTreeMap<String, String>,
+    /// The layouts of the types the declarations use, directly or through
+    /// other types, by name.
+    pub types: BTreeMap<String, Layout>,
+}
+
+impl Interface {
+    pub fn new(elf: &Elf) -> io::Result<Interface> {
+        let mut exports = BTreeMap::new();
+        for sym in elf
+            .dynamic_symbols()
+            .iter()
+            .filter(|sym| bind::is_export(sym))
+        {
+            let symbol = sym.symbol;
+            // Version definitions are symbols named after themselves.
+            if matches!(&sym.version, Some(version) if version.name == symbol.name) {
+                continue;
+            }
+
+            exports.insert(
+                (
+                    symbol.name.clone(),
+                    sym.version.as_ref().map(|version| version.name.clone()),
+                ),
+                Export {
+                    kind: symbol.kind(),
+                    bind: symbol.bind(),
+                    size: symbol.size(),
+                    default: sym.version.as_ref().is_none_or(|version| !version.hidden),
+                },
+            );
+        }
+
+        let mut interface = Interface {
+            soname: elf.soname(),
+            exports,
+            versions: elf.defined_versions().into_iter().collect(),
+            dwarf: false,
+            declarations: BTreeMap::new(),
+            types: BTreeMap::new(),
+        };
+
+        let bad_dwarf = |err: gimli::Error| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("bad DWARF: {}", err))
+        };
+        let sections = debug_sections(elf)?;
+        if let Some(info) = DebugInfo::new(&sections).map_err(bad_dwarf)? {
+            info.describe(&mut interface).map_err(bad_dwarf)?;
+            interface.dwarf = true;
+        }
+
+        Ok(interface)
+    }
+}
+
+// The .debug_* sections of `elf` by name, decompressed if need be.
+fn debug_sections(elf: &Elf) -> io::Result<HashMap<String, Cow<'_, [u8]>>> {
+    let mut sections = HashMap::new();
+    for shdr in elf.section_headers() {
+        let name = elf.section_name(shdr);
+        if !name.starts_with(".debug_") || shdr.sh_type == elf::SHT_NOBITS {
+            continue;
+        }
+
+        let data = elf.section_data(shdr);
+        let data = match shdr.sh_flags as u32 & elf::SHF_COMPRESSED {
+            0 => Cow::Borrowed(data),
+            _ => Cow::Owned(decompress(&name, data)?),
+        };
+        sections.insert(name, data);
+    }
+    Ok(sections)
+}
+
+// Uncompress the data of an SHF_COMPRESSED section.
+fn decompress(name: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+    let chdr: elf::Elf64Chdr = elf::read_struct(data, 0)?;
+    let compressed = &data[std::mem::size_of::<elf::Elf64Chdr>()..];
+    let unsupported = |algorithm: &str| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{}: {} compressed DWARF not supported", name, algorithm),
+        )
+    };
+
+    let decompressed = match chdr.ch_type {
+        elf::ELFCOMPRESS_ZLIB => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(
+            compressed,
+            chdr.ch_size as usize,
+        )
+        .map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: bad zlib data: {}", name, err),
+            )
+        })?,
+        elf::ELFCOMPRESS_ZSTD => return Err(unsupported("zstd")),
+        other => return Err(unsupported(&format!("type {}", other))),
+    };
+
+    match decompressed.len() as u64 == chdr.ch_size {
+        true => Ok(decompressed),
+        false => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: decompressed size does not match its header", name),
+        )),
+    }
+}
+
+// The struct, class and union tags, and what C and C++ call them.
+fn aggregate_keyword(tag: gimli::DwTag) -> Option<&'static str> {
+    match tag {
+        gimli::DW_TAG_structure_type => Some("struct"),
+        gimli::DW_TAG_class_type => Some("class"),
+        gimli::DW_TAG_union_type => Some("union"),
+        _ => None,
+    }
+}
+
+fn is_flag_set(value: Option<AttributeValue<Slice>>) -> bool {
+    matches!(value, Some(AttributeValue::Flag(true)))
+}
+
+// The offset of a data member in bits, from a constant or the DWARF 2
+// DW_OP_plus_uconst expression.
+fn member_offset(
+    location: Option<AttributeValue<Slice>>,
+    bit_offset: Option<AttributeValue<Slice>>,
+) -> Option<u64> {
+    if let Some(bits) = bit_offset.and_then(|value| value.udata_value()) {
+        return Some(bits);
+    }
+
+    let bytes = match location? {
+        AttributeValue::Exprloc(gimli::Expression(mut expr)) => {
+            match expr.read_u8().ok()? == gimli::DW_OP_plus_uconst.0 {
+                true => gimli::leb128::read::unsigned(&mut expr).ok()?,
+                false => return None,
+            }
+        }
+        value => value.udata_value()?,
+    };
+    Some(bytes * 8)
+}
+
+// The DWARF of a library, with the names of its scopes and types.
+struct DebugInfo<'a> {
+    dwarf: gimli::Dwarf<Slice<'a>>,
+    units: Vec<Unit<Slice<'a>>>,
+    // The qualified names of the named entries of each unit.
+    names: Vec<HashMap<UnitOffset, String>>,
+    // Where struct, class and union types are defined, by name.
+    definitions: HashMap<String, (usize, UnitOffset)>,
+    // The definitions of external functions and variables, with their
+    // symbol.
+    objects: Vec<(String, usize, UnitOffset)>,
+}
+
+impl<'a> DebugInfo<'a> {
+    // Load the DWARF from the debug sections of a library, or None if it
+    // has none.
+    fn new(sections: &'a HashMap<String, Cow<'a, [u8]>>) -> gimli::Result<Option<DebugInfo<'a>>> {
+        if !sections.contains_key(".debug_info") {
+            return Ok(None);
+        }
+
+        let dwarf = gimli::Dwarf::load(|id: SectionId| -> gimli::Result<Slice<'a>> {
+            let data = sections.get(id.name()).map_or(&[][..], |data| &data[..]);
+            Ok(EndianSlice::new(data, LittleEndian))
+        })?;
+
+        let mut units = Vec::new();
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next()? {
+            units.push(dwarf.unit(header)?);
+        }
+
+        let mut info = DebugInfo {
+            dwarf,
+            units,
+            names: Vec::new(),
+            definitions: HashMap::new(),
+            objects: Vec::new(),
+        };
+        let mut definitions = HashMap::new();
+        let mut objects = Vec::new();
+        for index in 0..info.units.len() {
+            let names = info.scan(index, &mut definitions, &mut objects)?;
+            info.names.push(names);
+        }
+        info.definitions = definitions;
+        info.objects = objects;
+
+        Ok(Some(info))
+    }
+
+    fn attr_string(&self, index: usize, value: AttributeValue<Slice>) -> gimli::Result<String> {
+        let name = self.dwarf.attr_string(&self.units[index], value)?;
+        Ok(name.to_string_lossy().into_owned())
+    }
+
+    // Name the entries of a unit after the namespaces and classes they
+    // are in, and find the aggregate types and external functions and
+    // variables it defines.
+    fn scan(
+        &self,
+        index: usize,
+        definitions: &mut HashMap<String, (usize, UnitOffset)>,
+        objects: &mut Vec<(String, usize, UnitOffset)>,
+    ) -> gimli::Result<HashMap<UnitOffset, String>> {
+        let unit = &self.units[index];
+        let mut names = HashMap::new();
+        let mut typedefs = Vec::new();
+        // The prefix each enclosing entry gives the names in it.
+        let mut scopes: Vec<String> = Vec::new();
+        let mut depth = 0;
+
+        let mut cursor = unit.entries();
+        while let Some((delta, entry)) = cursor.next_dfs()? {
+            depth += delta;
+            scopes.truncate(depth.max(0) as usize);
+            let prefix = scopes.last().cloned().unwrap_or_default();
+
+            let name = match entry.attr_value(gimli::DW_AT_name)? {
+                Some(value) => Some(format!("{}{}", prefix, self.attr_string(index, value)?)),
+                None => None,
+            };
+            let declaration = is_flag_set(entry.attr_value(gimli::DW_AT_declaration)?);
+            let tag = entry.tag();
+
+            match (tag, &name) {
+                (gimli::DW_TAG_namespace, Some(name)) => scopes.push(format!("{}::", name)),
+                (_, Some(name)) if aggregate_keyword(tag).is_some() => {
+                    scopes.push(format!("{}::", name))
+                }
+                _ => scopes.push(prefix),
+            }
+
+            match tag {
+                _ if declaration => {}
+                gimli::DW_TAG_typedef => {
+                    if let (Some(AttributeValue::UnitRef(target)), Some(name)) =
+                        (entry.attr_value(gimli::DW_AT_type)?, &name)
+                    {
+                        typedefs.push((target, name.clone()));
+                    }
+                }
+                gimli::DW_TAG_subprogram | gimli::DW_TAG_variable => {
+                    if let Some(symbol) = self.symbol_name(index, entry.offset())? {
+                        objects.push((symbol, index, entry.offset()));
+                    }
+                }
+                _ => {
+                    if let (Some(keyword), Some(name)) = (aggregate_keyword(tag), &name) {
+                        definitions
+                            .entry(format!("{} {}", keyword, name))
+                            .or_insert((index, entry.offset()));
+                    }
+                }
+            }
+
+            if let Some(name) = name {
+                names.insert(entry.offset(), name);
+            }
+        }
+
+        // Anonymous types are known by the typedefs naming them.
+        for (target, name) in typedefs {
+            if names.contains_key(&target) {
+                continue;
+            }
+            let entry = unit.entry(target)?;
+            if let Some(keyword) = aggregate_keyword(entry.tag()) {
+                if !is_flag_set(entry.attr_value(gimli::DW_AT_declaration)?) {
+                    definitions
+                        .entry(format!("{} {}", keyword, name))
+                        .or_insert((index, target));
+                }
+                names.insert(target, name);
+            }
+        }
+
+        Ok(names)
+    }
+
+    // The entries an entry completes: the declaration a definition
+    // refers to, and the abstract instance of an inlined function.
+    fn origins(&self, index: usize, offset: UnitOffset) -> gimli::Result<Vec<UnitOffset>> {
+        let unit = &self.units[index];
+        let mut offsets = vec![offset];
+
+        while offsets.len() < MAX_DEPTH {
+            let entry = unit.entry(*offsets.last().unwrap())?;
+            let origin = match entry.attr_value(gimli::DW_AT_specification)? {
+                Some(origin) => Some(origin),
+                None => entry.attr_value(gimli::DW_AT_abstract_origin)?,
+            };
+            match origin {
+                Some(AttributeValue::UnitRef(origin)) => offsets.push(origin),
+                _ => break,
+            }
+        }
+
+        Ok(offsets)
+    }
+
+    // An attribute of an entry or of the entries it completes.
+    fn attr(
+        &self,
+        index: usize,
+        offset: UnitOffset,
+        name: gimli::DwAt,
+    ) -> gimli::Result<Option<AttributeValue<Slice<'a>>>> {
+        for offset in self.origins(index, offset)? {
+            if let Some(value) = self.units[index].entry(offset)?.attr_value(name)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    // The symbol of an external function or variable, or None if it is
+    // not external.
+    fn symbol_name(&self, index: usize, offset: UnitOffset) -> gimli::Result<Option<String>> {
+        if !is_flag_set(self.attr(index, offset, gimli::DW_AT_external)?) {
+            return Ok(None);
+        }
+
+        let value = match self.attr(index, offset, gimli::DW_AT_linkage_name)? {
+            Some(value) => Some(value),
+            None => match self.attr(index, offset, gimli::DW_AT_MIPS_linkage_name)? {
+                Some(value) => Some(value),
+                None => self.attr(index, offset, gimli::DW_AT_name)?,
+            },
+        };
+        value
+            .map(|value| self.attr_string(index, value))
+            .transpose()
+    }
+
+    // Describe the exported functions and variables of `interface`, and
+    // the layouts of the types they use.
+    fn describe(&self, interface: &mut Interface) -> gimli::Result<()> {
+        let exported: HashSet<&str> = interface
+            .exports
+            .keys()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let mut reached = Vec::new();
+
+        for (symbol, index, offset) in self.objects.iter() {
+            if !exported.contains(symbol.as_str()) || interface.declarations.contains_key(symbol) {
+                continue;
+            }
+            let declaration = self.declaration(*index, *offset, &mut reached)?;
+            interface.declarations.insert(symbol.clone(), declaration);
+        }
+
+        while let Some(name) = reached.pop() {
+            if interface.types.contains_key(&name) {
+                continue;
+            }
+            if let Some(&(index, offset)) = self.definitions.get(&name) {
+                let unit = &self.units[index];
+                let size = unit
+                    .entry(offset)?
+                    .attr_value(gimli::DW_AT_byte_size)?
+                    .and_then(|value| value.udata_value());
+                let members = self.members(index, offset, &mut reached, 0)?;
+                interface.types.insert(name, Layout { size, members });
+            }
+        }
+
+        Ok(())
+    }
+
+    // A function or variable the way C declares it, adding the aggregate
+    // types it uses to `reached`.
+    fn declaration(
+        &self,
+        index: usize,
+        offset: UnitOffset,
+        reached: &mut Vec<String>,
+    ) -> gimli::Result<String> {
+        let origins = self.origins(index, offset)?;
+        let name = origins
+            .iter()
+            .find_map(|offset| self.names[index].get(offset))
+            .cloned()
+            .unwrap_or_default();
+        let type_name = self.type_name(
+            index,
+            self.attr(index, offset, gimli::DW_AT_type)?,
+            reached,
+            0,
+        )?;
+
+        let unit = &self.units[index];
+        if unit.entry(offset)?.tag() == gimli::DW_TAG_variable {
+            return Ok(format!("{} {}", type_name, name));
+        }
+
+        // Inlined and optimized instances may leave the parameters to the
+        // entries they complete.
+        let mut parameters = Vec::new();
+        for offset in origins {
+            parameters = self.parameters(index, offset, reached)?;
+            if !parameters.is_empty() {
+                break;
+            }
+        }
+        Ok(format!("{} {}({})", type_name, name, parameters.join(", ")))
+    }
+
+    // The parameter types of a function or function type, leaving out the
+    // implicit `this`.
+    fn parameters(
+        &self,
+        index: usize,
+        offset: UnitOffset,
+        reached: &mut Vec<String>,
+    ) -> gimli::Result<Vec<String>> {
+        let mut tree = self.units[index].entries_tree(Some(offset))?;
+        let mut children = tree.root()?.children();
+        let mut parameters = Vec::new();
+
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                gimli::DW_TAG_formal_parameter => {
+                    let value = self.attr(index, entry.offset(), gimli::DW_AT_type)?;
+                    let type_name = self.type_name(index, value, reached, 0)?;
+                    // The class is still reached through `this`.
+                    if !is_flag_set(self.attr(index, entry.offset(), gimli::DW_AT_artificial)?) {
+                        parameters.push(type_name);
+                    }
+                }
+                gimli::DW_TAG_unspecified_parameters => parameters.push(String::from("...")),
+                _ => {}
+            }
+        }
+
+        Ok(parameters)
+    }
+
+    // The data members and base classes of an aggregate type.
+    fn members(
+        &self,
+        index: usize,
+        offset: UnitOffset,
+        reached: &mut Vec<String>,
+        depth: usize,
+    ) -> gimli::Result<Vec<Member>> {
+        let mut tree = self.units[index].entries_tree(Some(offset))?;
+        let mut children = tree.root()?.children();
+        let mut members = Vec::new();
+        let mut anonymous = 0;
+
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            // Static data members are declared in the type, but live
+            // elsewhere.
+            if !matches!(
+                entry.tag(),
+                gimli::DW_TAG_member | gimli::DW_TAG_inheritance
+            ) || is_flag_set(entry.attr_value(gimli::DW_AT_declaration)?)
+            {
+                continue;
+            }
+
+            let type_name =
+                self.type_name(index, entry.attr_value(gimli::DW_AT_type)?, reached, depth)?;
+            let name = match (entry.tag(), entry.attr_value(gimli::DW_AT_name)?) {
+                (gimli::DW_TAG_inheritance, _) => format!("base {}", type_name),
+                (_, Some(value)) => self.attr_string(index, value)?,
+                (_, None) => {
+                    anonymous += 1;
+                    format!("<anonymous {}>", anonymous)
+                }
+            };
+
+            members.push(Member {
+                name,
+                type_name,
+                offset: member_offset(
+                    entry.attr_value(gimli::DW_AT_data_member_location)?,
+                    entry.attr_value(gimli::DW_AT_data_bit_offset)?,
+                ),
+                bit_size: entry
+                    .attr_value(gimli::DW_AT_bit_size)?
+                    .and_then(|value| value.udata_value()),
+            });
+        }
+
+        Ok(members)
+    }
+
+    // The name of the type `value` refers to, spelled out for pointers,
+    // arrays and anonymous types, adding the aggregate types it uses to
+    // `reached`.
+    fn type_name(
+        &self,
+        index: usize,
+        value: Option<AttributeValue<Slice>>,
+        reached: &mut Vec<String>,
+        depth: usize,
+    ) -> gimli::Result<String> {
+        let offset = match value {
+            Some(AttributeValue::UnitRef(offset)) => offset,
+            None => return Ok(String::from("void")),
+            Some(_) => return Ok(String::from("?")),
+        };
+        if depth > MAX_DEPTH {
+            return Ok(String::from("..."));
+        }
+
+        let unit = &self.units[index];
+        let entry = unit.entry(offset)?;
+        let name = self.names[index].get(&offset).cloned();
+        let target = entry.attr_value(gimli::DW_AT_type)?;
+        let mut inner = || self.type_name(index, target, reached, depth + 1);
+
+        let type_name = match entry.tag() {
+            gimli::DW_TAG_pointer_type => format!("{} *", inner()?),
+            gimli::DW_TAG_reference_type => format!("{} &", inner()?),
+            gimli::DW_TAG_rvalue_reference_type => format!("{} &&", inner()?),
+            gimli::DW_TAG_ptr_to_member_type => format!("{} ::*", inner()?),
+            gimli::DW_TAG_const_type => format!("const {}", inner()?),
+            gimli::DW_TAG_volatile_type => format!("volatile {}", inner()?),
+            gimli::DW_TAG_restrict_type => format!("{} restrict", inner()?),
+            gimli::DW_TAG_atomic_type => format!("_Atomic {}", inner()?),
+            gimli::DW_TAG_enumeration_type => {
+                format!(
+                    "enum {}",
+                    name.unwrap_or_else(|| String::from("<anonymous>"))
+                )
+            }
+            gimli::DW_TAG_typedef => {
+                // The typedef stands for what it names, which has to be
+                // reached too.
+                inner()?;
+                name.unwrap_or_default()
+            }
+            gimli::DW_TAG_array_type => {
+                let element = inner()?;
+                let mut tree = unit.entries_tree(Some(offset))?;
+                let mut children = tree.root()?.children();
+                let mut bounds = String::new();
+                while let Some(child) = children.next()? {
+                    let entry = child.entry();
+                    if entry.tag() != gimli::DW_TAG_subrange_type {
+                        continue;
+                    }
+                    let count = match entry.attr_value(gimli::DW_AT_count)? {
+                        Some(count) => count.udata_value(),
+                        None => entry
+                            .attr_value(gimli::DW_AT_upper_bound)?
+                            .and_then(|bound| bound.udata_value())
+                            .map(|bound| bound + 1),
+                    };
+                    match count {
+                        Some(count) => bounds.push_str(&format!("[{}]", count)),
+                        None => bounds.push_str("[]"),
+                    }
+                }
+                format!("{}{}", element, bounds)
+            }
+            gimli::DW_TAG_subroutine_type => {
+                let result = inner()?;
+                let parameters = self.parameters(index, offset, reached)?;
+                format!("{}({})", result, parameters.join(", "))
+            }
+            tag => match (aggregate_keyword(tag), name) {
+                (Some(keyword), Some(name)) => {
+                    let type_name = format!("{} {}", keyword, name);
+                    reached.push(type_name.clone());
+                    type_name
+                }
+                // Anonymous members are part of the enclosing layout.
+                (Some(keyword), None) => {
+                    let members = self.members(index, offset, reached, depth + 1)?;
+                    let members: Vec<String> = members
+                        .iter()
+                        .map(|member| format!("{} {};", member.type_name, member.name))
+                        .collect();
+                    format!("{} {{ {} }}", keyword, members.join(" "))
+                }
+                (None, Some(name)) => name,
+                (None, None) => String::from("?"),
+            },
+        };
+
+        Ok(type_name)
+    }
+}
+
+/// The part of the interface a change is to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Area {
+    Soname,
+    Version,
+    Symbol,
+    Declaration,
+    Type,
+}
+
+impl Area {
+    fn title(&self) -> &'static str {
+        match self {
+            Area::Soname => "SONAME",
+            Area::Version => "Version nodes",
+            Area::Symbol => "Symbols",
+            Area::Declaration => "Functions and variables",
+            Area::Type => "Types",
+        }
+    }
+}
+
+/// A difference between the interfaces of two versions of a library.
+#[derive(Debug)]
+pub struct Change {
+    pub area: Area,
+    pub name: String,
+    pub details: Vec<String>,
+    /// Objects built against the old library may not work with the new
+    /// one.
+    pub incompatible: bool,
+}
+
+/// The changes from one version of a library's interface to the next.
+pub struct AbiDiff {
+    pub changes: Vec<Change>,
+    old_dwarf: bool,
+    new_dwarf: bool,
+}
+
+// The name of an exported symbol with its version, as nm -D shows it.
+fn export_name((name, version): &Key, export: &Export) -> String {
+    match (version, export.default) {
+        (Some(version), true) => format!("{}@@{}", name, version),
+        (Some(version), false) => format!("{}@{}", name, version),
+        (None, _) => name.clone(),
+    }
+}
+
+fn kind_name(kind: u8) -> String {
+    elf::ELF_ST_TYPE
+        .get(&kind)
+        .map_or_else(|| kind.to_string(), |name| name.to_string())
+}
+
+fn bind_name(bind: u8) -> String {
+    elf::ELF_ST_BIND
+        .get(&bind)
+        .map_or_else(|| bind.to_string(), |name| name.to_string())
+}
+
+// The export of `new` references to `key` in objects built against the
+// old library bind to.
+fn bound_export<'a>(new: &'a Interface, key: &Key) -> Option<&'a Export> {
+    let (name, version) = key;
+    match version {
+        // Libraries without versions satisfy any version.
+        Some(_) if new.versions.is_empty() => new.exports.get(&(name.clone(), None)),
+        Some(_) => new.exports.get(key),
+        None => new
+            .exports
+            .iter()
+            .find(|((other, _), export)| other == name && export.default)
+            .map(|(_, export)| export),
+    }
+}
+
+// How the export an old reference binds to differs from the old one.
+fn export_changes(old: &Export, new: &Export) -> (Vec<String>, bool) {
+    let mut details = Vec::new();
+    let mut incompatible = false;
+
+    if old.kind != new.kind {
+        details.push(format!(
+            "type {} -> {}",
+            kind_name(old.kind),
+            kind_name(new.kind)
+        ));
+        incompatible = true;
+    }
+    // Executables keep copies of the variables they use, as large as the
+    // old library made them.
+    if matches!(old.kind, elf::STT_OBJECT | elf::STT_TLS) && old.size != new.size {
+        details.push(format!("size {} -> {}", old.size, new.size));
+        incompatible = true;
+    }
+    if old.bind != new.bind {
+        details.push(format!(
+            "binding {} -> {}",
+            bind_name(old.bind),
+            bind_name(new.bind)
+        ));
+    }
+
+    (details, incompatible)
+}
+
+// How the layout of a type differs, member by member.
+fn layout_changes(old: &Layout, new: &Layout) -> Vec<String> {
+    let mut details = Vec::new();
+    let offset = |offset: Option<u64>| match offset {
+        Some(bits) if bits % 8 == 0 => format!("offset {}", bits / 8),
+        Some(bits) => format!("bit offset {}", bits),
+        None => String::from("no offset"),
+    };
+
+    if old.size != new.size {
+        let size = |size: Option<u64>| size.map_or_else(|| String::from("?"), |s| s.to_string());
+        details.push(format!(
+            "size {} -> {} bytes",
+            size(old.size),
+            size(new.size)
+        ));
+    }
+
+    for member in old.members.iter() {
+        match new.members.iter().find(|other| other.name == member.name) {
+            None => details.push(format!("member {} removed", member.name)),
+            Some(other) => {
+                if member.type_name != other.type_name {
+                    details.push(format!(
+                        "member {} changed type {} -> {}",
+                        member.name, member.type_name, other.type_name
+                    ));
+                }
+                if member.offset != other.offset {
+                    details.push(format!(
+                        "member {} moved from {} to {}",
+                        member.name,
+                        offset(member.offset),
+                        offset(other.offset)
+                    ));
+                }
+                if member.bit_size != other.bit_size {
+                    details.push(format!(
+                        "member {} changed bit size {} -> {}",
+                        member.name,
+                        member.bit_size.unwrap_or(0),
+                        other.bit_size.unwrap_or(0)
+                    ));
+                }
+            }
+        }
+    }
+    for member in new.members.iter() {
+        if !old.members.iter().any(|other| other.name == member.name) {
+            details.push(format!(
+                "member {} added at {} ({})",
+                member.name,
+                offset(member.offset),
+                member.type_name
+            ));
+        }
+    }
+
+    details
+}
+
+impl AbiDiff {
+    /// Compare the interface of an old library with a new one. Whatever
+    /// objects built against the old one rely on and the new one drops or
+    /// changes is incompatible.
+    pub fn new(old: &Interface, new: &Interface) -> AbiDiff {
+        let mut changes = Vec::new();
+        let mut change = |area, name: String, details: Vec<String>, incompatible| {
+            changes.push(Change {
+                area,
+                name,
+                details,
+                incompatible,
+            })
+        };
+        let missing =
+            |name: &Option<String>| name.clone().unwrap_or_else(|| String::from("(none)"));
+
+        if old.soname != new.soname {
+            change(
+                Area::Soname,
+                format!("{} -> {}", missing(&old.soname), missing(&new.soname)),
+                Vec::new(),
+                true,
+            );
+        }
+
+        for version in old.versions.difference(&new.versions) {
+            change(
+                Area::Version,
+                version.clone(),
+                vec![String::from("removed")],
+                true,
+            );
+        }
+        for version in new.versions.difference(&old.versions) {
+            change(
+                Area::Version,
+                version.clone(),
+                vec![String::from("added")],
+                false,
+            );
+        }
+
+        for (key, export) in old.exports.iter() {
+            let name = export_name(key, export);
+            match bound_export(new, key) {
+                None => change(Area::Symbol, name, vec![String::from("removed")], true),
+                Some(other) => {
+                    let (details, incompatible) = export_changes(export, other);
+                    if !details.is_empty() {
+                        change(Area::Symbol, name, details, incompatible);
+                    }
+                }
+            }
+        }
+        for (key, export) in new.exports.iter() {
+            let (name, version) = key;
+            let known = old.exports.contains_key(key)
+                || match version {
+                    Some(_) => export.default && old.exports.contains_key(&(name.clone(), None)),
+                    None => old.exports.keys().any(|(other, _)| other == name),
+                };
+            if !known {
+                change(
+                    Area::Symbol,
+                    export_name(key, export),
+                    vec![String::from("added")],
+                    false,
+                );
+            }
+        }
+
+        for (symbol, declaration) in old.declarations.iter() {
+            match new.declarations.get(symbol) {
+                Some(other) if other != declaration => change(
+                    Area::Declaration,
+                    symbol.clone(),
+                    vec![format!("- {}", declaration), format!("+ {}", other)],
+                    true,
+                ),
+                _ => {}
+            }
+        }
+
+        for (name, layout) in old.types.iter() {
+            if let Some(other) = new.types.get(name) {
+                let details = layout_changes(layout, other);
+                if !details.is_empty() {
+                    change(Area::Type, name.clone(), details, true);
+                }
+            }
+        }
+
+        changes.sort_by_key(|change| change.area);
+        AbiDiff {
+            changes,
+            old_dwarf: old.dwarf,
+            new_dwarf: new.dwarf,
+        }
+    }
+
+    pub fn is_incompatible(&self) -> bool {
+        self.changes.iter().any(|change| change.incompatible)
+    }
+
+    /// The exit status telling what kind of changes there are, see
+    /// `ABI_CHANGE` and `ABI_INCOMPATIBLE`.
+    pub fn exit_code(&self) -> i32 {
+        match (self.changes.is_empty(), self.is_incompatible()) {
+            (true, _) => 0,
+            (false, false) => ABI_CHANGE,
+            (false, true) => ABI_INCOMPATIBLE,
+        }
+    }
+
+    /// Print the changes by area, marking incompatible ones with `!`,
+    /// additions with `+` and other compatible ones with `~`.
+    pub fn to_str(
+        &self,
+        old_file: &str,
+        new_file: &str,
+        demangle: bool,
+        buf: &mut dyn Write,
+    ) -> io::Result<()> {
+        buf.write_fmt(format_args!("--- {}\n+++ {}\n", old_file, new_file))?;
+        for (file, dwarf) in [(old_file, self.old_dwarf), (new_file, self.new_dwarf)] {
+            if !dwarf {
+                buf.write_fmt(format_args!(
+                    "\nNo DWARF in {}, function signatures and types are not compared.\n",
+                    file
+                ))?;
+            }
+        }
+
+        let mut area = None;
+        for change in self.changes.iter() {
+            if area != Some(change.area) {
+                area = Some(change.area);
+                buf.write_fmt(format_args!("\n{}:\n", change.area.title()))?;
+            }
+
+            let marker = match (change.incompatible, change.details.first()) {
+                (true, _) => '!',
+                (false, Some(detail)) if detail == "added" => '+',
+                (false, _) => '~',
+            };
+            let name = match (demangle, change.area) {
+                (true, Area::Symbol | Area::Declaration) => match change.name.find('@') {
+                    Some(at) => format!(
+                        "{}{}",
+                        crate::demangle::demangle(&change.name[..at]),
+                        &change.name[at..]
+                    ),
+                    None => crate::demangle::demangle(&change.name),
+                },
+                _ => change.name.clone(),
+            };
+
+            match change.details.as_slice() {
+                [] => buf.write_fmt(format_args!("  {} {}\n", marker, name))?,
+                [detail] => buf.write_fmt(format_args!("  {} {}: {}\n", marker, name, detail))?,
+                details => {
+                    buf.write_fmt(format_args!("  {} {}\n", marker, name))?;
+                    for detail in details {
+                        buf.write_fmt(format_args!("      {}\n", detail))?;
+                    }
+                }
+            }
+        }
+
+        let incompatible = self.changes.iter().filter(|c| c.incompatible).count();
+        match self.changes.len() {
+            0 => buf.write_fmt(format_args!("\nNo ABI changes.\n")),
+            total => buf.write_fmt(format_args!(
+                "\n{} incompatible and {} compatible change(s).\n",
+                incompatible,
+                total - incompatible
+            )),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+const ARMAG: &[u8] = b"!<arch>\n";
+const THINMAG: &[u8] = b"!<thin>\n";
+const ARFMAG: &[u8] = b"`\n";
+const AR_HDR_SIZE: usize = 60;
+
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(ARMAG) || data.starts_with(THINMAG)
+}
+
+pub struct Member {
+    pub name: String,
+    /// Offset of the member header inside the archive, as referenced by the
+    /// symbol index.
+    pub offset: usize,
+    /// The contents, or why the file of a thin archive member could not be
+    /// read.
+    pub data: io::Result<Vec<u8>>,
+}
+
+pub struct ArchiveSymbol {
+    pub name: String,
+    pub offset: usize,
+}
+
+pub struct Archive {
+    thin: bool,
+    symbols: Vec<ArchiveSymbol>,
+    members: Vec<Member>,
+}
+
+// The fixed size header preceding every member (struct ar_hdr).
+struct ArHdr<'a> {
+    name: &'a str,
+    size: usize,
+}
+
+impl<'a> ArHdr<'a> {
+    fn from_bytes(data: &'a [u8], off: usize) -> io::Result<ArHdr<'a>> {
+        let hdr = data
+            .get(off..off + AR_HDR_SIZE)
+            .ok_or_else(|| invalid("truncated archive member header"))?;
+
+        if &hdr[58..60] != ARFMAG {
+            return Err(invalid("bad archive member header magic"));
+        }
+
+        let name = std::str::from_utf8(&hdr[0..16])
+            .map_err(|_| invalid("bad archive member name"))?
+            .trim_end();
+        let size = std::str::from_utf8(&hdr[48..58])
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .ok_or_else(|| invalid("bad archive member size"))?;
+
+        Ok(ArHdr { name, size })
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Archive {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Archive> {
+        let path = path.as_ref();
+        let data = fs::read(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        Archive::from_bytes(&data, dir)
+    }
+
+    /// Parse a System V/GNU archive. Members of a thin archive are read
+    /// from disk relative to `dir`, the directory holding the archive; one
+    /// that cannot be read is kept with the error.
+    pub fn from_bytes(data: &[u8], dir: &Path) -> io::Result<Archive> {
+        let thin = data.starts_with(THINMAG);
+        if !thin && !data.starts_with(ARMAG) {
+            return Err(invalid("not an archive"));
+        }
+
+        let mut archive = Archive {
+            thin,
+            symbols: Vec::new(),
+            members: Vec::new(),
+        };
+        let mut long_names: &[u8] = &[];
+        let mut off = ARMAG.len();
+
+        while off < data.len() {
+            let hdr = ArHdr::from_bytes(data, off)?;
+            let start = off + AR_HDR_SIZE;
+
+            // Thin archives only carry the symbol index and long name table
+            // inline, regular members live in separate files.
+            let special = hdr.name == "/" || hdr.name == "/SYM64/" || hdr.name == "//";
+            let inline = !thin || special;
+            let body = if inline {
+                data.get(start..start + hdr.size)
+                    .ok_or_else(|| invalid("archive member extends past end of file"))?
+            } else {
+                &[]
+            };
+
+            match hdr.name {
+                "/" => archive.symbols = read_index(body, 4)?,
+                "/SYM64/" => archive.symbols = read_index(body, 8)?,
+                "//" => long_names = body,
+                name => {
+                    let name = member_name(name, long_names)?;
+                    let data = match thin {
+                        true => {
+                            let path = dir.join(&name);
+                            fs::read(&path).map_err(|err| {
+                                io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+                            })
+                        }
+                        false => Ok(body.to_vec()),
+                    };
+
+                    archive.members.push(Member {
+                        name,
+                        offset: off,
+                        data,
+                    });
+                }
+            }
+
+            off = start + body.len();
+            // Member headers are aligned on even offsets.
+            off += off & 1;
+        }
+
+        Ok(archive)
+    }
+
+    pub fn is_thin(&self) -> bool {
+        self.thin
+    }
+
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    pub fn symbols(&self) -> &[ArchiveSymbol] {
+        &self.symbols
+    }
+
+    pub fn symbol_member(&self, sym: &ArchiveSymbol) -> Option<&Member> {
+        self.members.iter().find(|m| m.offset == sym.offset)
+    }
+
+    /// Print the archive symbol index, in the format of `nm --print-armap`.
+    pub fn to_str(&self, buf: &mut dyn Write) -> io::Result<()> {
+        buf.write_fmt(format_args!("Archive index:\n"))?;
+
+        for sym in self.symbols.iter() {
+            match self.symbol_member(sym) {
+                Some(member) => buf.write_fmt(format_args!("{} in {}\n", sym.name, member.name))?,
+                None => buf.write_fmt(format_args!("{} in ??\n", sym.name))?,
+            }
+        }
+
+        buf.write_fmt(format_args!("\n"))
+    }
+}
+
+// Resolve a member name: "/123" indexes the long name table, GNU short
+// names are terminated by '/'.
+fn member_name(name: &str, long_names: &[u8]) -> io::Result<String> {
+    if let Some(index) = name.strip_prefix('/') {
+        let off: usize = index
+            .parse()
+            .map_err(|_| invalid("bad archive long name index"))?;
+        let table = long_names
+            .get(off..)
+            .ok_or_else(|| invalid("archive long name index out of range"))?;
+        let end = table
+            .windows(2)
+            .position(|w| w == b"/\n")
+            .or_else(|| table.iter().position(|&c| c == b'\n' || c == b'\0'))
+            .unwrap_or(table.len());
+
+        return Ok(String::from_utf8_lossy(&table[..end]).into_owned());
+    }
+
+    Ok(name.strip_suffix('/').unwrap_or(name).to_string())
+}
+
+// The symbol index is a big endian count, that many member offsets and the
+// NUL terminated symbol names. `width` is 4 for "/" and 8 for "/SYM64/".
+fn read_index(body: &[u8], width: usize) -> io::Result<Vec<ArchiveSymbol>> {
+    let read_be = |off: usize| -> io::Result<usize> {
+        let bytes = body
+            .get(off..off + width)
+            .ok_or_else(|| invalid("truncated archive symbol index"))?;
+        Ok(bytes.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize))
+    };
+
+    let count = read_be(0)?;
+    let names_off = count
+        .checked_add(1)
+        .and_then(|n| n.checked_mul(width))
+        .ok_or_else(|| invalid("bad archive symbol index"))?;
+    let mut names = body
+        .get(names_off..)
+        .ok_or_else(|| invalid("truncated archive symbol index"))?
+        .split(|&c| c == b'\0');
+
+    let mut symbols = Vec::with_capacity(count.min(body.len()));
+    for i in 0..count {
+        let name = names
+            .next()
+            .ok_or_else(|| invalid("truncated archive symbol names"))?;
+        symbols.push(ArchiveSymbol {
+            name: String::from_utf8_lossy(name).into_owned(),
+            offset: read_be((i + 1) * width)?,
+        });
+    }
+
+    Ok(symbols)
+}
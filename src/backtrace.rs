@@ -0,0 +1,359 @@
+use std::io::{self, Write};
+
+use gimli::{
+    BaseAddresses, CfaRule, EhFrame, EhFrameHdr, EndianSlice, FrameDescriptionEntry, LittleEndian,
+    Pointer, Register, RegisterRule, UnwindContext, UnwindSection,
+};
+
+use crate::coredump::{self, Core, Thread};
+use crate::elf::{self, Elf, Options};
+
+// Frames beyond this are assumed to be a loop in a corrupt stack.
+const MAX_FRAMES: usize = 256;
+
+/// A stack frame: its program counter and what it resolves to.
+pub struct Frame {
+    pub pc: u64,
+    pub module: Option<String>,
+    /// The symbol covering the frame and the offset into it.
+    pub symbol: Option<(String, u64)>,
+}
+
+// A file mapped into the dumped process, parsed from its local copy.
+struct Module {
+    path: String,
+    start: u64,
+    end: u64,
+    // What to add to the file's addresses to get run-time ones.
+    bias: u64,
+    elf: Elf,
+}
+
+type Slice<'a> = EndianSlice<'a, LittleEndian>;
+
+impl Module {
+    // The bytes of the file at link-time address `vaddr`, up to the end of
+    // the segment holding it.
+    fn bytes_at(&self, vaddr: u64) -> Option<&[u8]> {
+        let phdr = self
+            .elf
+            .program_headers()
+            .iter()
+            .filter(|phdr| phdr.p_type == elf::PT_LOAD)
+            .find(|phdr| phdr.p_vaddr <= vaddr && vaddr - phdr.p_vaddr < phdr.p_filesz)?;
+        let start = phdr.p_offset + (vaddr - phdr.p_vaddr);
+        let end = phdr.p_offset.checked_add(phdr.p_filesz)?;
+        self.elf.data().get(start as usize..end as usize)
+    }
+
+    // The FDE covering link-time address `pc`, looked up in the binary
+    // search table of .eh_frame_hdr, or by walking .eh_frame when there
+    // is none.
+    fn find_fde(
+        &self,
+        pc: u64,
+    ) -> Option<(
+        EhFrame<Slice<'_>>,
+        BaseAddresses,
+        FrameDescriptionEntry<Slice<'_>>,
+    )> {
+        let hdr = self
+            .elf
+            .program_headers()
+            .iter()
+            .find(|phdr| phdr.p_type == elf::PT_GNU_EH_FRAME);
+        if let Some(hdr) = hdr {
+            let bases = BaseAddresses::default().set_eh_frame_hdr(hdr.p_vaddr);
+            let parsed = EhFrameHdr::new(self.bytes_at(hdr.p_vaddr)?, LittleEndian)
+                .parse(&bases, 8)
+                .ok()?;
+            let eh_frame_addr = match parsed.eh_frame_ptr() {
+                Pointer::Direct(addr) => addr,
+                Pointer::Indirect(_) => return None,
+            };
+            let eh_frame = EhFrame::new(self.bytes_at(eh_frame_addr)?, LittleEndian);
+            let bases = bases.set_eh_frame(eh_frame_addr);
+
+            if let Some(table) = parsed.table() {
+                let fde = table
+                    .fde_for_address(&eh_frame, &bases, pc, EhFrame::cie_from_offset)
+                    .ok()?;
+                return Some((eh_frame, bases, fde));
+            }
+        }
+
+        let shdr = self.elf.section_by_name(".eh_frame")?;
+        let eh_frame = EhFrame::new(self.elf.section_data(shdr), LittleEndian);
+        let bases = BaseAddresses::default().set_eh_frame(shdr.sh_addr);
+        let fde = eh_frame
+            .fde_for_address(&bases, pc, EhFrame::cie_from_offset)
+            .ok()?;
+        Some((eh_frame, bases, fde))
+    }
+}
+
+/// Unwinds the threads of a core file, using the `.eh_frame` CFI of the
+/// mapped executable and libraries, and falling back on the frame pointer
+/// chain where there is none.
+pub struct Unwinder<'a> {
+    core: &'a Elf,
+    machine: u16,
+    modules: Vec<Module>,
+}
+
+// DWARF register numbers of the registers in NT_PRSTATUS, see the x86-64
+// and AArch64 psABIs.
+fn dwarf_number(machine: u16, name: &str) -> Option<u16> {
+    const X86_64: [&str; 17] = [
+        "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15", "rip",
+    ];
+
+    match machine {
+        elf::EM_X86_64 => X86_64.iter().position(|&n| n == name).map(|i| i as u16),
+        elf::EM_AARCH64 => match name {
+            "sp" => Some(31),
+            "pc" => Some(32),
+            _ => name.strip_prefix('x').and_then(|n| n.parse().ok()),
+        },
+        _ => None,
+    }
+}
+
+// The DWARF numbers of the stack pointer, frame pointer and return address.
+fn special_registers(machine: u16) -> (u16, u16, u16) {
+    match machine {
+        elf::EM_AARCH64 => (31, 29, 30),
+        _ => (7, 6, 16),
+    }
+}
+
+impl<'a> Unwinder<'a> {
+    /// Load the modules of `core` from the mapped files, see
+    /// `Elf::load_mappings` for where they are looked for.
+    pub fn new(core: &'a Elf) -> io::Result<Unwinder<'a>> {
+        let machine = core.machine();
+        if coredump::register_names(machine).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "unwinding is not supported for {}",
+                    elf::machine_name(machine)
+                ),
+            ));
+        }
+
+        let mut modules: Vec<Module> = Vec::new();
+        for mapping in core.mappings() {
+            if let Some(module) = modules.iter_mut().find(|m| m.path == mapping.path) {
+                module.start = module.start.min(mapping.start);
+                module.end = module.end.max(mapping.end);
+                continue;
+            }
+
+            // The mapping of the file start tells the load bias.
+            let data = match (mapping.offset, mapping.data()) {
+                (0, Some(data)) => data,
+                _ => continue,
+            };
+            let elf = match Elf::from_bytes(data.to_vec(), Options::default()) {
+                Ok(elf) => elf,
+                Err(_) => continue,
+            };
+            let first = elf
+                .program_headers()
+                .iter()
+                .find(|phdr| phdr.p_type == elf::PT_LOAD && phdr.p_offset == 0);
+            let bias = match first {
+                Some(phdr) => mapping.start.wrapping_sub(phdr.p_vaddr & !0xfff),
+                None => continue,
+            };
+
+            modules.push(Module {
+                path: mapping.path.clone(),
+                start: mapping.start,
+                end: mapping.end,
+                bias,
+                elf,
+            });
+        }
+
+        Ok(Unwinder {
+            core,
+            machine,
+            modules,
+        })
+    }
+
+    fn module_at(&self, pc: u64) -> Option<&Module> {
+        self.modules.iter().find(|m| m.start <= pc && pc < m.end)
+    }
+
+    fn read_u64(&self, addr: u64) -> Option<u64> {
+        let bytes = self.core.read_vaddr(addr, 8).ok()?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    pub fn backtrace(&self, thread: &Thread) -> Vec<Frame> {
+        let mut regs = [None; 33];
+        for &(name, value) in thread.registers.iter() {
+            if let Some(n) = dwarf_number(self.machine, name) {
+                regs[n as usize] = Some(value);
+            }
+        }
+
+        let (sp, _, ra) = special_registers(self.machine);
+        let mut pc = match thread.pc() {
+            Some(pc) => pc,
+            None => return Vec::new(),
+        };
+        let mut frames = Vec::new();
+
+        while pc != 0 && frames.len() < MAX_FRAMES {
+            // Return addresses point after the call, which may already be
+            // the next function.
+            let lookup = if frames.is_empty() { pc } else { pc - 1 };
+            frames.push(self.symbolize(pc, lookup));
+
+            let old_sp = regs[sp as usize];
+            let next = self
+                .step_cfi(lookup, &regs)
+                .or_else(|| self.step_frame_pointer(&regs));
+
+            regs = match next {
+                Some(next) => next,
+                None => break,
+            };
+            // The stack grows down, a caller's frame is above its callee's.
+            if regs[sp as usize] <= old_sp {
+                break;
+            }
+            pc = regs[ra as usize].unwrap_or(0);
+        }
+
+        frames
+    }
+
+    // Compute the caller's registers from the CFI covering `pc`.
+    fn step_cfi(&self, pc: u64, regs: &[Option<u64>; 33]) -> Option<[Option<u64>; 33]> {
+        let module = self.module_at(pc)?;
+        let rel_pc = pc.wrapping_sub(module.bias);
+
+        let (eh_frame, bases, fde) = module.find_fde(rel_pc)?;
+        let mut ctx = UnwindContext::new();
+        let row = fde
+            .unwind_info_for_address(&eh_frame, &bases, &mut ctx, rel_pc)
+            .ok()?;
+
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                regs.get(register.0 as usize).copied().flatten()? as i64 + offset
+            }
+            _ => return None,
+        } as u64;
+
+        let (sp, _, _) = special_registers(self.machine);
+        let ra = fde.cie().return_address_register().0;
+        let mut next = *regs;
+        for (n, value) in next.iter_mut().enumerate() {
+            let rule = row.register(Register(n as u16));
+            *value = match rule {
+                RegisterRule::Undefined if n == ra as usize => None,
+                // Callee-saved registers without a rule keep their values,
+                // caller-saved ones are lost; both read as their old value.
+                RegisterRule::Undefined | RegisterRule::SameValue => regs[n],
+                RegisterRule::Offset(offset) => self.read_u64(cfa.wrapping_add(offset as u64)),
+                RegisterRule::ValOffset(offset) => Some(cfa.wrapping_add(offset as u64)),
+                RegisterRule::Register(other) => regs.get(other.0 as usize).copied().flatten(),
+                RegisterRule::Constant(value) => Some(value),
+                _ => None,
+            };
+        }
+
+        // The CFA is by definition the caller's stack pointer.
+        next[sp as usize] = Some(cfa);
+        next[ra as usize]?;
+
+        Some(next)
+    }
+
+    // Follow the saved frame pointer and return address of a conventional
+    // frame record.
+    fn step_frame_pointer(&self, regs: &[Option<u64>; 33]) -> Option<[Option<u64>; 33]> {
+        let (sp, fp, ra) = special_registers(self.machine);
+        let frame = regs[fp as usize]?;
+
+        let mut next = *regs;
+        next[fp as usize] = Some(self.read_u64(frame)?);
+        next[ra as usize] = Some(self.read_u64(frame + 8)?);
+        next[sp as usize] = Some(frame + 16);
+
+        Some(next)
+    }
+
+    // Resolve the frame at `pc` by the symbol covering `lookup`, which is
+    // within the call instruction for the callers.
+    fn symbolize(&self, pc: u64, lookup: u64) -> Frame {
+        let module = match self.module_at(lookup) {
+            Some(module) => module,
+            None => {
+                return Frame {
+                    pc,
+                    module: None,
+                    symbol: None,
+                }
+            }
+        };
+
+        let rel_pc = pc.wrapping_sub(module.bias);
+        Frame {
+            pc,
+            module: Some(module.path.clone()),
+            symbol: module
+                .elf
+                .lookup_symbol(lookup.wrapping_sub(module.bias))
+                .map(|sym| (sym.name.clone(), rel_pc - sym.value())),
+        }
+    }
+
+    /// Print the backtrace of every thread, the crashed one first, with
+    /// the symbol names demangled if `demangle` is set.
+    pub fn to_str(&self, core: &Core, demangle: bool, buf: &mut dyn Write) -> io::Result<()> {
+        let crashed = core.crashed_thread().map(|thread| thread.pid);
+        let mut threads: Vec<(usize, &Thread)> = core.threads.iter().enumerate().collect();
+        threads.sort_by_key(|(_, thread)| Some(thread.pid) != crashed);
+
+        for (i, thread) in threads {
+            buf.write_fmt(format_args!("\nThread {} (LWP {})", i + 1, thread.pid))?;
+            if Some(thread.pid) == crashed {
+                buf.write_fmt(format_args!(
+                    " crashed with signal {} ({})",
+                    thread.signal,
+                    coredump::signal_name(thread.signal)
+                ))?;
+            }
+            buf.write_fmt(format_args!(":\n"))?;
+
+            for (n, frame) in self.backtrace(thread).iter().enumerate() {
+                buf.write_fmt(format_args!("  #{:<3}{:#018x} in ", n, frame.pc))?;
+                let symbol = frame.symbol.as_ref().map(|(name, offset)| match demangle {
+                    true => (crate::demangle::demangle(name), *offset),
+                    false => (name.clone(), *offset),
+                });
+                match symbol {
+                    Some((name, 0)) => buf.write_fmt(format_args!("{}", name))?,
+                    Some((name, offset)) => {
+                        buf.write_fmt(format_args!("{}+{:#x}", name, offset))?
+                    }
+                    None => buf.write_fmt(format_args!("??"))?,
+                }
+                if let Some(module) = frame.module.as_ref() {
+                    buf.write_fmt(format_args!(" ({})", module))?;
+                }
+                buf.write_fmt(format_args!("\n"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::elf::{self, Elf};
+
+/// Run `work` over `items` on `jobs` worker threads and pass the results to
+/// `emit` in the order of `items`. A result is emitted as soon as every
+/// result before it is available, so output streams while workers go on.
+pub fn run_ordered<T, R, W, E>(items: &[T], jobs: usize, work: W, mut emit: E)
+where
+    T: Sync,
+    R: Send,
+    W: Fn(&T) -> R + Sync,
+    E: FnMut(R),
+{
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _i in 0..jobs.max(1) {
+            let tx = tx.clone();
+            let next = &next;
+            let work = &work;
+
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= items.len() || tx.send((i, work(&items[i]))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        let mut pending = BTreeMap::new();
+        let mut expected = 0;
+
+        for (i, result) in rx {
+            pending.insert(i, result);
+
+            while let Some(result) = pending.remove(&expected) {
+                emit(result);
+                expected += 1;
+            }
+        }
+    });
+}
+
+/// The per-object facts aggregated by `Stats`.
+pub struct Summary {
+    pub machine: u16,
+    pub e_type: u16,
+    pub pie: bool,
+    pub stripped: bool,
+    pub interpreter: Option<String>,
+}
+
+impl Summary {
+    pub fn new(elf: &Elf) -> Summary {
+        Summary {
+            machine: elf.machine(),
+            e_type: elf.e_type(),
+            pie: elf.is_pie(),
+            stripped: elf.is_stripped(),
+            interpreter: elf.interpreter(),
+        }
+    }
+
+    fn is_executable(&self) -> bool {
+        self.e_type == elf::ET_EXEC || self.pie
+    }
+}
+
+#[derive(Default)]
+pub struct Stats {
+    objects: usize,
+    errors: usize,
+    executables: usize,
+    pie: usize,
+    stripped: usize,
+    machines: BTreeMap<u16, usize>,
+    interpreters: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    pub fn add(&mut self, summary: &Summary) {
+        self.objects += 1;
+        *self.machines.entry(summary.machine).or_insert(0) += 1;
+
+        if summary.is_executable() {
+            self.executables += 1;
+        }
+        if summary.pie {
+            self.pie += 1;
+        }
+        if summary.stripped {
+            self.stripped += 1;
+        }
+        if let Some(interp) = summary.interpreter.as_ref() {
+            *self.interpreters.entry(interp.clone()).or_insert(0) += 1;
+        }
+    }
+
+    pub fn add_error(&mut self) {
+        self.errors += 1;
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors > 0
+    }
+
+    pub fn to_str(&self, buf: &mut dyn Write) -> io::Result<()> {
+        buf.write_fmt(format_args!("Summary:\n"))?;
+        buf.write_fmt(format_args!("  {:<34} {}\n", "Objects:", self.objects))?;
+        buf.write_fmt(format_args!("  {:<34} {}\n", "Errors:", self.errors))?;
+        buf.write_fmt(format_args!(
+            "  {:<34} {}\n",
+            "PIE executables:",
+            ratio(self.pie, self.executables)
+        ))?;
+        buf.write_fmt(format_args!(
+            "  {:<34} {}\n",
+            "Stripped:",
+            ratio(self.stripped, self.objects)
+        ))?;
+
+        buf.write_fmt(format_args!("  Machines:\n"))?;
+        for (machine, count) in self.machines.iter() {
+            buf.write_fmt(format_args!(
+                "    {:<32} {}\n",
+                elf::machine_name(*machine),
+                count
+            ))?;
+        }
+
+        buf.write_fmt(format_args!("  Interpreters:\n"))?;
+        for (interp, count) in self.interpreters.iter() {
+            buf.write_fmt(format_args!("    {:<32} {}\n", interp, count))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn ratio(n: usize, total: usize) -> String {
+    if total == 0 {
+        return format!("{}/{}", n, total);
+    }
+
+    format!("{}/{} ({:.1}%)", n, total, n as f64 * 100.0 / total as f64)
+}
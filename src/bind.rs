@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+
+use crate::deps::Dependencies;
+use crate::elf;
+use crate::symver::{DynamicSymbol, SymbolVersion};
+
+/// An undefined dynamic symbol of an object and where it binds.
+pub struct Binding {
+    /// The referencing object, an index into `Dependencies::objects`.
+    pub object: usize,
+    pub name: String,
+    pub version: Option<SymbolVersion>,
+    pub weak: bool,
+    /// The library the version is needed from defines the symbol without
+    /// versions, which makes the dynamic loader abort.
+    pub unversioned: bool,
+    /// The object providing the definition the reference binds to.
+    pub provider: Option<usize>,
+    /// The objects after the provider in lookup order that define the
+    /// symbol too, and are interposed by it.
+    pub interposed: Vec<usize>,
+}
+
+/// How much an object uses one of its DT_NEEDED libraries.
+pub struct NeededUsage {
+    pub name: String,
+    pub object: Option<usize>,
+    /// The references of the object bound to the library or to what it
+    /// brings in.
+    pub symbols: usize,
+}
+
+/// The bindings of the undefined dynamic symbols of a dependency closure,
+/// looked up the way the dynamic loader does: through the global scope in
+/// load order, taking the first definition of a matching version.
+pub struct Bindings {
+    pub bindings: Vec<Binding>,
+}
+
+/// Whether `sym` is a definition other objects can bind to.
+pub(crate) fn is_export(sym: &DynamicSymbol) -> bool {
+    let symbol = sym.symbol;
+    let vis = symbol.sym.st_other & 0x3;
+
+    symbol.is_defined()
+        && !symbol.name.is_empty()
+        && matches!(
+            symbol.bind(),
+            elf::STB_GLOBAL | elf::STB_WEAK | elf::STB_GNU_UNIQUE
+        )
+        && (vis == elf::STV_DEFAULT || vis == elf::STV_PROTECTED)
+}
+
+// Whether a reference asking for `wanted` binds to a definition of
+// `def`, in an object that versions its symbols or not.
+fn version_matches(
+    wanted: Option<&SymbolVersion>,
+    def: Option<&SymbolVersion>,
+    versioned: bool,
+) -> bool {
+    match (wanted, def) {
+        // Unversioned objects satisfy any version.
+        (Some(_), _) if !versioned => true,
+        (Some(wanted), Some(def)) => wanted.name == def.name,
+        (Some(_), None) => false,
+        // Unversioned references get the default version.
+        (None, Some(def)) => !def.hidden,
+        (None, None) => true,
+    }
+}
+
+impl Bindings {
+    pub fn new(deps: &Dependencies) -> Bindings {
+        let symbols: Vec<Vec<DynamicSymbol>> = deps
+            .objects
+            .iter()
+            .map(|object| object.elf.dynamic_symbols())
+            .collect();
+        let versioned: Vec<bool> = deps
+            .objects
+            .iter()
+            .map(|object| object.elf.has_symbol_versions())
+            .collect();
+
+        // The names version needs may give each object by: its soname and
+        // the DT_NEEDED entries it was loaded for.
+        let mut names: Vec<HashSet<String>> = deps
+            .objects
+            .iter()
+            .map(|object| object.elf.soname().into_iter().collect())
+            .collect();
+        for object in deps.objects.iter() {
+            for needed in object.needed.iter() {
+                if let Some(j) = needed.object {
+                    names[j].insert(needed.name.clone());
+                }
+            }
+        }
+
+        // The definitions of each name, in lookup order.
+        let mut defs: HashMap<&str, Vec<(usize, &DynamicSymbol)>> = HashMap::new();
+        for (i, syms) in symbols.iter().enumerate() {
+            for sym in syms.iter().filter(|sym| is_export(sym)) {
+                defs.entry(sym.symbol.name.as_str())
+                    .or_default()
+                    .push((i, sym));
+            }
+        }
+
+        let mut bindings = Vec::new();
+        for (i, syms) in symbols.iter().enumerate() {
+            for sym in syms.iter() {
+                let symbol = sym.symbol;
+                if symbol.is_defined() || symbol.name.is_empty() || symbol.bind() == elf::STB_LOCAL
+                {
+                    continue;
+                }
+
+                let mut providers = defs
+                    .get(symbol.name.as_str())
+                    .into_iter()
+                    .flatten()
+                    .filter(|(j, def)| {
+                        version_matches(sym.version.as_ref(), def.version.as_ref(), versioned[*j])
+                    })
+                    .map(|&(j, def)| (j, def.symbol));
+                let mut first = providers.next();
+
+                // Objects without versions satisfy versioned references,
+                // except for the one the version is needed from: the
+                // dynamic loader takes that for a symbol that disappeared.
+                let unversioned = match (sym.version.as_ref(), first) {
+                    (
+                        Some(SymbolVersion {
+                            file: Some(file), ..
+                        }),
+                        Some((j, _)),
+                    ) => !versioned[j] && names[j].contains(file),
+                    _ => false,
+                };
+                if unversioned {
+                    first = None;
+                }
+                let provider = first.map(|(j, _)| j);
+
+                // Weak definitions give way to others without a conflict,
+                // and data the program copies from a library with a copy
+                // relocation is meant to be defined twice.
+                let copied = matches!(first, Some((0, def)) if def.kind() == elf::STT_OBJECT);
+                let interposed = providers
+                    .filter(|&(_, def)| !copied && def.bind() != elf::STB_WEAK)
+                    .map(|(j, _)| j)
+                    .collect();
+
+                bindings.push(Binding {
+                    object: i,
+                    name: symbol.name.clone(),
+                    version: sym.version.clone(),
+                    weak: symbol.bind() == elf::STB_WEAK,
+                    unversioned,
+                    provider,
+                    interposed,
+                });
+            }
+        }
+
+        Bindings { bindings }
+    }
+
+    /// The references nothing defines that are not weak, and those bound
+    /// to a library missing its versions, which make the program fail to
+    /// load or to run.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Binding> {
+        self.bindings
+            .iter()
+            .filter(|binding| binding.provider.is_none() && (!binding.weak || binding.unversioned))
+    }
+
+    /// The use `object` makes of each of its DT_NEEDED libraries. A
+    /// reference counts for the library defining it when that is a direct
+    /// dependency, and otherwise for each direct dependency that brings
+    /// the defining library in.
+    pub fn needed_usage(&self, deps: &Dependencies, object: usize) -> Vec<NeededUsage> {
+        let direct: HashSet<usize> = deps.objects[object]
+            .needed
+            .iter()
+            .filter_map(|needed| needed.object)
+            .collect();
+
+        deps.objects[object]
+            .needed
+            .iter()
+            .map(|needed| {
+                let reach = needed.object.map_or_else(HashSet::new, |i| deps.closure(i));
+                let symbols = self
+                    .bindings
+                    .iter()
+                    .filter(|binding| binding.object == object)
+                    .filter_map(|binding| binding.provider)
+                    .filter(|p| match direct.contains(p) {
+                        true => needed.object == Some(*p),
+                        false => reach.contains(p),
+                    })
+                    .count();
+
+                NeededUsage {
+                    name: needed.name.clone(),
+                    object: needed.object,
+                    symbols,
+                }
+            })
+            .collect()
+    }
+
+    /// Print a summary and the problem symbols, demangled if `demangle` is
+    /// set.
+    pub fn to_str(
+        &self,
+        deps: &Dependencies,
+        demangle: bool,
+        buf: &mut dyn Write,
+    ) -> io::Result<()> {
+        let bound = self
+            .bindings
+            .iter()
+            .filter(|binding| binding.provider.is_some())
+            .count();
+        let weak = self
+            .bindings
+            .iter()
+            .filter(|binding| binding.provider.is_none() && binding.weak && !binding.unversioned)
+            .count();
+        let unresolved: Vec<&Binding> = self.unresolved().collect();
+
+        // Several objects binding the same interposed symbol are reported
+        // once.
+        let mut interposed: BTreeMap<(String, usize), &Binding> = BTreeMap::new();
+        for binding in self.bindings.iter() {
+            if let (Some(provider), false) = (binding.provider, binding.interposed.is_empty()) {
+                let name = symbol_name(binding, demangle);
+                interposed.entry((name, provider)).or_insert(binding);
+            }
+        }
+
+        buf.write_fmt(format_args!(
+            "\nUndefined symbols: {} bound, {} weak unresolved, {} unresolved, {} interposed\n",
+            bound,
+            weak,
+            unresolved.len(),
+            interposed.len()
+        ))?;
+
+        if !unresolved.is_empty() {
+            buf.write_fmt(format_args!("\nUnresolved symbols:\n"))?;
+            for binding in unresolved {
+                buf.write_fmt(format_args!(
+                    "  {} (in {})",
+                    symbol_name(binding, demangle),
+                    deps.objects[binding.object].path
+                ))?;
+                match binding.unversioned {
+                    true => {
+                        buf.write_fmt(format_args!(": the library has no symbol versions\n"))?
+                    }
+                    false => buf.write_fmt(format_args!("\n"))?,
+                }
+            }
+        }
+
+        if !interposed.is_empty() {
+            buf.write_fmt(format_args!("\nInterposed symbols:\n"))?;
+            for ((name, provider), binding) in interposed {
+                let others: Vec<&str> = binding
+                    .interposed
+                    .iter()
+                    .map(|&i| deps.objects[i].path.as_str())
+                    .collect();
+                buf.write_fmt(format_args!(
+                    "  {} => {}, also in {}\n",
+                    name,
+                    deps.objects[provider].path,
+                    others.join(", ")
+                ))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The symbol as `name@version (file)`.
+fn symbol_name(binding: &Binding, demangle: bool) -> String {
+    let name = match demangle {
+        true => crate::demangle::demangle(&binding.name),
+        false => binding.name.clone(),
+    };
+
+    match binding.version.as_ref() {
+        Some(SymbolVersion {
+            name: version,
+            file: Some(file),
+            ..
+        }) => format!("{}@{} ({})", name, version, file),
+        Some(version) => format!("{}@{}", name, version.name),
+        None => name,
+    }
+}
+
+/// Print the use `object` makes of its DT_NEEDED libraries, like ldd -u.
+pub fn write_usage(
+    usage: &[NeededUsage],
+    deps: &Dependencies,
+    object: usize,
+    buf: &mut dyn Write,
+) -> io::Result<()> {
+    buf.write_fmt(format_args!(
+        "\nDirect dependencies of {}:\n",
+        deps.objects[object].path
+    ))?;
+
+    for needed in usage.iter() {
+        buf.write_fmt(format_args!("  {} => ", needed.name))?;
+        match (needed.object, needed.symbols) {
+            (None, _) => buf.write_fmt(format_args!("not found\n"))?,
+            (Some(i), 0) => buf.write_fmt(format_args!("{} (unused)\n", deps.objects[i].path))?,
+            (Some(i), n) => {
+                buf.write_fmt(format_args!("{} ({} symbol(s))\n", deps.objects[i].path, n))?
+            }
+        }
+    }
+
+    Ok(())
+}
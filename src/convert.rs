@@ -0,0 +1,369 @@
+use std::io::{self, Write};
+
+use crate::elf::{self, Elf};
+
+/// Output formats of `Image`, as named by `objcopy -O`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Binary,
+    Ihex,
+    Srec,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "binary" => Some(Format::Binary),
+            "ihex" => Some(Format::Ihex),
+            "srec" => Some(Format::Srec),
+            _ => None,
+        }
+    }
+}
+
+/// A run of bytes to be loaded at `addr`.
+pub struct Chunk {
+    pub addr: u64,
+    pub data: Vec<u8>,
+}
+
+/// The memory image the PT_LOAD segments of a file describe, at their
+/// physical (load) addresses. Only the `p_filesz` part of a segment has
+/// contents; the rest up to `p_memsz` is zeroed at run time and left out.
+pub struct Image {
+    pub entry: u64,
+    pub chunks: Vec<Chunk>,
+}
+
+// Data bytes per Intel HEX and S-record line, as objcopy writes them.
+const RECORD_SIZE: usize = 16;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Image {
+    pub fn new(elf: &Elf) -> io::Result<Image> {
+        let mut chunks: Vec<Chunk> = elf
+            .program_headers()
+            .iter()
+            .filter(|phdr| phdr.p_type == elf::PT_LOAD && phdr.p_filesz != 0)
+            .map(|phdr| {
+                let start = phdr.p_offset as usize;
+                let data = start
+                    .checked_add(phdr.p_filesz as usize)
+                    .and_then(|end| elf.data().get(start..end))
+                    .ok_or_else(|| invalid("segment extends past end of file".to_string()))?;
+                // Chunk ends are computed freely from here on.
+                if phdr.p_paddr.checked_add(phdr.p_filesz).is_none() {
+                    return Err(invalid(format!(
+                        "segment at {:#x} extends past end of address space",
+                        phdr.p_paddr
+                    )));
+                }
+
+                Ok(Chunk {
+                    addr: phdr.p_paddr,
+                    data: data.to_vec(),
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        if chunks.is_empty() {
+            return Err(invalid("no loadable segments".to_string()));
+        }
+        chunks.sort_by_key(|chunk| chunk.addr);
+
+        for pair in chunks.windows(2) {
+            if pair[0].addr + pair[0].data.len() as u64 > pair[1].addr {
+                return Err(invalid(format!(
+                    "segments overlap at load address {:#x}",
+                    pair[1].addr
+                )));
+            }
+        }
+
+        Ok(Image {
+            entry: elf.header().e_entry,
+            chunks,
+        })
+    }
+
+    /// Keep only the bytes in `start..stop`.
+    pub fn restrict(&mut self, start: Option<u64>, stop: Option<u64>) -> io::Result<()> {
+        let start = start.unwrap_or(0);
+        let stop = stop.unwrap_or(u64::MAX);
+        if start > stop {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "start address {:#x} is past stop address {:#x}",
+                    start, stop
+                ),
+            ));
+        }
+
+        for chunk in self.chunks.iter_mut() {
+            let end = chunk.addr + chunk.data.len() as u64;
+            let lo = chunk.addr.clamp(start, stop);
+            let hi = end.clamp(start, stop);
+
+            chunk.data = match lo < hi {
+                true => chunk.data[(lo - chunk.addr) as usize..(hi - chunk.addr) as usize].to_vec(),
+                false => Vec::new(),
+            };
+            chunk.addr = lo;
+        }
+
+        self.chunks.retain(|chunk| !chunk.data.is_empty());
+        Ok(())
+    }
+
+    /// Join the chunks into one, filling the gaps between them with `fill`.
+    pub fn fill_gaps(&mut self, fill: u8) {
+        let mut chunks = self.chunks.drain(..);
+        let mut joined = match chunks.next() {
+            Some(chunk) => chunk,
+            None => return,
+        };
+
+        for chunk in chunks {
+            let gap = chunk.addr - (joined.addr + joined.data.len() as u64);
+            joined.data.resize(joined.data.len() + gap as usize, fill);
+            joined.data.extend_from_slice(&chunk.data);
+        }
+
+        self.chunks = vec![joined];
+    }
+
+    /// The raw bytes from the lowest to the highest address, gaps filled
+    /// with `fill`.
+    pub fn write_binary(&self, fill: u8, buf: &mut dyn Write) -> io::Result<()> {
+        let mut next = match self.chunks.first() {
+            Some(chunk) => chunk.addr,
+            None => return Ok(()),
+        };
+
+        for chunk in self.chunks.iter() {
+            buf.write_all(&vec![fill; (chunk.addr - next) as usize])?;
+            buf.write_all(&chunk.data)?;
+            next = chunk.addr + chunk.data.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_ihex(&self, buf: &mut dyn Write) -> io::Result<()> {
+        let mut upper = 0;
+
+        for chunk in self.chunks.iter() {
+            if chunk.addr + chunk.data.len() as u64 > 1 << 32 {
+                return Err(invalid(format!(
+                    "address {:#x} does not fit in Intel HEX",
+                    chunk.addr
+                )));
+            }
+
+            let mut addr = chunk.addr;
+            let mut data = chunk.data.as_slice();
+            while !data.is_empty() {
+                // Records must not cross a 64KiB boundary, the upper half of
+                // the address comes from an extended linear address record.
+                if addr >> 16 != upper {
+                    upper = addr >> 16;
+                    ihex_record(buf, 0, 4, &(upper as u16).to_be_bytes())?;
+                }
+
+                let room = 0x10000 - (addr & 0xffff) as usize;
+                let n = data.len().min(RECORD_SIZE).min(room);
+                ihex_record(buf, addr as u16, 0, &data[..n])?;
+
+                addr += n as u64;
+                data = &data[n..];
+            }
+        }
+
+        // Like objcopy, entry points below 1MiB are given as CS:IP.
+        if self.entry <= 0xfffff {
+            let cs = ((self.entry & 0xf0000) >> 4) as u16;
+            let ip = (self.entry & 0xffff) as u16;
+            let mut start = cs.to_be_bytes().to_vec();
+            start.extend_from_slice(&ip.to_be_bytes());
+            ihex_record(buf, 0, 3, &start)?;
+        } else if self.entry <= u32::MAX as u64 {
+            ihex_record(buf, 0, 5, &(self.entry as u32).to_be_bytes())?;
+        }
+
+        ihex_record(buf, 0, 1, &[])
+    }
+
+    /// Motorola S-records, with the smallest address width that fits and
+    /// `header` in the S0 record.
+    pub fn write_srec(&self, header: &str, buf: &mut dyn Write) -> io::Result<()> {
+        let end = self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.addr + chunk.data.len() as u64 - 1)
+            .chain([self.entry])
+            .max()
+            .unwrap_or(0);
+        let width = match end {
+            0..=0xffff => 2,
+            0x10000..=0xffffff => 3,
+            0x1000000..=0xffffffff => 4,
+            _ => {
+                return Err(invalid(format!(
+                    "address {:#x} does not fit in S-records",
+                    end
+                )))
+            }
+        };
+
+        srec_record(buf, 0, 0, 2, header.as_bytes())?;
+
+        for chunk in self.chunks.iter() {
+            for (i, data) in chunk.data.chunks(RECORD_SIZE).enumerate() {
+                let addr = chunk.addr + (i * RECORD_SIZE) as u64;
+                srec_record(buf, width - 1, addr, width, data)?;
+            }
+        }
+
+        // S7, S8 and S9 terminate S3, S2 and S1 records respectively.
+        srec_record(buf, 11 - width, self.entry, width, &[])
+    }
+}
+
+fn ihex_record(buf: &mut dyn Write, addr: u16, kind: u8, data: &[u8]) -> io::Result<()> {
+    let mut record = vec![data.len() as u8];
+    record.extend_from_slice(&addr.to_be_bytes());
+    record.push(kind);
+    record.extend_from_slice(data);
+
+    let sum = record.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+    record.push(sum.wrapping_neg());
+
+    buf.write_fmt(format_args!(":{}\r\n", hex(&record)))
+}
+
+fn srec_record(
+    buf: &mut dyn Write,
+    kind: usize,
+    addr: u64,
+    width: usize,
+    data: &[u8],
+) -> io::Result<()> {
+    // The count byte covers the address, data and checksum; like objcopy,
+    // a longer S0 header is truncated to fit.
+    let data = &data[..data.len().min(u8::MAX as usize - width - 1)];
+    let mut record = vec![(width + data.len() + 1) as u8];
+    record.extend_from_slice(&addr.to_be_bytes()[8 - width..]);
+    record.extend_from_slice(data);
+
+    let sum = record.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+    record.push(!sum);
+
+    buf.write_fmt(format_args!("S{}{}\r\n", kind, hex(&record)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use super::*;
+    use crate::elf::{Elf64Phdr, Options};
+    use crate::testutil::Scratch;
+
+    fn image(entry: u64, addr: u64, data: &[u8]) -> Image {
+        Image {
+            entry,
+            chunks: vec![Chunk {
+                addr,
+                data: data.to_vec(),
+            }],
+        }
+    }
+
+    // The bytes of each record of `text`, after its start code.
+    fn records(text: &str, start: char) -> Vec<Vec<u8>> {
+        text.lines()
+            .map(|line| {
+                let digits = &line.trim_start_matches(start)[usize::from(start == 'S')..];
+                (0..digits.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rejects_segment_past_address_space() {
+        let scratch = Scratch::new("convert-overflow");
+        scratch.write("t.c", "int main(void) { return 0; }\n");
+        let mut data = scratch.cc("t", &["-no-pie", "t.c"]);
+
+        let elf = Elf::from_bytes(data.clone(), Options::default()).unwrap();
+        let i = elf
+            .program_headers()
+            .iter()
+            .position(|phdr| phdr.p_type == elf::PT_LOAD && phdr.p_filesz != 0)
+            .unwrap();
+        let off = elf.header().e_phoff as usize
+            + i * mem::size_of::<Elf64Phdr>()
+            + mem::offset_of!(Elf64Phdr, p_paddr);
+        data[off..off + 8].copy_from_slice(&0xffff_ffff_ffff_ff00u64.to_le_bytes());
+
+        let elf = Elf::from_bytes(data, Options::default()).unwrap();
+        let err = Image::new(&elf).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn ihex_checksums() {
+        let mut out = Vec::new();
+        image(0x12340, 0x12340, &[1, 2, 3, 4, 5])
+            .write_ihex(&mut out)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert!(text.contains(":05234000010203040589\r\n"), "{}", text);
+        assert!(text.ends_with(":00000001FF\r\n"));
+        for record in records(&text, ':') {
+            let sum = record.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+            assert_eq!(sum, 0, "{:02x?}", record);
+        }
+    }
+
+    #[test]
+    fn srec_matches_objcopy() {
+        let mut out = Vec::new();
+        image(0x12340, 0x12340, &[1, 2, 3, 4, 5])
+            .write_srec("d.srec", &mut out)
+            .unwrap();
+
+        // objcopy -I binary -O srec --change-addresses 0x12340
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "S0090000642E73726563B7\r\nS209012340010203040583\r\nS80401234097\r\n"
+        );
+    }
+
+    #[test]
+    fn srec_truncates_long_header() {
+        let mut out = Vec::new();
+        image(0, 0, &[0])
+            .write_srec(&"h".repeat(300), &mut out)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        for record in records(&text, 'S') {
+            assert_eq!(record[0] as usize, record.len() - 1, "{:02x?}", record);
+            let sum = record.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+            assert_eq!(sum, 0xff, "{:02x?}", record);
+        }
+    }
+}
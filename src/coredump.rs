@@ -0,0 +1,426 @@
+use std::io::{self, Write};
+
+use crate::elf::{self, Elf, Note};
+
+/// A thread of the dumped process, from its NT_PRSTATUS note.
+pub struct Thread {
+    pub pid: i32,
+    /// The signal the thread was stopped by, 0 for bystanders.
+    pub signal: i32,
+    pub registers: Vec<(&'static str, u64)>,
+}
+
+/// The NT_PRPSINFO note.
+pub struct ProcessInfo {
+    pub pid: i32,
+    pub ppid: i32,
+    pub uid: u32,
+    pub gid: u32,
+    pub state: char,
+    pub fname: String,
+    pub psargs: String,
+}
+
+/// The NT_SIGINFO note of the signal that caused the dump.
+pub struct SigInfo {
+    pub signo: i32,
+    pub errno: i32,
+    pub code: i32,
+    /// The faulting address, for signals raised by a memory access.
+    pub addr: Option<u64>,
+}
+
+/// An entry of the NT_FILE note.
+pub struct MappedFile {
+    pub start: u64,
+    pub end: u64,
+    /// Offset in the file, in bytes.
+    pub offset: u64,
+    pub path: String,
+}
+
+/// What the notes of an ET_CORE file tell about the dumped process.
+pub struct Core {
+    pub machine: u16,
+    pub threads: Vec<Thread>,
+    pub process: Option<ProcessInfo>,
+    pub siginfo: Option<SigInfo>,
+    pub auxv: Vec<(u64, u64)>,
+    pub page_size: u64,
+    pub files: Vec<MappedFile>,
+}
+
+// user_regs_struct of x86_64, in order.
+const X86_64_REGS: [&str; 27] = [
+    "r15", "r14", "r13", "r12", "rbp", "rbx", "r11", "r10", "r9", "r8", "rax", "rcx", "rdx", "rsi",
+    "rdi", "orig_rax", "rip", "cs", "eflags", "rsp", "ss", "fs_base", "gs_base", "ds", "es", "fs",
+    "gs",
+];
+
+// user_pt_regs of AArch64, in order.
+const AARCH64_REGS: [&str; 34] = [
+    "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13", "x14",
+    "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26", "x27",
+    "x28", "x29", "x30", "sp", "pc", "pstate",
+];
+
+// Offset of pr_reg in struct elf_prstatus.
+const PR_REG: usize = 112;
+
+pub const AT_NULL: u64 = 0;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_ENTRY: u64 = 9;
+pub const AT_SYSINFO_EHDR: u64 = 33;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u32(bytes: &[u8], off: usize) -> u32 {
+    bytes
+        .get(off..off + 4)
+        .map_or(0, |b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+fn read_u64(bytes: &[u8], off: usize) -> u64 {
+    bytes
+        .get(off..off + 8)
+        .map_or(0, |b| u64::from_le_bytes(b.try_into().unwrap()))
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&c| c == b'\0')
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// The register names of pr_reg for `machine`, if it is supported.
+pub fn register_names(machine: u16) -> Option<&'static [&'static str]> {
+    match machine {
+        elf::EM_X86_64 => Some(&X86_64_REGS),
+        elf::EM_AARCH64 => Some(&AARCH64_REGS),
+        _ => None,
+    }
+}
+
+/// Decode an auxiliary vector, as found in NT_AUXV or /proc/<pid>/auxv,
+/// up to AT_NULL.
+pub fn parse_auxv(bytes: &[u8]) -> Vec<(u64, u64)> {
+    bytes
+        .chunks_exact(16)
+        .map(|entry| (read_u64(entry, 0), read_u64(entry, 8)))
+        .take_while(|&(a_type, _)| a_type != AT_NULL)
+        .collect()
+}
+
+pub fn auxv_name(a_type: u64) -> Option<&'static str> {
+    let name = match a_type {
+        2 => "AT_EXECFD",
+        3 => "AT_PHDR",
+        4 => "AT_PHENT",
+        5 => "AT_PHNUM",
+        6 => "AT_PAGESZ",
+        7 => "AT_BASE",
+        8 => "AT_FLAGS",
+        9 => "AT_ENTRY",
+        11 => "AT_UID",
+        12 => "AT_EUID",
+        13 => "AT_GID",
+        14 => "AT_EGID",
+        15 => "AT_PLATFORM",
+        16 => "AT_HWCAP",
+        17 => "AT_CLKTCK",
+        23 => "AT_SECURE",
+        24 => "AT_BASE_PLATFORM",
+        25 => "AT_RANDOM",
+        26 => "AT_HWCAP2",
+        27 => "AT_RSEQ_FEATURE_SIZE",
+        28 => "AT_RSEQ_ALIGN",
+        29 => "AT_HWCAP3",
+        30 => "AT_HWCAP4",
+        31 => "AT_EXECFN",
+        32 => "AT_SYSINFO",
+        33 => "AT_SYSINFO_EHDR",
+        51 => "AT_MINSIGSTKSZ",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+pub fn signal_name(signo: i32) -> &'static str {
+    match signo {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        10 => "SIGUSR1",
+        11 => "SIGSEGV",
+        12 => "SIGUSR2",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        24 => "SIGXCPU",
+        25 => "SIGXFSZ",
+        31 => "SIGSYS",
+        _ => "unknown",
+    }
+}
+
+// si_code values, generic ones first and then those of the fault signals.
+fn signal_code_name(signo: i32, code: i32) -> Option<&'static str> {
+    let name = match (signo, code) {
+        (_, 0) => "SI_USER",
+        (_, 0x80) => "SI_KERNEL",
+        (_, -1) => "SI_QUEUE",
+        (_, -6) => "SI_TKILL",
+        (11, 1) => "SEGV_MAPERR",
+        (11, 2) => "SEGV_ACCERR",
+        (7, 1) => "BUS_ADRALN",
+        (7, 2) => "BUS_ADRERR",
+        (7, 3) => "BUS_OBJERR",
+        (4, 1) => "ILL_ILLOPC",
+        (4, 2) => "ILL_ILLOPN",
+        (4, 5) => "ILL_PRVOPC",
+        (8, 1) => "FPE_INTDIV",
+        (8, 3) => "FPE_FLTDIV",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+impl Thread {
+    fn from_note(desc: &[u8], names: &[&'static str]) -> io::Result<Thread> {
+        if desc.len() < PR_REG + names.len() * 8 {
+            return Err(invalid("truncated NT_PRSTATUS note"));
+        }
+
+        Ok(Thread {
+            pid: read_u32(desc, 32) as i32,
+            signal: i16::from_le_bytes([desc[12], desc[13]]) as i32,
+            registers: names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (*name, read_u64(desc, PR_REG + i * 8)))
+                .collect(),
+        })
+    }
+
+    pub fn register(&self, name: &str) -> Option<u64> {
+        self.registers
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, value)| value)
+    }
+
+    pub fn pc(&self) -> Option<u64> {
+        self.register("rip").or_else(|| self.register("pc"))
+    }
+
+    pub fn sp(&self) -> Option<u64> {
+        self.register("rsp").or_else(|| self.register("sp"))
+    }
+}
+
+impl Core {
+    pub fn new(elf: &Elf) -> io::Result<Core> {
+        if elf.e_type() != elf::ET_CORE {
+            return Err(invalid("not a core file"));
+        }
+
+        let mut core = Core {
+            machine: elf.machine(),
+            threads: Vec::new(),
+            process: None,
+            siginfo: None,
+            auxv: Vec::new(),
+            page_size: 0,
+            files: Vec::new(),
+        };
+
+        for note in elf.notes().iter().filter(|note| note.name == "CORE") {
+            core.add_note(note)?;
+        }
+
+        Ok(core)
+    }
+
+    fn add_note(&mut self, note: &Note) -> io::Result<()> {
+        let desc = note.desc.as_slice();
+
+        match note.n_type {
+            elf::NT_PRSTATUS => {
+                if let Some(names) = register_names(self.machine) {
+                    self.threads.push(Thread::from_note(desc, names)?);
+                }
+            }
+            elf::NT_PRPSINFO => {
+                if desc.len() < 136 {
+                    return Err(invalid("truncated NT_PRPSINFO note"));
+                }
+
+                self.process = Some(ProcessInfo {
+                    state: desc[1] as char,
+                    uid: read_u32(desc, 16),
+                    gid: read_u32(desc, 20),
+                    pid: read_u32(desc, 24) as i32,
+                    ppid: read_u32(desc, 28) as i32,
+                    fname: c_string(&desc[40..56]),
+                    psargs: c_string(&desc[56..136]).trim_end().to_string(),
+                });
+            }
+            elf::NT_SIGINFO => {
+                let signo = read_u32(desc, 0) as i32;
+                let fault = matches!(signo, 4 | 7 | 8 | 11);
+
+                self.siginfo = Some(SigInfo {
+                    signo,
+                    errno: read_u32(desc, 4) as i32,
+                    code: read_u32(desc, 8) as i32,
+                    addr: fault.then(|| read_u64(desc, 16)),
+                });
+            }
+            elf::NT_AUXV => self.auxv = parse_auxv(desc),
+            elf::NT_FILE => {
+                let count = read_u64(desc, 0) as usize;
+                self.page_size = read_u64(desc, 8);
+
+                let names_off = count
+                    .checked_mul(24)
+                    .and_then(|n| n.checked_add(16))
+                    .filter(|&off| off <= desc.len())
+                    .ok_or_else(|| invalid("truncated NT_FILE note"))?;
+                let mut names = desc[names_off..].split(|&c| c == b'\0');
+
+                for i in 0..count {
+                    let entry = 16 + i * 24;
+                    self.files.push(MappedFile {
+                        start: read_u64(desc, entry),
+                        end: read_u64(desc, entry + 8),
+                        offset: read_u64(desc, entry + 16) * self.page_size,
+                        path: String::from_utf8_lossy(names.next().unwrap_or(&[])).into_owned(),
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// The thread that took the fatal signal. The kernel writes its note
+    /// first, so that is the fallback when no thread has a signal.
+    pub fn crashed_thread(&self) -> Option<&Thread> {
+        self.threads
+            .iter()
+            .find(|thread| thread.signal != 0)
+            .or_else(|| self.threads.first())
+    }
+
+    pub fn auxv_value(&self, a_type: u64) -> Option<u64> {
+        self.auxv
+            .iter()
+            .find(|&&(t, _)| t == a_type)
+            .map(|&(_, value)| value)
+    }
+
+    /// The mapped file covering `addr`.
+    pub fn file_at(&self, addr: u64) -> Option<&MappedFile> {
+        self.files
+            .iter()
+            .find(|file| file.start <= addr && addr < file.end)
+    }
+
+    pub fn to_str(&self, buf: &mut dyn Write) -> io::Result<()> {
+        buf.write_fmt(format_args!("\nCore file:\n"))?;
+
+        if let Some(process) = self.process.as_ref() {
+            buf.write_fmt(format_args!("  {:<18} {}\n", "Command:", process.psargs))?;
+            buf.write_fmt(format_args!(
+                "  {:<18} pid {}, ppid {}, uid {}, gid {}, state {}\n",
+                "Process:", process.pid, process.ppid, process.uid, process.gid, process.state
+            ))?;
+        }
+
+        if let Some(info) = self.siginfo.as_ref() {
+            buf.write_fmt(format_args!(
+                "  {:<18} {} ({}), code {}",
+                "Signal:",
+                info.signo,
+                signal_name(info.signo),
+                info.code
+            ))?;
+            if let Some(name) = signal_code_name(info.signo, info.code) {
+                buf.write_fmt(format_args!(" ({})", name))?;
+            }
+            if let Some(addr) = info.addr {
+                buf.write_fmt(format_args!(", fault address {:#x}", addr))?;
+            }
+            buf.write_fmt(format_args!("\n"))?;
+        }
+
+        buf.write_fmt(format_args!(
+            "  {:<18} {}\n",
+            "Threads:",
+            self.threads.len()
+        ))?;
+
+        let crashed = self.crashed_thread().map(|thread| thread.pid);
+        for (i, thread) in self.threads.iter().enumerate() {
+            buf.write_fmt(format_args!("\nThread {} (LWP {})", i + 1, thread.pid))?;
+            if Some(thread.pid) == crashed {
+                buf.write_fmt(format_args!(
+                    " crashed with signal {} ({})",
+                    thread.signal,
+                    signal_name(thread.signal)
+                ))?;
+            }
+            buf.write_fmt(format_args!(":\n"))?;
+
+            for row in thread.registers.chunks(3) {
+                for (name, value) in row {
+                    buf.write_fmt(format_args!("  {:<8} {:#018x}", name, value))?;
+                }
+                buf.write_fmt(format_args!("\n"))?;
+            }
+        }
+
+        if !self.auxv.is_empty() {
+            buf.write_fmt(format_args!("\nAuxiliary vector:\n"))?;
+            for &(a_type, value) in self.auxv.iter() {
+                match auxv_name(a_type) {
+                    Some(name) => buf.write_fmt(format_args!("  {:<20} {:#x}\n", name, value))?,
+                    None => buf.write_fmt(format_args!("  {:<20} {:#x}\n", a_type, value))?,
+                }
+            }
+        }
+
+        if !self.files.is_empty() {
+            buf.write_fmt(format_args!(
+                "\nMapped files ({} entries, page size {}):\n",
+                self.files.len(),
+                self.page_size
+            ))?;
+            buf.write_fmt(format_args!(
+                "  {:<18} {:<18} {:<10} {}\n",
+                "Start", "End", "Offset", "Path"
+            ))?;
+            for file in self.files.iter() {
+                buf.write_fmt(format_args!(
+                    "  {:#018x} {:#018x} {:<#10x} {}\n",
+                    file.start, file.end, file.offset, file.path
+                ))?;
+            }
+        }
+
+        Ok(())
+    }
+}
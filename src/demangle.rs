@@ -0,0 +1,21 @@
+use cpp_demangle::{DemangleOptions, Symbol};
+
+/// Demangle a Rust (legacy or v0) or Itanium C++ symbol name, or return it
+/// unchanged when it is neither. Rust names lose their hash suffix, like
+/// in the output of binutils.
+pub fn demangle(name: &str) -> String {
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        return format!("{:#}", demangled);
+    }
+
+    if name.starts_with("_Z") {
+        if let Ok(demangled) = Symbol::new(name)
+            .map_err(|_| ())
+            .and_then(|sym| sym.demangle(&DemangleOptions::default()).map_err(|_| ()))
+        {
+            return demangled;
+        }
+    }
+
+    name.to_string()
+}
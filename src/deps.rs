@@ -0,0 +1,394 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::elf::{self, Elf, Options};
+use crate::ldcache::LdCache;
+use crate::strip::glob_match;
+
+// How deep ld.so.conf includes may nest before they are taken as a loop.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+// How many symbolic links to follow to the dynamic loader.
+const MAX_SYMLINKS: usize = 8;
+
+/// Where the dynamic loader looks for libraries, read from the file system
+/// below `sysroot` so that a target image can be examined from the host.
+pub struct SearchPath {
+    sysroot: PathBuf,
+    cache: Option<LdCache>,
+    conf_dirs: Vec<String>,
+    // What $LIB stands for when given rather than found out.
+    lib: Option<String>,
+}
+
+impl SearchPath {
+    pub fn new(sysroot: Option<&Path>, lib: Option<&str>) -> SearchPath {
+        let mut search = SearchPath {
+            sysroot: sysroot.map_or_else(|| PathBuf::from("/"), Path::to_path_buf),
+            cache: None,
+            conf_dirs: Vec::new(),
+            lib: lib.map(|lib| lib.trim_matches('/').to_string()),
+        };
+
+        search.cache = fs::read(search.host_path("/etc/ld.so.cache"))
+            .ok()
+            .and_then(|data| LdCache::from_bytes(&data).ok());
+
+        let mut dirs = Vec::new();
+        search.read_conf("/etc/ld.so.conf", 0, &mut dirs);
+        search.conf_dirs = dirs;
+
+        search
+    }
+
+    /// Where the target's `path` is on the host.
+    pub fn host_path(&self, path: &str) -> PathBuf {
+        self.sysroot.join(path.trim_start_matches('/'))
+    }
+
+    /// The path the target sees for the host's `path`, if it lies below the
+    /// sysroot.
+    pub fn target_path(&self, path: &Path) -> Option<String> {
+        let rest = path.strip_prefix(&self.sysroot).ok()?;
+        Some(format!("/{}", rest.to_string_lossy()))
+    }
+
+    /// The directory $LIB stands for, such as "lib64" or
+    /// "lib/x86_64-linux-gnu": glibc installs the dynamic loader there, so
+    /// it is found by following the `interpreter` symlinks below the
+    /// sysroot.
+    pub fn lib_dir(&self, interpreter: &str) -> String {
+        if let Some(lib) = self.lib.as_ref() {
+            return lib.clone();
+        }
+
+        let mut path = PathBuf::from(interpreter);
+        for _ in 0..MAX_SYMLINKS {
+            let link = match fs::read_link(self.host_path(&path.to_string_lossy())) {
+                Ok(link) => link,
+                Err(_) => break,
+            };
+            path = match path.parent() {
+                Some(dir) => dir.join(link),
+                None => link,
+            };
+        }
+
+        let dir = path.parent().map_or(String::new(), |dir| {
+            dir.to_string_lossy().trim_matches('/').to_string()
+        });
+        match dir.strip_prefix("usr/").unwrap_or(&dir) {
+            "" => String::from("lib64"),
+            dir => dir.to_string(),
+        }
+    }
+
+    // Collect the directories of an ld.so.conf file, following its
+    // "include" lines. Unreadable files are skipped like ldconfig does.
+    fn read_conf(&self, path: &str, depth: usize, dirs: &mut Vec<String>) {
+        let text = match fs::read_to_string(self.host_path(path)) {
+            Ok(text) if depth < MAX_INCLUDE_DEPTH => text,
+            _ => return,
+        };
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new("/"));
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+
+            if let Some(pattern) = line.strip_prefix("include") {
+                for pattern in pattern.split_whitespace() {
+                    let pattern = dir.join(pattern).to_string_lossy().into_owned();
+                    for file in self.expand_glob(&pattern) {
+                        self.read_conf(&file, depth + 1, dirs);
+                    }
+                }
+            } else if line.starts_with('/') && !dirs.iter().any(|d| d == line) {
+                dirs.push(line.to_string());
+            }
+        }
+    }
+
+    // The files matching a pattern with wildcards in its last component, in
+    // sorted order.
+    fn expand_glob(&self, pattern: &str) -> Vec<String> {
+        let (dir, name) = pattern.rsplit_once('/').unwrap_or(("", pattern));
+        let dir = if dir.is_empty() { "/" } else { dir };
+
+        let mut files: Vec<String> = match fs::read_dir(self.host_path(dir)) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|file| glob_match(name, file))
+                .map(|file| Path::new(dir).join(file).to_string_lossy().into_owned())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        files.sort();
+
+        files
+    }
+}
+
+/// A DT_NEEDED entry and the object it was resolved to.
+pub struct Needed {
+    pub name: String,
+    pub object: Option<usize>,
+}
+
+/// An object of a dependency closure.
+pub struct Object {
+    /// The target path of the object.
+    pub path: String,
+    pub elf: Elf,
+    /// The object whose DT_NEEDED first brought this one in.
+    pub loader: Option<usize>,
+    pub needed: Vec<Needed>,
+}
+
+/// The objects a program loads, resolved the way the dynamic loader does
+/// without running it. They are kept in load order: the program first,
+/// then its libraries breadth first, which is also the order symbols are
+/// looked up in.
+pub struct Dependencies {
+    pub objects: Vec<Object>,
+    // The directory $LIB stands for.
+    lib: String,
+}
+
+// The platform name of $PLATFORM.
+fn platform(machine: u16) -> &'static str {
+    match machine {
+        elf::EM_X86_64 => "x86_64",
+        elf::EM_AARCH64 => "aarch64",
+        _ => "",
+    }
+}
+
+// The dynamic loader of the psABI, for libraries that name none.
+fn default_interpreter(machine: u16) -> &'static str {
+    match machine {
+        elf::EM_AARCH64 => "/lib/ld-linux-aarch64.so.1",
+        _ => "/lib64/ld-linux-x86-64.so.2",
+    }
+}
+
+impl Dependencies {
+    /// Resolve the dependency closure of the file at host path `path`.
+    pub fn resolve(path: &Path, search: &SearchPath) -> io::Result<Dependencies> {
+        let elf = Elf::from_bytes(fs::read(path)?, Options::default())?;
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let target = search
+            .target_path(&path)
+            .unwrap_or_else(|| path.to_string_lossy().into_owned());
+
+        let interpreter = elf
+            .interpreter()
+            .unwrap_or_else(|| default_interpreter(elf.machine()).to_string());
+        let mut deps = Dependencies {
+            objects: vec![Object {
+                path: target,
+                elf,
+                loader: None,
+                needed: Vec::new(),
+            }],
+            lib: search.lib_dir(&interpreter),
+        };
+
+        let mut i = 0;
+        while i < deps.objects.len() {
+            for name in deps.objects[i].elf.needed() {
+                let object = deps.load(i, &name, search);
+                deps.objects[i].needed.push(Needed { name, object });
+            }
+            i += 1;
+        }
+
+        Ok(deps)
+    }
+
+    // Find the object `requester` needs by `name`, reusing one already
+    // loaded under that name or path.
+    fn load(&mut self, requester: usize, name: &str, search: &SearchPath) -> Option<usize> {
+        let loaded = self.objects.iter().position(|object| {
+            object.elf.soname().as_deref() == Some(name)
+                || Path::new(&object.path).file_name() == Some(name.as_ref())
+        });
+        if loaded.is_some() {
+            return loaded;
+        }
+
+        let machine = self.objects[0].elf.machine();
+        for path in self.candidates(requester, name, search) {
+            if let Some(i) = self.objects.iter().position(|o| o.path == path) {
+                return Some(i);
+            }
+
+            let elf = match fs::read(search.host_path(&path))
+                .and_then(|data| Elf::from_bytes(data, Options::default()))
+            {
+                Ok(elf) if elf.machine() == machine => elf,
+                _ => continue,
+            };
+
+            self.objects.push(Object {
+                path,
+                elf,
+                loader: Some(requester),
+                needed: Vec::new(),
+            });
+            return Some(self.objects.len() - 1);
+        }
+
+        None
+    }
+
+    // The paths to try for `name`, in the order of ld.so(8): DT_RPATH of the
+    // requester and its loaders unless the requester has a DT_RUNPATH, its
+    // DT_RUNPATH, ld.so.cache, and the default directories.
+    fn candidates(&self, requester: usize, name: &str, search: &SearchPath) -> Vec<String> {
+        let object = &self.objects[requester];
+
+        if name.contains('/') {
+            return vec![self.expand(requester, name)];
+        }
+
+        let mut dirs: Vec<String> = Vec::new();
+        let runpath = object.elf.runpath();
+
+        if runpath.is_none() {
+            let mut cur = Some(requester);
+            while let Some(i) = cur {
+                if let Some(rpath) = self.objects[i].elf.rpath() {
+                    dirs.extend(rpath.split(':').map(|dir| self.expand(i, dir)));
+                }
+                cur = self.objects[i].loader;
+            }
+        }
+        if let Some(runpath) = runpath {
+            dirs.extend(runpath.split(':').map(|dir| self.expand(requester, dir)));
+        }
+
+        let mut paths: Vec<String> = dirs
+            .iter()
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| format!("{}/{}", dir.trim_end_matches('/'), name))
+            .collect();
+
+        let nodeflib = object.elf.dynamic_value(elf::DT_FLAGS_1).unwrap_or(0) & elf::DF_1_NODEFLIB;
+        if nodeflib == 0 {
+            if let Some(cache) = search.cache.as_ref() {
+                let machine = self.objects[0].elf.machine();
+                paths.extend(cache.lookup(name, machine).into_iter().map(String::from));
+            }
+            // Without a cache, fall back on what it would have been built
+            // from.
+            if search.cache.is_none() {
+                paths.extend(
+                    search
+                        .conf_dirs
+                        .iter()
+                        .map(|dir| format!("{}/{}", dir, name)),
+                );
+            }
+            // The system directories: those of $LIB, then the plain ones
+            // multiarch systems search as well.
+            let mut system = vec![format!("/{}", self.lib), format!("/usr/{}", self.lib)];
+            for dir in ["/lib", "/usr/lib"] {
+                if !system.iter().any(|d| d == dir) {
+                    system.push(dir.to_string());
+                }
+            }
+            paths.extend(system.iter().map(|dir| format!("{}/{}", dir, name)));
+        }
+
+        paths
+    }
+
+    // Expand the $ORIGIN, $LIB and $PLATFORM tokens of a search path entry
+    // of object `i`.
+    fn expand(&self, i: usize, s: &str) -> String {
+        let object = &self.objects[i];
+        let origin = Path::new(&object.path)
+            .parent()
+            .map_or(String::from("/"), |dir| dir.to_string_lossy().into_owned());
+        let machine = object.elf.machine();
+
+        let mut s = s.to_string();
+        for (token, value) in [
+            ("ORIGIN", origin.as_str()),
+            ("LIB", self.lib.as_str()),
+            ("PLATFORM", platform(machine)),
+        ] {
+            s = s
+                .replace(&format!("${{{}}}", token), value)
+                .replace(&format!("${}", token), value);
+        }
+
+        s
+    }
+
+    /// Object `i` and everything it loads, directly or not.
+    pub fn closure(&self, i: usize) -> HashSet<usize> {
+        let mut seen = HashSet::from([i]);
+        let mut stack = vec![i];
+
+        while let Some(i) = stack.pop() {
+            for j in self.objects[i].needed.iter().filter_map(|n| n.object) {
+                if seen.insert(j) {
+                    stack.push(j);
+                }
+            }
+        }
+
+        seen
+    }
+
+    /// The DT_NEEDED entries nothing was found for.
+    pub fn missing(&self) -> Vec<&str> {
+        self.objects
+            .iter()
+            .flat_map(|object| object.needed.iter())
+            .filter(|needed| needed.object.is_none())
+            .map(|needed| needed.name.as_str())
+            .collect()
+    }
+
+    /// Print the dependencies as a tree. A library is only expanded the
+    /// first time it appears.
+    pub fn to_str(&self, buf: &mut dyn Write) -> io::Result<()> {
+        buf.write_fmt(format_args!("{}", self.objects[0].path))?;
+        if let Some(interp) = self.objects[0].elf.interpreter() {
+            buf.write_fmt(format_args!(" (interpreter {})", interp))?;
+        }
+        buf.write_fmt(format_args!("\n"))?;
+
+        let mut shown = HashSet::from([0]);
+        self.write_tree(0, 1, &mut shown, buf)
+    }
+
+    fn write_tree(
+        &self,
+        i: usize,
+        depth: usize,
+        shown: &mut HashSet<usize>,
+        buf: &mut dyn Write,
+    ) -> io::Result<()> {
+        for needed in self.objects[i].needed.iter() {
+            buf.write_fmt(format_args!("{:1$}{2} => ", "", depth * 4, needed.name))?;
+
+            match needed.object {
+                Some(j) => {
+                    buf.write_fmt(format_args!("{}\n", self.objects[j].path))?;
+                    if shown.insert(j) {
+                        self.write_tree(j, depth + 1, shown, buf)?;
+                    }
+                }
+                None => buf.write_fmt(format_args!("not found\n"))?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::elf::{self, Elf, Elf64Phdr, Elf64Shdr};
+use crate::nm::{self, Entry};
+
+/// Whether an item is only in the new file, only in the old one, or in
+/// both with differences.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl Status {
+    fn sign(&self) -> char {
+        match self {
+            Status::Added => '+',
+            Status::Removed => '-',
+            Status::Changed => '~',
+        }
+    }
+}
+
+/// A property with a different value in the two files.
+#[derive(Debug, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// A program header, section, symbol or dynamic entry that differs.
+#[derive(Debug, Serialize)]
+pub struct ItemDiff {
+    pub name: String,
+    pub status: Status,
+    pub changes: Vec<FieldChange>,
+    /// The sizes of sections, symbols and segments in memory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_size: Option<u64>,
+}
+
+impl ItemDiff {
+    pub fn size_delta(&self) -> i64 {
+        self.new_size.unwrap_or(0) as i64 - self.old_size.unwrap_or(0) as i64
+    }
+}
+
+/// The differences between two ELF files. Program headers are matched by
+/// type and position among those of the type, sections and symbols by
+/// name, and dynamic entries by tag, or by value for those naming
+/// libraries and search paths.
+#[derive(Debug, Serialize)]
+pub struct Diff {
+    pub old_file: String,
+    pub new_file: String,
+    pub old_file_size: u64,
+    pub new_file_size: u64,
+    pub header: Vec<FieldChange>,
+    pub program_headers: Vec<ItemDiff>,
+    pub sections: Vec<ItemDiff>,
+    pub symbols: Vec<ItemDiff>,
+    pub dynamic: Vec<ItemDiff>,
+}
+
+// The properties of an item to compare, by name, and its size if it has
+// one.
+struct Item {
+    name: String,
+    fields: Vec<(&'static str, String)>,
+    size: Option<u64>,
+}
+
+// Fields holding addresses or file offsets, which change whenever
+// anything before them grows.
+const ADDRESS_FIELDS: [&str; 4] = ["entry", "offset", "vaddr", "addr"];
+
+// Dynamic tags whose value is an address.
+const ADDRESS_TAGS: [i64; 17] = [
+    elf::DT_PLTGOT,
+    elf::DT_HASH,
+    elf::DT_STRTAB,
+    elf::DT_SYMTAB,
+    elf::DT_RELA,
+    elf::DT_INIT,
+    elf::DT_FINI,
+    elf::DT_REL,
+    elf::DT_DEBUG,
+    elf::DT_JMPREL,
+    elf::DT_INIT_ARRAY,
+    elf::DT_FINI_ARRAY,
+    elf::DT_PREINIT_ARRAY,
+    elf::DT_GNU_HASH,
+    elf::DT_VERSYM,
+    elf::DT_VERDEF,
+    elf::DT_VERNEED,
+];
+
+// Name the items that share a name by their position, "name[2]" for the
+// second one, so that they can be matched.
+fn unique_names(items: &mut [Item]) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+
+    for item in items.iter_mut() {
+        let count = seen.entry(item.name.clone()).or_default();
+        *count += 1;
+        if *count > 1 {
+            item.name = format!("{}[{}]", item.name, count);
+        }
+    }
+}
+
+// Compare two lists of fields, leaving out addresses if asked to.
+fn compare_fields(
+    old: &[(&'static str, String)],
+    new: &[(&'static str, String)],
+    addresses: bool,
+) -> Vec<FieldChange> {
+    old.iter()
+        .zip(new.iter())
+        .filter(|((field, _), _)| addresses || !ADDRESS_FIELDS.contains(field))
+        .filter(|((_, old), (_, new))| old != new)
+        .map(|((field, old), (_, new))| FieldChange {
+            field: field.to_string(),
+            old: old.clone(),
+            new: new.clone(),
+        })
+        .collect()
+}
+
+// Match two lists of items by name: the removed and changed ones in the
+// order of the old file, then the added ones in the order of the new.
+fn compare_items(mut old: Vec<Item>, mut new: Vec<Item>, addresses: bool) -> Vec<ItemDiff> {
+    unique_names(&mut old);
+    unique_names(&mut new);
+
+    let new_index: HashMap<&str, &Item> = new.iter().map(|i| (i.name.as_str(), i)).collect();
+    let old_index: HashMap<&str, &Item> = old.iter().map(|i| (i.name.as_str(), i)).collect();
+    let mut diffs = Vec::new();
+
+    for item in old.iter() {
+        match new_index.get(item.name.as_str()) {
+            Some(other) => {
+                let changes = compare_fields(&item.fields, &other.fields, addresses);
+                if !changes.is_empty() {
+                    diffs.push(ItemDiff {
+                        name: item.name.clone(),
+                        status: Status::Changed,
+                        changes,
+                        old_size: item.size,
+                        new_size: other.size,
+                    });
+                }
+            }
+            None => diffs.push(ItemDiff {
+                name: item.name.clone(),
+                status: Status::Removed,
+                changes: Vec::new(),
+                old_size: item.size,
+                new_size: item.size.map(|_| 0),
+            }),
+        }
+    }
+
+    for item in new.iter() {
+        if !old_index.contains_key(item.name.as_str()) {
+            diffs.push(ItemDiff {
+                name: item.name.clone(),
+                status: Status::Added,
+                changes: Vec::new(),
+                old_size: item.size.map(|_| 0),
+                new_size: item.size,
+            });
+        }
+    }
+
+    diffs
+}
+
+fn header_fields(elf: &Elf) -> Vec<(&'static str, String)> {
+    let ehdr = elf.header();
+
+    vec![
+        (
+            "type",
+            elf::ELFTYPE
+                .get(&ehdr.e_type)
+                .map_or_else(|| format!("{:#x}", ehdr.e_type), |name| name.to_string()),
+        ),
+        ("machine", elf::machine_name(ehdr.e_machine).to_string()),
+        ("osabi", ehdr.e_ident[7].to_string()),
+        ("entry", format!("{:#x}", ehdr.e_entry)),
+        ("flags", format!("{:#x}", ehdr.e_flags)),
+        ("program headers", elf.program_headers().len().to_string()),
+        ("section headers", elf.section_headers().len().to_string()),
+        ("interpreter", elf.interpreter().unwrap_or_default()),
+        ("build id", elf.build_id().unwrap_or_default()),
+    ]
+}
+
+fn segment_items(elf: &Elf) -> Vec<Item> {
+    elf.program_headers()
+        .iter()
+        .map(|phdr: &Elf64Phdr| Item {
+            name: elf::ELF_PH_TYPE
+                .get(&phdr.p_type)
+                .map_or_else(|| format!("{:#x}", phdr.p_type), |name| name.to_string()),
+            fields: vec![
+                ("offset", format!("{:#x}", phdr.p_offset)),
+                ("vaddr", format!("{:#x}", phdr.p_vaddr)),
+                ("filesz", format!("{:#x}", phdr.p_filesz)),
+                ("memsz", format!("{:#x}", phdr.p_memsz)),
+                ("flags", phdr.flags_str()),
+                ("align", format!("{:#x}", phdr.p_align)),
+            ],
+            size: Some(phdr.p_memsz),
+        })
+        .collect()
+}
+
+fn section_items(elf: &Elf) -> Vec<Item> {
+    elf.section_headers()
+        .iter()
+        .filter(|shdr| shdr.sh_type != elf::SHT_NULL)
+        .map(|shdr: &Elf64Shdr| Item {
+            name: elf.section_name(shdr),
+            fields: vec![
+                (
+                    "type",
+                    elf::ELF_SH_TYPE
+                        .get(&shdr.sh_type)
+                        .map_or_else(|| format!("{:#x}", shdr.sh_type), |name| name.to_string()),
+                ),
+                ("flags", shdr.flags_str()),
+                ("addr", format!("{:#x}", shdr.sh_addr)),
+                ("offset", format!("{:#x}", shdr.sh_offset)),
+                ("size", shdr.sh_size.to_string()),
+                ("entsize", shdr.sh_entsize.to_string()),
+                ("align", shdr.sh_addralign.to_string()),
+            ],
+            size: Some(shdr.sh_size),
+        })
+        .collect()
+}
+
+// The symbols of .symtab, or of .dynsym with their versions if there is
+// none, as nm lists them.
+fn symbol_items(elf: &Elf) -> Vec<Item> {
+    let mut options = nm::Options {
+        format: nm::Format::Bsd,
+        sort: nm::Sort::None,
+        reverse: false,
+        dynamic: false,
+        defined_only: false,
+        undefined_only: false,
+        print_size: false,
+        demangle: false,
+    };
+    let entries = elf.nm_entries(&options).or_else(|| {
+        options.dynamic = true;
+        elf.nm_entries(&options)
+    });
+
+    entries
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry: Entry| Item {
+            fields: vec![
+                ("type", entry.letter.to_string()),
+                ("addr", format!("{:#x}", entry.value)),
+                ("size", entry.size.to_string()),
+                ("section", entry.section),
+            ],
+            name: entry.name,
+            size: Some(entry.size),
+        })
+        .collect()
+}
+
+// Dynamic entries naming a library or search path are told apart by their
+// value, the others by their tag.
+fn dynamic_items(elf: &Elf) -> Vec<Item> {
+    elf.dynamic()
+        .iter()
+        .map(|dyn_| {
+            let tag = elf::ELF_DT_TAG
+                .get(&dyn_.d_tag)
+                .map_or_else(|| format!("{:#x}", dyn_.d_tag), |name| name.to_string());
+
+            match dyn_.d_tag {
+                elf::DT_NEEDED | elf::DT_SONAME | elf::DT_RPATH | elf::DT_RUNPATH => Item {
+                    name: format!("{} {}", tag, elf.dynamic_str(dyn_.d_val)),
+                    fields: Vec::new(),
+                    size: None,
+                },
+                _ => Item {
+                    name: tag,
+                    fields: vec![(
+                        match ADDRESS_TAGS.contains(&dyn_.d_tag) {
+                            true => "addr",
+                            false => "value",
+                        },
+                        format!("{:#x}", dyn_.d_val),
+                    )],
+                    size: None,
+                },
+            }
+        })
+        .collect()
+}
+
+impl Diff {
+    /// Compare `old` with `new`. Changes of addresses and file offsets
+    /// alone are left out unless `addresses` is set.
+    pub fn new(old_file: &str, old: &Elf, new_file: &str, new: &Elf, addresses: bool) -> Diff {
+        Diff {
+            old_file: old_file.to_string(),
+            new_file: new_file.to_string(),
+            old_file_size: old.data().len() as u64,
+            new_file_size: new.data().len() as u64,
+            header: compare_fields(&header_fields(old), &header_fields(new), addresses),
+            program_headers: compare_items(segment_items(old), segment_items(new), addresses),
+            sections: compare_items(section_items(old), section_items(new), addresses),
+            symbols: compare_items(symbol_items(old), symbol_items(new), addresses),
+            dynamic: compare_items(dynamic_items(old), dynamic_items(new), addresses),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty()
+            && self.program_headers.is_empty()
+            && self.sections.is_empty()
+            && self.symbols.is_empty()
+            && self.dynamic.is_empty()
+            && self.old_file_size == self.new_file_size
+    }
+
+    pub fn to_str(&self, buf: &mut dyn Write) -> io::Result<()> {
+        buf.write_fmt(format_args!(
+            "--- {}\n+++ {}\n",
+            self.old_file, self.new_file
+        ))?;
+
+        if !self.header.is_empty() {
+            buf.write_fmt(format_args!("\nFile header:\n"))?;
+            for change in self.header.iter() {
+                buf.write_fmt(format_args!(
+                    "  {}: {} -> {}\n",
+                    change.field,
+                    show(&change.old),
+                    show(&change.new)
+                ))?;
+            }
+        }
+
+        for (title, diffs) in [
+            ("Program headers", &self.program_headers),
+            ("Section headers", &self.sections),
+            ("Symbols", &self.symbols),
+            ("Dynamic section", &self.dynamic),
+        ] {
+            if diffs.is_empty() {
+                continue;
+            }
+
+            buf.write_fmt(format_args!("\n{}:\n", title))?;
+            for diff in diffs.iter() {
+                write_item(diff, buf)?;
+            }
+        }
+
+        let sections = |diffs: &[ItemDiff]| -> i64 { diffs.iter().map(ItemDiff::size_delta).sum() };
+        buf.write_fmt(format_args!(
+            "\nSection sizes: {:+}\nFile size: {} -> {} ({:+})\n",
+            sections(&self.sections),
+            self.old_file_size,
+            self.new_file_size,
+            self.new_file_size as i64 - self.old_file_size as i64
+        ))
+    }
+
+    pub fn to_json(&self, buf: &mut dyn Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *buf, self)?;
+        buf.write_fmt(format_args!("\n"))
+    }
+}
+
+// An empty value, such as a missing interpreter, shown so it can be seen.
+fn show(value: &str) -> &str {
+    match value {
+        "" => "(none)",
+        value => value,
+    }
+}
+
+fn write_item(diff: &ItemDiff, buf: &mut dyn Write) -> io::Result<()> {
+    buf.write_fmt(format_args!("  {} {}", diff.status.sign(), diff.name))?;
+    if diff.size_delta() != 0 {
+        buf.write_fmt(format_args!(" ({:+})", diff.size_delta()))?;
+    }
+
+    let changes: Vec<String> = diff
+        .changes
+        .iter()
+        .map(|change| {
+            format!(
+                "{} {} -> {}",
+                change.field,
+                show(&change.old),
+                show(&change.new)
+            )
+        })
+        .collect();
+    if !changes.is_empty() {
+        buf.write_fmt(format_args!(": {}", changes.join(", ")))?;
+    }
+
+    buf.write_fmt(format_args!("\n"))
+}
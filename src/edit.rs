@@ -0,0 +1,546 @@
+use std::io;
+use std::mem;
+
+use crate::elf::{self, read_struct, Elf, Elf64Dyn, Elf64Phdr};
+use crate::symver::{Elf64Vernaux, Elf64Verneed, VERSYM_HIDDEN, VER_NDX_LOCAL};
+use crate::writer::{as_bytes, ElfWriter};
+
+/// Changes the program interpreter and the dynamic entries naming the
+/// search paths, the soname and the needed libraries of an executable or
+/// shared object, in the manner of patchelf.
+///
+/// Strings already present in .dynstr are reused and new ones are appended,
+/// so existing string offsets (symbol names, version needs) stay valid.
+/// Whatever no longer fits where it is (the interpreter, .dynstr or
+/// .dynamic) moves to a new PT_LOAD segment at the end of the file, which
+/// also holds the enlarged program header table.
+pub struct Editor {
+    writer: ElfWriter,
+    dynamic: Vec<Elf64Dyn>,
+    dynamic_range: Option<(u64, u64)>,
+    dynamic_changed: bool,
+    dynstr: Vec<u8>,
+    dynstr_size: usize,
+    interpreter: Option<String>,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    match align {
+        0 | 1 => value,
+        _ => value.div_ceil(align) * align,
+    }
+}
+
+const DYN_SIZE: u64 = mem::size_of::<Elf64Dyn>() as u64;
+const VERNEED_SIZE: u32 = mem::size_of::<Elf64Verneed>() as u32;
+const VERNAUX_SIZE: u32 = mem::size_of::<Elf64Vernaux>() as u32;
+
+impl Editor {
+    pub fn new(elf: &Elf) -> io::Result<Editor> {
+        let dynstr = elf.dynstr().to_vec();
+
+        Ok(Editor {
+            writer: ElfWriter::new(elf)?,
+            dynamic: elf.dynamic(),
+            dynamic_range: elf.dynamic_range(),
+            dynamic_changed: false,
+            dynstr_size: dynstr.len(),
+            dynstr,
+            interpreter: None,
+        })
+    }
+
+    pub fn set_interpreter(&mut self, path: &str) -> io::Result<()> {
+        if !self
+            .writer
+            .segments
+            .iter()
+            .any(|p| p.p_type == elf::PT_INTERP)
+        {
+            return Err(invalid("no PT_INTERP segment"));
+        }
+
+        self.interpreter = Some(path.to_string());
+        Ok(())
+    }
+
+    /// Set DT_SONAME, adding the entry if the object has none.
+    pub fn set_soname(&mut self, name: &str) -> io::Result<()> {
+        self.set_string(elf::DT_SONAME, name)
+    }
+
+    /// Set DT_RPATH. DT_RUNPATH is removed, as it would make the dynamic
+    /// linker ignore DT_RPATH.
+    pub fn set_rpath(&mut self, path: &str) -> io::Result<()> {
+        self.remove(elf::DT_RUNPATH, None)?;
+        self.set_string(elf::DT_RPATH, path)
+    }
+
+    /// Set DT_RUNPATH, removing DT_RPATH.
+    pub fn set_runpath(&mut self, path: &str) -> io::Result<()> {
+        self.remove(elf::DT_RPATH, None)?;
+        self.set_string(elf::DT_RUNPATH, path)
+    }
+
+    /// Remove both DT_RPATH and DT_RUNPATH.
+    pub fn remove_rpath(&mut self) -> io::Result<()> {
+        self.remove(elf::DT_RPATH, None)?;
+        self.remove(elf::DT_RUNPATH, None)
+    }
+
+    /// Add a DT_NEEDED entry after the existing ones, unless `name` is
+    /// already needed.
+    pub fn add_needed(&mut self, name: &str) -> io::Result<()> {
+        self.check_dynamic()?;
+        if self.find(elf::DT_NEEDED, Some(name)).is_some() {
+            return Ok(());
+        }
+
+        let pos = self
+            .dynamic
+            .iter()
+            .rposition(|d| d.d_tag == elf::DT_NEEDED)
+            .map_or(0, |i| i + 1);
+        let dyn_ = Elf64Dyn {
+            d_tag: elf::DT_NEEDED,
+            d_val: self.add_string(name),
+        };
+
+        self.dynamic.insert(pos, dyn_);
+        self.dynamic_changed = true;
+        Ok(())
+    }
+
+    /// Remove the DT_NEEDED entry of `name` and the versions it is needed
+    /// with, which the dynamic linker would otherwise look for in a library
+    /// it no longer loads. Fails if a symbol still needs one of them.
+    pub fn remove_needed(&mut self, name: &str) -> io::Result<()> {
+        self.remove(elf::DT_NEEDED, Some(name))?;
+        self.remove_version_needs(name)
+    }
+
+    // Unlink the .gnu.version_r record of library `name`, and zero the
+    // .gnu.version entries of the weak references to its versions.
+    fn remove_version_needs(&mut self, name: &str) -> io::Result<()> {
+        let verneed = match self
+            .writer
+            .sections
+            .iter()
+            .position(|s| s.header.sh_type == elf::SHT_GNU_VERNEED)
+        {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+
+        let section = &self.writer.sections[verneed];
+        let mut kept: Vec<(Elf64Verneed, Vec<Elf64Vernaux>)> = Vec::new();
+        let mut removed: Vec<Elf64Vernaux> = Vec::new();
+        let mut off = 0;
+        for _ in 0..section.header.sh_info {
+            let need: Elf64Verneed = read_struct(&section.data, off)?;
+            let mut auxs = Vec::new();
+            let mut aux_off = off + need.vn_aux as usize;
+            for _ in 0..need.vn_cnt {
+                let aux: Elf64Vernaux = read_struct(&section.data, aux_off)?;
+                auxs.push(aux);
+                if aux.vna_next == 0 {
+                    break;
+                }
+                aux_off += aux.vna_next as usize;
+            }
+
+            match self.string(need.vn_file as u64) == name.as_bytes() {
+                true => removed.extend(auxs),
+                false => kept.push((need, auxs)),
+            }
+            if need.vn_next == 0 {
+                break;
+            }
+            off += need.vn_next as usize;
+        }
+        if removed.is_empty() {
+            return Ok(());
+        }
+
+        // Weak references may go unversioned; anything else would be left
+        // asking for a version no loaded library defines.
+        let versym = self
+            .writer
+            .sections
+            .iter()
+            .position(|s| s.header.sh_type == elf::SHT_GNU_VERSYM);
+        let mut versions = Vec::new();
+        if let Some(v) = versym {
+            let symbols = self
+                .writer
+                .symbols(self.writer.sections[v].header.sh_link as usize);
+            versions = self.writer.sections[v].data.clone();
+
+            for (j, entry) in versions.chunks_exact_mut(2).enumerate() {
+                let ndx = u16::from_le_bytes([entry[0], entry[1]]) & !VERSYM_HIDDEN;
+                let aux = match removed.iter().find(|aux| aux.vna_other == ndx) {
+                    Some(aux) => aux,
+                    None => continue,
+                };
+                match symbols.get(j) {
+                    Some(sym) if sym.st_info >> 4 == elf::STB_WEAK => {}
+                    sym => {
+                        return Err(invalid(&format!(
+                            "symbol {} still needs version {} of {}",
+                            String::from_utf8_lossy(
+                                self.string(sym.map_or(0, |s| s.st_name) as u64)
+                            ),
+                            String::from_utf8_lossy(self.string(aux.vna_name as u64)),
+                            name
+                        )))
+                    }
+                }
+                entry.copy_from_slice(&VER_NDX_LOCAL.to_le_bytes());
+            }
+        }
+
+        // The remaining records are chained again from the start of the
+        // section, which keeps its size.
+        let mut bytes = Vec::with_capacity(section.data.len());
+        for (k, (need, auxs)) in kept.iter().enumerate() {
+            let need = Elf64Verneed {
+                vn_cnt: auxs.len() as u16,
+                vn_aux: VERNEED_SIZE,
+                vn_next: match k + 1 == kept.len() {
+                    true => 0,
+                    false => VERNEED_SIZE + VERNAUX_SIZE * auxs.len() as u32,
+                },
+                ..*need
+            };
+            bytes.extend_from_slice(as_bytes(&need));
+            for (a, aux) in auxs.iter().enumerate() {
+                let aux = Elf64Vernaux {
+                    vna_next: match a + 1 == auxs.len() {
+                        true => 0,
+                        false => VERNAUX_SIZE,
+                    },
+                    ..*aux
+                };
+                bytes.extend_from_slice(as_bytes(&aux));
+            }
+        }
+        bytes.resize(section.data.len(), 0);
+
+        let verneed_off = section.header.sh_offset;
+        self.writer.sections[verneed].header.sh_info = kept.len() as u32;
+        self.writer.write_at(verneed_off, &bytes)?;
+        if let Some(v) = versym {
+            let versym_off = self.writer.sections[v].header.sh_offset;
+            self.writer.write_at(versym_off, &versions)?;
+        }
+
+        // The dynamic linker walks the records until vn_next is 0, so an
+        // empty table has to go altogether.
+        match kept.len() {
+            0 => {
+                self.remove(elf::DT_VERNEED, None)?;
+                self.remove(elf::DT_VERNEEDNUM, None)
+            }
+            n => {
+                self.set_value(elf::DT_VERNEEDNUM, n as u64);
+                self.dynamic_changed = true;
+                Ok(())
+            }
+        }
+    }
+
+    fn check_dynamic(&self) -> io::Result<()> {
+        match self.dynamic_range {
+            Some(_) => Ok(()),
+            None => Err(invalid("no dynamic section")),
+        }
+    }
+
+    fn string(&self, off: u64) -> &[u8] {
+        let table = self.dynstr.get(off as usize..).unwrap_or(&[]);
+        let end = table.iter().position(|&c| c == b'\0').unwrap_or(0);
+
+        &table[..end]
+    }
+
+    // Index of the first entry with `tag`, and naming `value` if given.
+    fn find(&self, tag: i64, value: Option<&str>) -> Option<usize> {
+        self.dynamic.iter().position(|d| {
+            d.d_tag == tag && value.is_none_or(|v| self.string(d.d_val) == v.as_bytes())
+        })
+    }
+
+    fn remove(&mut self, tag: i64, value: Option<&str>) -> io::Result<()> {
+        self.check_dynamic()?;
+
+        while let Some(i) = self.find(tag, value) {
+            self.dynamic.remove(i);
+            self.dynamic_changed = true;
+        }
+
+        Ok(())
+    }
+
+    fn set_string(&mut self, tag: i64, value: &str) -> io::Result<()> {
+        self.check_dynamic()?;
+        let d_val = self.add_string(value);
+
+        match self.find(tag, None) {
+            Some(i) => self.dynamic[i].d_val = d_val,
+            // New entries go before the first non-string entry, where the
+            // linker puts them.
+            None => {
+                let pos = self
+                    .dynamic
+                    .iter()
+                    .position(|d| d.d_tag != elf::DT_NEEDED)
+                    .unwrap_or(self.dynamic.len());
+                self.dynamic.insert(pos, Elf64Dyn { d_tag: tag, d_val });
+            }
+        }
+
+        self.dynamic_changed = true;
+        Ok(())
+    }
+
+    // Offset of `s` in .dynstr; any NUL terminated tail of an existing
+    // string will do.
+    fn add_string(&mut self, s: &str) -> u64 {
+        let mut needle = s.as_bytes().to_vec();
+        needle.push(b'\0');
+
+        if let Some(off) = self
+            .dynstr
+            .windows(needle.len())
+            .position(|w| w == needle.as_slice())
+        {
+            return off as u64;
+        }
+
+        let off = self.dynstr.len() as u64;
+        self.dynstr.extend_from_slice(&needle);
+        off
+    }
+
+    /// Apply the edits and return the writer holding the result.
+    pub fn finish(mut self) -> io::Result<ElfWriter> {
+        let loads: Vec<Elf64Phdr> = self
+            .writer
+            .segments
+            .iter()
+            .filter(|p| p.p_type == elf::PT_LOAD)
+            .copied()
+            .collect();
+        let interp_index = self
+            .writer
+            .segments
+            .iter()
+            .position(|p| p.p_type == elf::PT_INTERP);
+        let mut interp = self.interpreter.take().map(|s| {
+            let mut bytes = s.into_bytes();
+            bytes.push(b'\0');
+            bytes
+        });
+
+        let dynstr_moves = self.dynstr.len() > self.dynstr_size;
+        let (dyn_off, dyn_size) = self.dynamic_range.unwrap_or((0, 0));
+        let dynamic_moves = (self.dynamic.len() as u64 + 1) * DYN_SIZE > dyn_size;
+        let dynamic_moves = self.dynamic_changed && dynamic_moves;
+        let interp_moves = match (interp.as_mut(), interp_index) {
+            (Some(bytes), Some(i)) => {
+                let filesz = self.writer.segments[i].p_filesz as usize;
+                if bytes.len() <= filesz {
+                    bytes.resize(filesz, 0);
+                    false
+                } else {
+                    true
+                }
+            }
+            _ => false,
+        };
+
+        if dynstr_moves || dynamic_moves || interp_moves {
+            if loads.is_empty() {
+                return Err(invalid("no loadable segments"));
+            }
+
+            // Append the new segment to the file and map it past every
+            // existing segment, at an address congruent to its offset
+            // modulo the page size. The program headers move into it, which
+            // the dynamic loader finds through PT_PHDR.
+            let page = loads
+                .iter()
+                .map(|p| p.p_align)
+                .max()
+                .unwrap_or(0)
+                .max(0x1000);
+            let vaddr_end = loads
+                .iter()
+                .map(|p| p.p_vaddr.saturating_add(p.p_memsz))
+                .max()
+                .unwrap_or(0);
+            let off = align_up(self.writer.end_offset()?, 8);
+            let vaddr = align_up(vaddr_end, page) + off % page;
+
+            let phentsize = self.writer.header.e_phentsize as u64;
+            let mut region =
+                vec![0; ((self.writer.segments.len() + 1) as u64 * phentsize) as usize];
+            let mut place = |bytes: &[u8], align: u64| {
+                let start = align_up(region.len() as u64, align);
+                region.resize(start as usize, 0);
+                region.extend_from_slice(bytes);
+                start
+            };
+
+            let interp_at = match (interp_moves, interp.as_ref()) {
+                (true, Some(bytes)) => Some(place(bytes, 1)),
+                _ => None,
+            };
+            let dynstr_at = dynstr_moves.then(|| place(&self.dynstr, 1));
+            let dynamic_at = dynamic_moves
+                .then(|| place(&vec![0; (self.dynamic.len() + 1) * DYN_SIZE as usize], 8));
+
+            let mut flags = elf::PF_R as u32;
+            if dynamic_moves {
+                flags |= elf::PF_W as u32;
+            }
+            let load = Elf64Phdr {
+                p_type: elf::PT_LOAD,
+                p_flags: flags,
+                p_offset: off,
+                p_vaddr: vaddr,
+                p_paddr: vaddr,
+                p_filesz: region.len() as u64,
+                p_memsz: region.len() as u64,
+                p_align: page,
+            };
+            let pos = self
+                .writer
+                .segments
+                .iter()
+                .rposition(|p| p.p_type == elf::PT_LOAD)
+                .map_or(0, |i| i + 1);
+            self.writer.segments.insert(pos, load);
+
+            self.writer.header.e_phoff = off;
+            let phdrs_size = self.writer.segments.len() as u64 * phentsize;
+            self.move_segment(elf::PT_PHDR, off, vaddr, phdrs_size);
+
+            if let Some(at) = interp_at {
+                let size = interp.as_ref().map_or(0, |b| b.len() as u64);
+                self.move_segment(elf::PT_INTERP, off + at, vaddr + at, size);
+                self.move_section(".interp", off + at, vaddr + at, size);
+            }
+
+            if let Some(at) = dynstr_at {
+                let name = self.dynstr_section_name();
+                self.move_section(&name, off + at, vaddr + at, self.dynstr.len() as u64);
+                self.set_value(elf::DT_STRTAB, vaddr + at);
+                self.set_value(elf::DT_STRSZ, self.dynstr.len() as u64);
+            }
+
+            if let Some(at) = dynamic_at {
+                let size = (self.dynamic.len() as u64 + 1) * DYN_SIZE;
+                self.move_segment(elf::PT_DYNAMIC, off + at, vaddr + at, size);
+                if let Some(section) = self
+                    .writer
+                    .sections
+                    .iter_mut()
+                    .find(|s| s.header.sh_type == elf::SHT_DYNAMIC)
+                {
+                    section.header.sh_offset = off + at;
+                    section.header.sh_addr = vaddr + at;
+                    section.header.sh_size = size;
+                    section.data = vec![0; size as usize];
+                }
+            }
+
+            self.writer.write_at(off, &region)?;
+            if let Some(at) = dynstr_at {
+                self.writer.write_at(off + at, &self.dynstr.clone())?;
+            }
+            if let (Some(at), Some(bytes)) = (interp_at, interp.as_ref()) {
+                self.writer.write_at(off + at, bytes)?;
+            }
+        }
+
+        if let (false, Some(bytes), Some(i)) = (interp_moves, interp.as_ref(), interp_index) {
+            let p_offset = self.writer.segments[i].p_offset;
+            self.writer.write_at(p_offset, bytes)?;
+        }
+
+        if self.dynamic_changed || dynstr_moves {
+            // Fill the rest of the original table with DT_NULL so that a
+            // shrunk array leaves no stale entries behind.
+            let (off, size) = match self
+                .writer
+                .segments
+                .iter()
+                .find(|p| p.p_type == elf::PT_DYNAMIC)
+            {
+                Some(p) => (p.p_offset, p.p_filesz),
+                None => (dyn_off, dyn_size),
+            };
+            let mut bytes = Vec::with_capacity(size as usize);
+            for dyn_ in self.dynamic.iter() {
+                bytes.extend_from_slice(as_bytes(dyn_));
+            }
+            bytes.resize(size.max(bytes.len() as u64 + DYN_SIZE) as usize, 0);
+
+            self.writer.write_at(off, &bytes)?;
+        }
+
+        Ok(self.writer)
+    }
+
+    pub fn to_bytes(self) -> io::Result<Vec<u8>> {
+        self.finish()?.to_bytes()
+    }
+
+    fn set_value(&mut self, tag: i64, value: u64) {
+        for dyn_ in self.dynamic.iter_mut().filter(|d| d.d_tag == tag) {
+            dyn_.d_val = value;
+        }
+    }
+
+    fn move_segment(&mut self, p_type: u32, off: u64, vaddr: u64, size: u64) {
+        for phdr in self
+            .writer
+            .segments
+            .iter_mut()
+            .filter(|p| p.p_type == p_type)
+        {
+            phdr.p_offset = off;
+            phdr.p_vaddr = vaddr;
+            phdr.p_paddr = vaddr;
+            phdr.p_filesz = size;
+            phdr.p_memsz = size;
+        }
+    }
+
+    // Point the section at its new home. Its contents are filled in by the
+    // following `write_at`.
+    fn move_section(&mut self, name: &str, off: u64, vaddr: u64, size: u64) {
+        if let Some(i) = self.writer.section_index(name) {
+            let section = &mut self.writer.sections[i];
+            section.header.sh_offset = off;
+            section.header.sh_addr = vaddr;
+            section.header.sh_size = size;
+            section.data = vec![0; size as usize];
+        }
+    }
+
+    // The string table linked from the dynamic section, usually .dynstr.
+    fn dynstr_section_name(&self) -> String {
+        self.writer
+            .sections
+            .iter()
+            .find(|s| s.header.sh_type == elf::SHT_DYNAMIC)
+            .and_then(|s| self.writer.sections.get(s.header.sh_link as usize))
+            .map_or_else(|| ".dynstr".to_string(), |s| s.name.clone())
+    }
+}
@@ -0,0 +1,577 @@
+use std::io::{self, Write};
+use std::mem;
+
+use crate::elf::{self, read_struct, str_at};
+
+/// A library ldconfig recorded in ld.so.cache.
+pub struct Entry {
+    /// The FLAG_* bits of the library's type and ABI.
+    pub flags: i32,
+    /// The name libraries are looked up by, usually the soname.
+    pub name: String,
+    pub path: String,
+    /// The lowest kernel version the library needs, 0 for any.
+    pub osversion: u32,
+    /// The legacy hardware capability bits the library needs.
+    pub hwcap: u64,
+    /// The glibc-hwcaps subdirectory the library was found in, such as
+    /// "x86-64-v3".
+    pub hwcap_subdir: Option<String>,
+}
+
+/// The decoded `/etc/ld.so.cache`, the index of libraries in the trusted
+/// directories the dynamic loader searches before the default ones. Both
+/// the libc5 era format and the glibc 2.2 one are read, alone or with the
+/// new one following the old one as ldconfig wrote them for compatibility.
+pub struct LdCache {
+    pub entries: Vec<Entry>,
+    /// The ldconfig that wrote the cache, from the new format's extensions.
+    pub generator: Option<String>,
+}
+
+const CACHE_MAGIC_OLD: &[u8] = b"ld.so-1.7.0";
+const CACHE_MAGIC_NEW: &[u8] = b"glibc-ld.so.cache1.1";
+const CACHE_EXTENSION_MAGIC: u32 = 0xeaa42174;
+
+// struct cache_file of glibc's dl-cache.h.
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct CacheFile {
+    magic: [u8; 11],
+    nlibs: u32,
+}
+
+// struct file_entry.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct FileEntry {
+    flags: i32,
+    key: u32,
+    value: u32,
+}
+
+// struct cache_file_new.
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct CacheFileNew {
+    magic: [u8; 20],
+    nlibs: u32,
+    len_strings: u32,
+    flags: u8,
+    padding: [u8; 3],
+    extension_offset: u32,
+    unused: [u32; 3],
+}
+
+// struct file_entry_new.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct FileEntryNew {
+    flags: i32,
+    key: u32,
+    value: u32,
+    osversion: u32,
+    hwcap: u64,
+}
+
+// struct cache_extension and struct cache_extension_section.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct CacheExtension {
+    magic: u32,
+    count: u32,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct CacheExtensionSection {
+    tag: u32,
+    flags: u32,
+    offset: u32,
+    size: u32,
+}
+
+const CACHE_EXTENSION_TAG_GENERATOR: u32 = 0;
+const CACHE_EXTENSION_TAG_GLIBC_HWCAPS: u32 = 1;
+
+// Set in `hwcap` when its low 32 bits index the glibc-hwcaps
+// subdirectories instead of being capability bits.
+const DL_CACHE_HWCAP_EXTENSION: u64 = 1 << 62;
+
+/* Legal values of the `flags` of an entry.  */
+pub const FLAG_ANY: i32 = -1;
+pub const FLAG_TYPE_MASK: i32 = 0x00ff;
+pub const FLAG_LIBC4: i32 = 0x0000;
+pub const FLAG_ELF: i32 = 0x0001;
+pub const FLAG_ELF_LIBC5: i32 = 0x0002;
+pub const FLAG_ELF_LIBC6: i32 = 0x0003;
+pub const FLAG_REQUIRED_MASK: i32 = 0xff00;
+pub const FLAG_SPARC_LIB64: i32 = 0x0100;
+pub const FLAG_IA64_LIB64: i32 = 0x0200;
+pub const FLAG_X8664_LIB64: i32 = 0x0300;
+pub const FLAG_S390_LIB64: i32 = 0x0400;
+pub const FLAG_POWERPC_LIB64: i32 = 0x0500;
+pub const FLAG_MIPS64_LIBN32: i32 = 0x0600;
+pub const FLAG_MIPS64_LIBN64: i32 = 0x0700;
+pub const FLAG_X8664_LIBX32: i32 = 0x0800;
+pub const FLAG_ARM_LIBHF: i32 = 0x0900;
+pub const FLAG_AARCH64_LIB64: i32 = 0x0a00;
+pub const FLAG_ARM_LIBSF: i32 = 0x0b00;
+pub const FLAG_MIPS_LIB32_NAN2008: i32 = 0x0c00;
+pub const FLAG_MIPS64_LIBN32_NAN2008: i32 = 0x0d00;
+pub const FLAG_MIPS64_LIBN64_NAN2008: i32 = 0x0e00;
+pub const FLAG_RISCV_FLOAT_ABI_SOFT: i32 = 0x0f00;
+pub const FLAG_RISCV_FLOAT_ABI_DOUBLE: i32 = 0x1000;
+pub const FLAG_LARCH_FLOAT_ABI_SOFT: i32 = 0x1100;
+pub const FLAG_LARCH_FLOAT_ABI_DOUBLE: i32 = 0x1200;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// The new format follows the old one at the next 8-byte boundary.
+fn align_cache(off: usize) -> usize {
+    (off + 7) & !7
+}
+
+impl LdCache {
+    pub fn from_bytes(data: &[u8]) -> io::Result<LdCache> {
+        if data.starts_with(CACHE_MAGIC_NEW) {
+            return LdCache::from_new(data);
+        }
+        if !data.starts_with(CACHE_MAGIC_OLD) {
+            return Err(invalid("not an ld.so.cache file"));
+        }
+
+        let header: CacheFile = read_struct(data, 0)?;
+        let entries_off = mem::size_of::<CacheFile>();
+        let strings_off = entries_off + header.nlibs as usize * mem::size_of::<FileEntry>();
+
+        // A new format cache after the old one supersedes it.
+        if let Some(new) = data.get(align_cache(strings_off)..) {
+            if new.starts_with(CACHE_MAGIC_NEW) {
+                return LdCache::from_new(new);
+            }
+        }
+
+        // The strings of the old format follow the entries.
+        let strings = data.get(strings_off..).unwrap_or(&[]);
+        let entries = (0..header.nlibs as usize)
+            .map(|i| {
+                let entry: FileEntry =
+                    read_struct(data, entries_off + i * mem::size_of::<FileEntry>())?;
+                Ok(Entry {
+                    flags: entry.flags,
+                    name: str_at(strings, entry.key as usize),
+                    path: str_at(strings, entry.value as usize),
+                    osversion: 0,
+                    hwcap: 0,
+                    hwcap_subdir: None,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(LdCache {
+            entries,
+            generator: None,
+        })
+    }
+
+    // The strings of the new format are addressed from its header.
+    fn from_new(data: &[u8]) -> io::Result<LdCache> {
+        let header: CacheFileNew = read_struct(data, 0)?;
+        // 0 is an old ldconfig that did not record it, 2 little endian.
+        if header.flags != 0 && header.flags != 2 {
+            return Err(invalid("ld.so.cache is not little endian"));
+        }
+
+        let mut cache = LdCache {
+            entries: Vec::new(),
+            generator: None,
+        };
+        let hwcap_subdirs = cache.read_extensions(data, header.extension_offset as usize);
+
+        let base = mem::size_of::<CacheFileNew>();
+        for i in 0..header.nlibs as usize {
+            let entry: FileEntryNew = read_struct(data, base + i * mem::size_of::<FileEntryNew>())?;
+            let subdir = match entry.hwcap & DL_CACHE_HWCAP_EXTENSION {
+                0 => None,
+                _ => hwcap_subdirs.get(entry.hwcap as u32 as usize).cloned(),
+            };
+
+            cache.entries.push(Entry {
+                flags: entry.flags,
+                name: str_at(data, entry.key as usize),
+                path: str_at(data, entry.value as usize),
+                osversion: entry.osversion,
+                hwcap: if subdir.is_some() { 0 } else { entry.hwcap },
+                hwcap_subdir: subdir,
+            });
+        }
+
+        Ok(cache)
+    }
+
+    // Read the generator string and return the glibc-hwcaps subdirectory
+    // names, which entries refer to by index. Extensions are optional, so a
+    // damaged one is ignored.
+    fn read_extensions(&mut self, data: &[u8], off: usize) -> Vec<String> {
+        let mut subdirs = Vec::new();
+
+        let ext = match read_struct::<CacheExtension>(data, off) {
+            Ok(ext) if off != 0 && ext.magic == CACHE_EXTENSION_MAGIC => ext,
+            _ => return subdirs,
+        };
+
+        for i in 0..ext.count as usize {
+            let at = off
+                + mem::size_of::<CacheExtension>()
+                + i * mem::size_of::<CacheExtensionSection>();
+            let section: CacheExtensionSection = match read_struct(data, at) {
+                Ok(section) => section,
+                Err(_) => break,
+            };
+            let start = section.offset as usize;
+            let bytes = match start
+                .checked_add(section.size as usize)
+                .and_then(|end| data.get(start..end))
+            {
+                Some(bytes) => bytes,
+                None => continue,
+            };
+
+            match section.tag {
+                CACHE_EXTENSION_TAG_GENERATOR => {
+                    self.generator = Some(String::from_utf8_lossy(bytes).into_owned());
+                }
+                CACHE_EXTENSION_TAG_GLIBC_HWCAPS => {
+                    subdirs = bytes
+                        .chunks_exact(4)
+                        .map(|off| {
+                            let off = u32::from_le_bytes([off[0], off[1], off[2], off[3]]);
+                            str_at(data, off as usize)
+                        })
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+
+        subdirs
+    }
+
+    /// The paths recorded for library `name` built for `machine`. Entries
+    /// for a glibc-hwcaps subdirectory come last: which of them the loader
+    /// would pick depends on the CPU it runs on, while the baseline ones
+    /// work on all.
+    pub fn lookup(&self, name: &str, machine: u16) -> Vec<&str> {
+        let required = match machine {
+            elf::EM_X86_64 => FLAG_X8664_LIB64,
+            elf::EM_AARCH64 => FLAG_AARCH64_LIB64,
+            _ => 0,
+        };
+
+        let mut entries: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.name == name)
+            .filter(|entry| entry.flags & FLAG_TYPE_MASK == FLAG_ELF_LIBC6)
+            .filter(|entry| entry.flags & FLAG_REQUIRED_MASK == required)
+            .collect();
+        entries.sort_by_key(|entry| entry.hwcap_subdir.is_some());
+
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    /// Print the entries the way `ldconfig -p` does.
+    pub fn to_str(&self, path: &str, buf: &mut dyn Write) -> io::Result<()> {
+        buf.write_fmt(format_args!(
+            "{} libs found in cache `{}'\n",
+            self.entries.len(),
+            path
+        ))?;
+
+        for entry in self.entries.iter() {
+            buf.write_fmt(format_args!(
+                "\t{} ({}{}",
+                entry.name,
+                flag_type(entry.flags),
+                flag_required(entry.flags)
+            ))?;
+
+            if let Some(subdir) = entry.hwcap_subdir.as_ref() {
+                buf.write_fmt(format_args!(", hwcap: \"{}\"", subdir))?;
+            } else if entry.hwcap != 0 {
+                buf.write_fmt(format_args!(", hwcap: {:#018x}", entry.hwcap))?;
+            }
+            if entry.osversion != 0 {
+                const ABI: [&str; 6] =
+                    ["Linux", "Hurd", "Solaris", "FreeBSD", "kNetBSD", "Syllable"];
+                buf.write_fmt(format_args!(
+                    ", OS ABI: {} {}.{}.{}",
+                    ABI.get((entry.osversion >> 24) as usize)
+                        .unwrap_or(&"Unknown OS"),
+                    (entry.osversion >> 16) & 0xff,
+                    (entry.osversion >> 8) & 0xff,
+                    entry.osversion & 0xff
+                ))?;
+            }
+
+            buf.write_fmt(format_args!(") => {}\n", entry.path))?;
+        }
+
+        if let Some(generator) = self.generator.as_ref() {
+            buf.write_fmt(format_args!("Cache generated by: {}\n", generator))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn flag_type(flags: i32) -> &'static str {
+    match flags & FLAG_TYPE_MASK {
+        FLAG_LIBC4 => "libc4",
+        FLAG_ELF => "ELF",
+        FLAG_ELF_LIBC5 => "libc5",
+        FLAG_ELF_LIBC6 => "libc6",
+        _ => "unknown",
+    }
+}
+
+fn flag_required(flags: i32) -> String {
+    let name = match flags & FLAG_REQUIRED_MASK {
+        0 => "",
+        FLAG_SPARC_LIB64 | FLAG_S390_LIB64 | FLAG_POWERPC_LIB64 | FLAG_MIPS64_LIBN64 => ",64bit",
+        FLAG_IA64_LIB64 => ",IA-64",
+        FLAG_X8664_LIB64 => ",x86-64",
+        FLAG_MIPS64_LIBN32 => ",N32",
+        FLAG_X8664_LIBX32 => ",x32",
+        FLAG_ARM_LIBHF => ",hard-float",
+        FLAG_AARCH64_LIB64 => ",AArch64",
+        FLAG_ARM_LIBSF | FLAG_RISCV_FLOAT_ABI_SOFT | FLAG_LARCH_FLOAT_ABI_SOFT => ",soft-float",
+        FLAG_MIPS_LIB32_NAN2008 => ",nan2008",
+        FLAG_MIPS64_LIBN32_NAN2008 => ",N32,nan2008",
+        FLAG_MIPS64_LIBN64_NAN2008 => ",64bit,nan2008",
+        FLAG_RISCV_FLOAT_ABI_DOUBLE | FLAG_LARCH_FLOAT_ABI_DOUBLE => ",double-float",
+        other => return format!(",{}", other),
+    };
+
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::as_bytes;
+
+    const X8664: i32 = FLAG_ELF_LIBC6 | FLAG_X8664_LIB64;
+
+    // Append `s` to the string table, returning its offset in the image
+    // given the table starts at `base`.
+    fn push_str(strings: &mut Vec<u8>, base: usize, s: &str) -> u32 {
+        let off = base + strings.len();
+        strings.extend_from_slice(s.as_bytes());
+        strings.push(0);
+        off as u32
+    }
+
+    // An old format cache of (flags, name, path) entries.
+    fn old_image(libs: &[(i32, &str, &str)]) -> Vec<u8> {
+        let mut magic = [0; 11];
+        magic.copy_from_slice(CACHE_MAGIC_OLD);
+        let mut data = as_bytes(&CacheFile {
+            magic,
+            nlibs: libs.len() as u32,
+        })
+        .to_vec();
+
+        // Old format strings are addressed from the end of the entries.
+        let mut strings = Vec::new();
+        for &(flags, name, path) in libs {
+            let entry = FileEntry {
+                flags,
+                key: push_str(&mut strings, 0, name),
+                value: push_str(&mut strings, 0, path),
+            };
+            data.extend_from_slice(as_bytes(&entry));
+        }
+        data.extend_from_slice(&strings);
+        data
+    }
+
+    // A new format cache of (flags, name, path, hwcap) entries, with
+    // glibc-hwcaps subdirectories and a generator in its extensions.
+    fn new_image(libs: &[(i32, &str, &str, u64)], subdirs: &[&str]) -> Vec<u8> {
+        let base = mem::size_of::<CacheFileNew>() + libs.len() * mem::size_of::<FileEntryNew>();
+        let mut strings = Vec::new();
+        let mut entries = Vec::new();
+        for &(flags, name, path, hwcap) in libs {
+            let entry = FileEntryNew {
+                flags,
+                key: push_str(&mut strings, base, name),
+                value: push_str(&mut strings, base, path),
+                osversion: 0,
+                hwcap,
+            };
+            entries.extend_from_slice(as_bytes(&entry));
+        }
+        let subdir_offs: Vec<u32> = subdirs
+            .iter()
+            .map(|subdir| push_str(&mut strings, base, subdir))
+            .collect();
+        let generator = b"ldconfig (test)";
+
+        // The extension directory, then the hwcaps index and generator.
+        let ext_off = (base + strings.len() + 3) & !3;
+        let payload_off = ext_off
+            + mem::size_of::<CacheExtension>()
+            + 2 * mem::size_of::<CacheExtensionSection>();
+        let index: Vec<u8> = subdir_offs
+            .iter()
+            .flat_map(|off| off.to_le_bytes())
+            .collect();
+
+        let mut magic = [0; 20];
+        magic.copy_from_slice(CACHE_MAGIC_NEW);
+        let mut data = as_bytes(&CacheFileNew {
+            magic,
+            nlibs: libs.len() as u32,
+            len_strings: strings.len() as u32,
+            flags: 2,
+            padding: [0; 3],
+            extension_offset: ext_off as u32,
+            unused: [0; 3],
+        })
+        .to_vec();
+        data.extend_from_slice(&entries);
+        data.extend_from_slice(&strings);
+        data.resize(ext_off, 0);
+        data.extend_from_slice(as_bytes(&CacheExtension {
+            magic: CACHE_EXTENSION_MAGIC,
+            count: 2,
+        }));
+        data.extend_from_slice(as_bytes(&CacheExtensionSection {
+            tag: CACHE_EXTENSION_TAG_GLIBC_HWCAPS,
+            flags: 0,
+            offset: payload_off as u32,
+            size: index.len() as u32,
+        }));
+        data.extend_from_slice(as_bytes(&CacheExtensionSection {
+            tag: CACHE_EXTENSION_TAG_GENERATOR,
+            flags: 0,
+            offset: (payload_off + index.len()) as u32,
+            size: generator.len() as u32,
+        }));
+        data.extend_from_slice(&index);
+        data.extend_from_slice(generator);
+        data
+    }
+
+    fn names(cache: &LdCache) -> Vec<(&str, &str)> {
+        cache
+            .entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_old_format() {
+        let data = old_image(&[
+            (X8664, "libfoo.so.1", "/lib/libfoo.so.1"),
+            (FLAG_ELF_LIBC5, "libbar.so.2", "/lib/libbar.so.2"),
+        ]);
+        let cache = LdCache::from_bytes(&data).unwrap();
+
+        assert_eq!(
+            names(&cache),
+            [
+                ("libfoo.so.1", "/lib/libfoo.so.1"),
+                ("libbar.so.2", "/lib/libbar.so.2")
+            ]
+        );
+        assert_eq!(cache.entries[1].flags, FLAG_ELF_LIBC5);
+        assert_eq!(cache.generator, None);
+        assert_eq!(
+            cache.lookup("libfoo.so.1", elf::EM_X86_64),
+            ["/lib/libfoo.so.1"]
+        );
+    }
+
+    #[test]
+    fn reads_new_format() {
+        let data = new_image(&[(X8664, "libfoo.so.1", "/usr/lib/libfoo.so.1", 0)], &[]);
+        let cache = LdCache::from_bytes(&data).unwrap();
+
+        assert_eq!(names(&cache), [("libfoo.so.1", "/usr/lib/libfoo.so.1")]);
+        assert_eq!(cache.entries[0].hwcap_subdir, None);
+        assert_eq!(cache.generator.as_deref(), Some("ldconfig (test)"));
+    }
+
+    #[test]
+    fn prefers_new_format_after_old() {
+        // As ldconfig writes them, the entries of both formats share the
+        // strings at the end of the file, which old format entries address
+        // from the end of their own.
+        let new = new_image(&[(X8664, "libfoo.so.1", "/lib/libfoo.so.1", 0)], &[]);
+        let entries_end = mem::size_of::<CacheFile>() + mem::size_of::<FileEntry>();
+        let to_new = (align_cache(entries_end) - entries_end) as u32;
+        let entry: FileEntryNew = read_struct(&new, mem::size_of::<CacheFileNew>()).unwrap();
+
+        let mut magic = [0; 11];
+        magic.copy_from_slice(CACHE_MAGIC_OLD);
+        let mut data = as_bytes(&CacheFile { magic, nlibs: 1 }).to_vec();
+        data.extend_from_slice(as_bytes(&FileEntry {
+            flags: X8664,
+            key: to_new + entry.key,
+            value: to_new + entry.value,
+        }));
+        data.resize(align_cache(data.len()), 0);
+        data.extend_from_slice(&new);
+        let cache = LdCache::from_bytes(&data).unwrap();
+
+        assert_eq!(names(&cache), [("libfoo.so.1", "/lib/libfoo.so.1")]);
+        assert_eq!(cache.generator.as_deref(), Some("ldconfig (test)"));
+    }
+
+    #[test]
+    fn reads_hwcaps_subdirectories() {
+        let data = new_image(
+            &[
+                (
+                    X8664,
+                    "libfoo.so.1",
+                    "/lib/glibc-hwcaps/x86-64-v3/libfoo.so.1",
+                    DL_CACHE_HWCAP_EXTENSION | 1,
+                ),
+                (X8664, "libfoo.so.1", "/lib/libfoo.so.1", 0),
+            ],
+            &["x86-64-v2", "x86-64-v3"],
+        );
+        let cache = LdCache::from_bytes(&data).unwrap();
+
+        assert_eq!(cache.entries[0].hwcap_subdir.as_deref(), Some("x86-64-v3"));
+        assert_eq!(cache.entries[0].hwcap, 0);
+        assert_eq!(
+            cache.lookup("libfoo.so.1", elf::EM_X86_64),
+            [
+                "/lib/libfoo.so.1",
+                "/lib/glibc-hwcaps/x86-64-v3/libfoo.so.1"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_cache() {
+        let old = old_image(&[(X8664, "libfoo.so.1", "/lib/libfoo.so.1")]);
+        let new = new_image(&[(X8664, "libfoo.so.1", "/lib/libfoo.so.1", 0)], &[]);
+
+        // Cut into the entries, and into the header.
+        for data in [&old[..20], &new[..60], &old[..12], &new[..30]] {
+            assert!(LdCache::from_bytes(data).is_err());
+        }
+    }
+}
@@ -1,139 +1,624 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod abi;
+pub mod archive;
+pub mod backtrace;
+pub mod batch;
+pub mod bind;
+pub mod convert;
+pub mod coredump;
+pub mod demangle;
+pub mod deps;
+pub mod diff;
+pub mod edit;
+pub mod ldcache;
+pub mod lint;
+pub mod memory;
+pub mod nm;
+pub mod procfs;
+pub mod size;
+pub mod strip;
+pub mod symver;
+#[cfg(test)]
+mod testutil;
+pub mod writer;
+
 #[allow(dead_code)]
 pub mod elf {
     use core::fmt::{self, Display};
     use std::collections::HashMap;
     use std::fs::{self, File};
     use std::io::{self, prelude::*};
+    use std::sync::OnceLock;
+    use std::{mem, ptr};
 
+    #[derive(Clone, Copy, Default)]
     pub struct Options {
         pub file_header: bool,
         pub program_headers: bool,
         pub section_headers: bool,
+        pub symbols: bool,
+        pub dynamic: bool,
+        /// Show symbol names demangled.
+        pub demangle: bool,
     }
 
     pub struct Elf {
-        header: Option<Elf64Ehdr>,
-        program_headers: Option<Vec<Elf64Phdr>>,
-        section_headers: Option<Vec<Elf64Shdr>>,
+        options: Options,
+        data: Vec<u8>,
+        header: Elf64Ehdr,
+        // e_shstrndx, or the value from section header 0 for SHN_XINDEX.
+        shstrndx: usize,
+        program_headers: Vec<Elf64Phdr>,
+        section_headers: Vec<Elf64Shdr>,
+        shstrtab: Vec<u8>,
+        // Symbols are only decoded on first use, so that scanning large
+        // trees for header facts stays cheap.
+        symbol_tables: OnceLock<Vec<SymbolTable>>,
+        // MiniDebugInfo: an xz-compressed ELF carried in .gnu_debugdata
+        // whose .symtab complements .dynsym on stripped distro binaries.
+        debugdata: OnceLock<Option<Box<Elf>>>,
+        // For core files, the NT_FILE mappings with the contents of the
+        // files still found on disk, loaded on the first read of memory the
+        // core did not save.
+        pub(crate) mappings: OnceLock<Vec<crate::memory::Mapping>>,
     }
 
-    static mut SH_INDEX: u16 = 0;
-    static mut SH_STRTABLE: Vec<u8> = Vec::new();
+    pub(crate) fn str_at(table: &[u8], off: usize) -> String {
+        if off >= table.len() {
+            return String::new();
+        }
 
-    // TODO: is there a better way to do this?
-    fn read_str(off: usize) -> String {
-        unsafe {
-            if off >= SH_STRTABLE.len() {
-                return String::new();
-            }
+        let end = table[off..]
+            .iter()
+            .position(|&c| c == b'\0')
+            .map_or(table.len(), |n| off + n);
 
-            let mut end = off;
-            for i in off..SH_STRTABLE.len() {
-                if SH_STRTABLE[i] == b'\0' {
-                    end = i;
-                    break;
-                }
-            }
+        String::from_utf8_lossy(&table[off..end]).into_owned()
+    }
+
+    pub fn is_elf(data: &[u8]) -> bool {
+        data.starts_with(ELFMAG)
+    }
 
-            String::from_utf8(SH_STRTABLE[off..end].to_vec()).unwrap()
+    // Only used with the #[repr(C)] on-disk structures below, which are
+    // valid for any bit pattern.
+    pub(crate) fn read_struct<T: Copy>(data: &[u8], off: usize) -> io::Result<T> {
+        match off
+            .checked_add(mem::size_of::<T>())
+            .and_then(|end| data.get(off..end))
+        {
+            Some(buf) => Ok(unsafe { ptr::read_unaligned(buf.as_ptr() as *const T) }),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "structure extends past end of file",
+            )),
         }
     }
 
     impl Elf {
         pub fn new(path: &str, options: Options) -> Elf {
-            let mut f = File::open(path).unwrap();
-            let ehdr = Elf64Ehdr::from_file(&mut f).unwrap();
+            let data = fs::read(path).unwrap();
 
-            let mut header = None;
-            let mut program_headers = None;
-            let mut section_headers = None;
+            Elf::from_bytes(data, options).unwrap()
+        }
+
+        pub fn from_bytes(data: Vec<u8>, options: Options) -> io::Result<Elf> {
+            let ehdr = Elf64Ehdr::from_bytes(&data)?;
+
+            // Section header 0 carries the real counts of files using
+            // extended numbering, so it is read before anything else.
+            let shdr0 = if ehdr.e_shoff != 0 {
+                Some(
+                    Elf64Shdr::from_bytes(&data, ehdr.e_shoff as usize).map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "section header table extends past end of file",
+                        )
+                    })?,
+                )
+            } else {
+                None
+            };
+
+            let phnum = match shdr0 {
+                Some(shdr0) if ehdr.e_phnum == PN_XNUM => shdr0.sh_info as usize,
+                _ => ehdr.e_phnum as usize,
+            };
+            let shnum = match shdr0 {
+                Some(shdr0) if ehdr.e_shnum == 0 => shdr0.sh_size as usize,
+                _ => ehdr.e_shnum as usize,
+            };
+            let shstrndx = match shdr0 {
+                Some(shdr0) if ehdr.e_shstrndx == SHN_XINDEX => shdr0.sh_link as usize,
+                _ => ehdr.e_shstrndx as usize,
+            };
+
+            // Smaller entries would make consecutive headers overlap; larger
+            // ones are tolerated and only reported by validate().
+            if phnum > 0 && (ehdr.e_phentsize as usize) < mem::size_of::<Elf64Phdr>() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "program header entry size too small",
+                ));
+            }
+            if shnum > 0 && (ehdr.e_shensize as usize) < mem::size_of::<Elf64Shdr>() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "section header entry size too small",
+                ));
+            }
+
+            let mut phdrs: Vec<Elf64Phdr> = Vec::new();
+            let mut poff = ehdr.e_phoff as usize;
+
+            for _i in 0..phnum {
+                phdrs.push(Elf64Phdr::from_bytes(&data, poff).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "program header table extends past end of file",
+                    )
+                })?);
+                poff = poff.saturating_add(ehdr.e_phentsize as usize);
+            }
 
-            if options.file_header {
-                header = Some(ehdr);
+            let mut shdrs: Vec<Elf64Shdr> = Vec::new();
+            let mut soff = ehdr.e_shoff as usize;
+
+            for _i in 0..shnum {
+                shdrs.push(Elf64Shdr::from_bytes(&data, soff).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "section header table extends past end of file",
+                    )
+                })?);
+                soff = soff.saturating_add(ehdr.e_shensize as usize);
             }
 
-            if options.program_headers {
-                let mut phdrs: Vec<Elf64Phdr> = Vec::new();
-                let mut poff = ehdr.e_phoff;
+            let shstrtab = match shdrs.get(shstrndx) {
+                Some(shstr_ent) => section_bytes(&data, shstr_ent).to_vec(),
+                None => Vec::new(),
+            };
+
+            Ok(Elf {
+                options,
+                data,
+                header: ehdr,
+                shstrndx,
+                program_headers: phdrs,
+                section_headers: shdrs,
+                shstrtab,
+                symbol_tables: OnceLock::new(),
+                debugdata: OnceLock::new(),
+                mappings: OnceLock::new(),
+            })
+        }
+
+        pub fn header(&self) -> &Elf64Ehdr {
+            &self.header
+        }
+
+        /// The section header string table index, resolving SHN_XINDEX.
+        pub fn shstrndx(&self) -> usize {
+            self.shstrndx
+        }
+
+        pub fn data(&self) -> &[u8] {
+            &self.data
+        }
+
+        pub fn e_type(&self) -> u16 {
+            self.header.e_type
+        }
+
+        pub fn machine(&self) -> u16 {
+            self.header.e_machine
+        }
+
+        pub fn program_headers(&self) -> &[Elf64Phdr] {
+            &self.program_headers
+        }
+
+        pub fn section_headers(&self) -> &[Elf64Shdr] {
+            &self.section_headers
+        }
 
-                for _i in 0..ehdr.e_phnum {
-                    phdrs.push(Elf64Phdr::from_file(&mut f, poff).unwrap());
-                    poff = poff + ehdr.e_phentsize as u64;
+        /// The program interpreter requested by PT_INTERP, if any.
+        pub fn interpreter(&self) -> Option<String> {
+            let phdr = self
+                .program_headers
+                .iter()
+                .find(|phdr| phdr.p_type == PT_INTERP)?;
+            let start = phdr.p_offset as usize;
+            let bytes = start
+                .checked_add(phdr.p_filesz as usize)
+                .and_then(|end| self.data.get(start..end))?;
+
+            Some(str_at(bytes, 0))
+        }
 
-                    // TODO: why can't this work?
-                    // let poff = poff + ehdr.e_phentsize as u64;
+        /// A position independent executable is an ET_DYN object that asks
+        /// for a program interpreter.
+        pub fn is_pie(&self) -> bool {
+            self.header.e_type == ET_DYN && self.interpreter().is_some()
+        }
+
+        pub fn is_stripped(&self) -> bool {
+            !self
+                .section_headers
+                .iter()
+                .any(|shdr| shdr.sh_type == SHT_SYMTAB)
+        }
+
+        pub fn section_name(&self, shdr: &Elf64Shdr) -> String {
+            str_at(&self.shstrtab, shdr.sh_name as usize)
+        }
+
+        pub fn section_by_name(&self, name: &str) -> Option<&Elf64Shdr> {
+            self.section_headers
+                .iter()
+                .find(|shdr| self.section_name(shdr) == name)
+        }
+
+        pub fn section_data(&self, shdr: &Elf64Shdr) -> &[u8] {
+            section_bytes(&self.data, shdr)
+        }
+
+        pub fn symbol_tables(&self) -> &[SymbolTable] {
+            self.symbol_tables.get_or_init(|| self.read_symbol_tables())
+        }
+
+        /// The ELF embedded in .gnu_debugdata, if present and well formed.
+        pub fn debugdata(&self) -> Option<&Elf> {
+            self.debugdata
+                .get_or_init(|| self.read_debugdata().map(Box::new))
+                .as_deref()
+        }
+
+        fn read_symbol_tables(&self) -> Vec<SymbolTable> {
+            let mut tables = Vec::new();
+
+            for (index, shdr) in self.section_headers.iter().enumerate() {
+                if shdr.sh_type != SHT_SYMTAB && shdr.sh_type != SHT_DYNSYM {
+                    continue;
                 }
 
-                program_headers = Some(phdrs);
+                let strtab = match self.section_headers.get(shdr.sh_link as usize) {
+                    Some(link) => self.section_data(link),
+                    None => &[],
+                };
+
+                // Section indices that don't fit in st_shndx live in a
+                // SHT_SYMTAB_SHNDX section linked to this table.
+                let xindex = self
+                    .section_headers
+                    .iter()
+                    .find(|x| x.sh_type == SHT_SYMTAB_SHNDX && x.sh_link as usize == index)
+                    .map_or(&[][..], |x| self.section_data(x));
+
+                let symbols = self
+                    .section_data(shdr)
+                    .chunks_exact(mem::size_of::<Elf64Sym>())
+                    .filter_map(|chunk| read_struct::<Elf64Sym>(chunk, 0).ok())
+                    .enumerate()
+                    .map(|(i, sym)| Symbol {
+                        name: str_at(strtab, sym.st_name as usize),
+                        shndx: if sym.st_shndx == SHN_XINDEX {
+                            read_struct::<u32>(xindex, i * 4).unwrap_or(0)
+                        } else {
+                            sym.st_shndx as u32
+                        },
+                        sym,
+                    })
+                    .collect();
+
+                tables.push(SymbolTable {
+                    name: self.section_name(shdr),
+                    symbols,
+                });
             }
 
-            if options.section_headers {
-                let mut shdrs: Vec<Elf64Shdr> = Vec::new();
-                let mut soff = ehdr.e_shoff;
+            tables
+        }
 
-                for _i in 0..ehdr.e_shnum {
-                    shdrs.push(Elf64Shdr::from_file(&mut f, soff).unwrap());
-                    soff = soff + ehdr.e_shensize as u64;
-                }
+        fn read_debugdata(&self) -> Option<Elf> {
+            let shdr = self.section_by_name(".gnu_debugdata")?;
+            let mut compressed = self.section_data(shdr);
+            let mut decompressed = Vec::new();
 
-                unsafe {
-                    let shstr_ent = shdrs[ehdr.e_shstrndx as usize];
-                    let mut buf = vec![0; shstr_ent.sh_size as usize];
+            lzma_rs::xz_decompress(&mut compressed, &mut decompressed).ok()?;
 
-                    f.seek(io::SeekFrom::Start(shstr_ent.sh_offset)).unwrap();
-                    f.read_exact(&mut buf).unwrap();
+            Elf::from_bytes(decompressed, Options::default()).ok()
+        }
+
+        pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+            let minidebuginfo = self
+                .debugdata()
+                .into_iter()
+                .flat_map(|elf| elf.symbol_tables().iter());
+
+            self.symbol_tables()
+                .iter()
+                .chain(minidebuginfo)
+                .flat_map(|table| table.symbols.iter())
+        }
+
+        /// Map a virtual address to its file offset through the PT_LOAD
+        /// segments.
+        pub fn vaddr_to_offset(&self, addr: u64) -> Option<u64> {
+            self.program_headers
+                .iter()
+                .filter(|phdr| phdr.p_type == PT_LOAD)
+                .find(|phdr| phdr.p_vaddr <= addr && addr - phdr.p_vaddr < phdr.p_filesz)
+                .map(|phdr| phdr.p_offset + (addr - phdr.p_vaddr))
+        }
+
+        /// File offset and size of the dynamic section, found through
+        /// PT_DYNAMIC or, for objects without program headers, SHT_DYNAMIC.
+        pub fn dynamic_range(&self) -> Option<(u64, u64)> {
+            if let Some(phdr) = self
+                .program_headers
+                .iter()
+                .find(|phdr| phdr.p_type == PT_DYNAMIC)
+            {
+                return Some((phdr.p_offset, phdr.p_filesz));
+            }
 
-                    SH_STRTABLE = buf;
+            self.section_headers
+                .iter()
+                .find(|shdr| shdr.sh_type == SHT_DYNAMIC)
+                .map(|shdr| (shdr.sh_offset, shdr.sh_size))
+        }
+
+        /// The dynamic entries up to, not including, DT_NULL.
+        pub fn dynamic(&self) -> Vec<Elf64Dyn> {
+            let bytes = match self.dynamic_range() {
+                Some((off, size)) => (off as usize)
+                    .checked_add(size as usize)
+                    .and_then(|end| self.data.get(off as usize..end))
+                    .unwrap_or(&[]),
+                None => &[],
+            };
+
+            bytes
+                .chunks_exact(mem::size_of::<Elf64Dyn>())
+                .filter_map(|chunk| read_struct::<Elf64Dyn>(chunk, 0).ok())
+                .take_while(|dyn_| dyn_.d_tag != DT_NULL)
+                .collect()
+        }
+
+        /// The value of the first dynamic entry tagged `tag`.
+        pub fn dynamic_value(&self, tag: i64) -> Option<u64> {
+            self.dynamic()
+                .iter()
+                .find(|dyn_| dyn_.d_tag == tag)
+                .map(|dyn_| dyn_.d_val)
+        }
+
+        /// The dynamic string table, located through DT_STRTAB/DT_STRSZ.
+        pub fn dynstr(&self) -> &[u8] {
+            let range = self
+                .dynamic_value(DT_STRTAB)
+                .and_then(|addr| self.vaddr_to_offset(addr))
+                .zip(self.dynamic_value(DT_STRSZ));
+
+            if let Some((off, size)) = range {
+                if let Some(bytes) = (off as usize)
+                    .checked_add(size as usize)
+                    .and_then(|end| self.data.get(off as usize..end))
+                {
+                    return bytes;
                 }
+            }
 
-                section_headers = Some(shdrs);
+            // Objects without program headers only have the section.
+            self.section_headers
+                .iter()
+                .find(|shdr| shdr.sh_type == SHT_DYNAMIC)
+                .and_then(|shdr| self.section_headers.get(shdr.sh_link as usize))
+                .map_or(&[], |shdr| self.section_data(shdr))
+        }
+
+        pub fn dynamic_str(&self, off: u64) -> String {
+            str_at(self.dynstr(), off as usize)
+        }
+
+        fn dynamic_strs(&self, tag: i64) -> Vec<String> {
+            self.dynamic()
+                .iter()
+                .filter(|dyn_| dyn_.d_tag == tag)
+                .map(|dyn_| self.dynamic_str(dyn_.d_val))
+                .collect()
+        }
+
+        pub fn needed(&self) -> Vec<String> {
+            self.dynamic_strs(DT_NEEDED)
+        }
+
+        pub fn soname(&self) -> Option<String> {
+            self.dynamic_strs(DT_SONAME).into_iter().next()
+        }
+
+        pub fn rpath(&self) -> Option<String> {
+            self.dynamic_strs(DT_RPATH).into_iter().next()
+        }
+
+        pub fn runpath(&self) -> Option<String> {
+            self.dynamic_strs(DT_RUNPATH).into_iter().next()
+        }
+
+        /// The notes of the PT_NOTE segments or, for objects without program
+        /// headers, of the SHT_NOTE sections.
+        pub fn notes(&self) -> Vec<Note> {
+            let mut ranges: Vec<(u64, u64, u64)> = self
+                .program_headers
+                .iter()
+                .filter(|phdr| phdr.p_type == PT_NOTE)
+                .map(|phdr| (phdr.p_offset, phdr.p_filesz, phdr.p_align))
+                .collect();
+
+            if self.program_headers.is_empty() {
+                ranges = self
+                    .section_headers
+                    .iter()
+                    .filter(|shdr| shdr.sh_type == SHT_NOTE)
+                    .map(|shdr| (shdr.sh_offset, shdr.sh_size, shdr.sh_addralign))
+                    .collect();
             }
 
-            Elf {
-                header,
-                program_headers,
-                section_headers,
+            let mut notes = Vec::new();
+            for (off, size, align) in ranges {
+                let bytes = (off as usize)
+                    .checked_add(size as usize)
+                    .and_then(|end| self.data.get(off as usize..end))
+                    .unwrap_or(&[]);
+                read_notes(bytes, align, &mut notes);
             }
+
+            notes
+        }
+
+        /// The GNU build ID, as the lowercase hex string debuggers use.
+        pub fn build_id(&self) -> Option<String> {
+            self.notes()
+                .into_iter()
+                .find(|note| note.name == "GNU" && note.n_type == NT_GNU_BUILD_ID)
+                .map(|note| note.desc.iter().map(|b| format!("{:02x}", b)).collect())
+        }
+
+        /// Find the function or object symbol covering `addr`, searching
+        /// .symtab and .dynsym first and then the MiniDebugInfo symbols.
+        pub fn lookup_symbol(&self, addr: u64) -> Option<&Symbol> {
+            self.symbols().find(|sym| sym.contains(addr))
         }
 
         pub fn to_str(&mut self, buf: &mut dyn Write) -> io::Result<()> {
-            // Elf64Ehdr implements Copy trait, so no ownership moved here.
-            if let Some(ehdr) = self.header {
+            if self.options.file_header {
                 buf.write_fmt(format_args!("ELF Header:\n"))?;
-                buf.write_fmt(format_args!("{}", ehdr))?;
+                buf.write_fmt(format_args!(
+                    "{}",
+                    ExtendedEhdr {
+                        ehdr: &self.header,
+                        phnum: self.program_headers.len(),
+                        shnum: self.section_headers.len(),
+                        shstrndx: self.shstrndx,
+                    }
+                ))?;
             }
 
-            // Vec<> doesn't implement Copy trait, so if we don't add as_deref,
-            // data move happens.
-            if let Some(phdrs) = self.program_headers.as_deref() {
+            if self.options.program_headers {
                 buf.write_fmt(format_args!("Program Headers:\n"))?;
                 buf.write_fmt(format_args!("{}", Elf::phdr_header()))?;
 
-                for phdr in phdrs {
+                for phdr in self.program_headers.iter() {
                     buf.write_fmt(format_args!("{}", &phdr))?;
                 }
 
                 buf.write_fmt(format_args!("{}", Elf::phdr_footer()))?;
             }
 
-            if let Some(shdrs) = self.section_headers.as_deref() {
+            if self.options.section_headers {
                 buf.write_fmt(format_args!("Section Headers:\n"))?;
                 buf.write_fmt(format_args!("{}", Elf::shdr_header()))?;
 
-                for (i, shdr) in shdrs.iter().enumerate() {
-                    unsafe { SH_INDEX = i as u16 };
-                    buf.write_fmt(format_args!("{}", shdr))?;
+                for (i, shdr) in self.section_headers.iter().enumerate() {
+                    buf.write_fmt(format_args!(
+                        "  [{:<2}] {:<17} {}",
+                        i,
+                        self.section_name(shdr),
+                        shdr
+                    ))?;
                 }
 
                 buf.write_fmt(format_args!("{}", Elf::shdr_footer()))?;
             }
 
+            if self.options.symbols {
+                for table in self.symbol_tables().iter() {
+                    buf.write_fmt(format_args!(
+                        "\nSymbol table '{}' contains {} entries:\n",
+                        table.name,
+                        table.symbols.len()
+                    ))?;
+                    self.write_symbols(buf, table)?;
+                }
+
+                if let Some(debugdata) = self.debugdata() {
+                    for table in debugdata.symbol_tables().iter() {
+                        buf.write_fmt(format_args!(
+                            "\nSymbol table '{}' in '.gnu_debugdata' contains {} entries:\n",
+                            table.name,
+                            table.symbols.len()
+                        ))?;
+                        self.write_symbols(buf, table)?;
+                    }
+                }
+            }
+
+            if self.options.dynamic {
+                self.write_dynamic(buf)?;
+            }
+
             buf.write_fmt(format_args!("\n"))
         }
 
+        fn write_dynamic(&self, buf: &mut dyn Write) -> io::Result<()> {
+            let (off, _) = match self.dynamic_range() {
+                Some(range) => range,
+                None => {
+                    return buf.write_fmt(format_args!(
+                        "\nThere is no dynamic section in this file.\n"
+                    ))
+                }
+            };
+            let entries = self.dynamic();
+
+            buf.write_fmt(format_args!(
+                "\nDynamic section at offset {:#x} contains {} entries:\n",
+                off,
+                entries.len() + 1
+            ))?;
+            buf.write_fmt(format_args!(
+                "  {:<18} {:<20} {}\n",
+                "Tag", "Type", "Name/Value"
+            ))?;
+
+            for dyn_ in entries.iter() {
+                let name = match ELF_DT_TAG.get(&dyn_.d_tag) {
+                    Some(name) => format!("({})", name),
+                    None => format!("({:#x})", dyn_.d_tag),
+                };
+                let value = match dyn_.d_tag {
+                    DT_NEEDED => format!("Shared library: [{}]", self.dynamic_str(dyn_.d_val)),
+                    DT_SONAME => format!("Library soname: [{}]", self.dynamic_str(dyn_.d_val)),
+                    DT_RPATH => format!("Library rpath: [{}]", self.dynamic_str(dyn_.d_val)),
+                    DT_RUNPATH => format!("Library runpath: [{}]", self.dynamic_str(dyn_.d_val)),
+                    _ => format!("{:#x}", dyn_.d_val),
+                };
+
+                buf.write_fmt(format_args!(
+                    "  {:#018x} {:<20} {}\n",
+                    dyn_.d_tag, name, value
+                ))?;
+            }
+
+            Ok(())
+        }
+
+        fn write_symbols(&self, buf: &mut dyn Write, table: &SymbolTable) -> io::Result<()> {
+            buf.write_fmt(format_args!("{}", Elf::sym_header()))?;
+
+            for (i, sym) in table.symbols.iter().enumerate() {
+                match self.options.demangle {
+                    true => buf.write_fmt(format_args!("{:>6}: {:#}", i, sym))?,
+                    false => buf.write_fmt(format_args!("{:>6}: {}", i, sym))?,
+                }
+            }
+
+            Ok(())
+        }
+
         fn phdr_header() -> String {
             format!(
                 "  {:<16}{:<18} {:<18} {:<18}\n  {:<16}{:<18} {:<18}  {:<6} {}\n",
@@ -142,7 +627,7 @@ pub mod elf {
         }
 
         fn phdr_footer() -> String {
-            format!("\n")
+            "\n".to_string()
         }
 
         fn shdr_header() -> String {
@@ -161,6 +646,58 @@ pub mod elf {
   l (large), p (processor specific)",
             )
         }
+
+        fn sym_header() -> String {
+            format!(
+                "{:>6}: {:<16} {:>5} {:<7} {:<6} {:<8} {:>3} {}\n",
+                "Num", "Value", "Size", "Type", "Bind", "Vis", "Ndx", "Name"
+            )
+        }
+    }
+
+    // NOBITS sections occupy no file space, and the size of SHT_NULL section
+    // 0 may hold the extended section count.
+    fn section_bytes<'a>(data: &'a [u8], shdr: &Elf64Shdr) -> &'a [u8] {
+        if shdr.sh_type == SHT_NOBITS || shdr.sh_type == SHT_NULL {
+            return &[];
+        }
+
+        let start = shdr.sh_offset as usize;
+        start
+            .checked_add(shdr.sh_size as usize)
+            .and_then(|end| data.get(start..end))
+            .unwrap_or(&[])
+    }
+
+    // Notes are an Elf64_Nhdr followed by the name and the descriptor, each
+    // padded to 4 bytes, or to 8 in segments aligned that way (such as
+    // .note.gnu.property).
+    pub(crate) fn read_notes(bytes: &[u8], align: u64, notes: &mut Vec<Note>) {
+        let pad = |n: usize| match align {
+            8 => (n + 7) & !7,
+            _ => (n + 3) & !3,
+        };
+        let mut off = 0;
+
+        while let Ok(nhdr) = read_struct::<Elf64Nhdr>(bytes, off) {
+            let name_off = off + mem::size_of::<Elf64Nhdr>();
+            let desc_off = name_off + pad(nhdr.n_namesz as usize);
+            let desc_end = desc_off + nhdr.n_descsz as usize;
+            let (name, desc) = match (
+                bytes.get(name_off..name_off + nhdr.n_namesz as usize),
+                bytes.get(desc_off..desc_end),
+            ) {
+                (Some(name), Some(desc)) => (name, desc),
+                _ => break,
+            };
+
+            notes.push(Note {
+                name: str_at(name, 0),
+                n_type: nhdr.n_type,
+                desc: desc.to_vec(),
+            });
+            off = pad(desc_end);
+        }
     }
 
     // TODO: how can we implement fmt::Display trait here?
@@ -171,6 +708,14 @@ pub mod elf {
     // }
 
     const EI_NIDENT: usize = 16;
+    const ELFMAG: &[u8] = b"\x7fELF";
+    const ELFCLASS64: u8 = 2;
+    const ELFDATA2LSB: u8 = 1;
+
+    pub const ET_REL: u16 = 1;
+    pub const ET_EXEC: u16 = 2;
+    pub const ET_DYN: u16 = 3;
+    pub const ET_CORE: u16 = 4;
 
     lazy_static! {
         static ref ELFOSABI: HashMap<u8, &'static str> = {
@@ -191,7 +736,7 @@ pub mod elf {
             m.insert(0xFF, "Standalone (embedded) application");
             m
         };
-        static ref ELFTYPE: HashMap<u16, &'static str> = {
+        pub(crate) static ref ELFTYPE: HashMap<u16, &'static str> = {
             let mut m = HashMap::new();
             m.insert(0x0, "No file type");
             m.insert(0x1, "Relocatable file");
@@ -465,23 +1010,30 @@ pub mod elf {
         "Reserved",
     ];
 
-    #[repr(C, packed)] // TODO: why should add this?
+    pub fn machine_name(e_machine: u16) -> &'static str {
+        EM_ARRAY
+            .get(e_machine as usize)
+            .copied()
+            .unwrap_or("Unknown")
+    }
+
+    #[repr(C)]
     #[derive(Clone, Copy, Debug)]
     pub struct Elf64Ehdr {
-        e_ident: [u8; EI_NIDENT],
-        e_type: u16,
-        e_machine: u16,
-        e_version: u32,
-        e_entry: u64,
-        e_phoff: u64,
-        e_shoff: u64,
-        e_flags: u32,
-        e_ehsize: u16,
-        e_phentsize: u16,
-        e_phnum: u16,
-        e_shensize: u16,
-        e_shnum: u16,
-        e_shstrndx: u16,
+        pub e_ident: [u8; EI_NIDENT],
+        pub e_type: u16,
+        pub e_machine: u16,
+        pub e_version: u32,
+        pub e_entry: u64,
+        pub e_phoff: u64,
+        pub e_shoff: u64,
+        pub e_flags: u32,
+        pub e_ehsize: u16,
+        pub e_phentsize: u16,
+        pub e_phnum: u16,
+        pub e_shensize: u16,
+        pub e_shnum: u16,
+        pub e_shstrndx: u16,
     }
 
     impl Elf64Ehdr {
@@ -490,40 +1042,84 @@ pub mod elf {
 
             f.read_exact(&mut buf[..])?;
 
-            let (_head, body, _tail) = unsafe { buf.align_to::<Elf64Ehdr>() };
+            Elf64Ehdr::from_bytes(&buf)
+        }
+
+        pub fn from_bytes(data: &[u8]) -> io::Result<Elf64Ehdr> {
+            if !data.starts_with(ELFMAG) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not an ELF file",
+                ));
+            }
 
-            let elfhdr = body[0];
+            let elfhdr: Elf64Ehdr = read_struct(data, 0)?;
+
+            if elfhdr.e_ident[4] != ELFCLASS64 || elfhdr.e_ident[5] != ELFDATA2LSB {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "only little endian ELF64 files are supported",
+                ));
+            }
 
             Ok(elfhdr)
         }
     }
 
     impl Display for Elf64Ehdr {
-        #[allow(unaligned_references)]
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            ExtendedEhdr {
+                ehdr: self,
+                phnum: self.e_phnum as usize,
+                shnum: self.e_shnum as usize,
+                shstrndx: self.e_shstrndx as usize,
+            }
+            .fmt(f)
+        }
+    }
+
+    // An ELF header along with the counts resolved from section header 0,
+    // which differ from the raw fields when extended numbering is in use.
+    struct ExtendedEhdr<'a> {
+        ehdr: &'a Elf64Ehdr,
+        phnum: usize,
+        shnum: usize,
+        shstrndx: usize,
+    }
+
+    fn write_count(f: &mut fmt::Formatter, raw: u16, real: usize) -> fmt::Result {
+        if raw as usize == real {
+            writeln!(f, "{}", raw)
+        } else {
+            writeln!(f, "{} ({})", raw, real)
+        }
+    }
+
+    impl Display for ExtendedEhdr<'_> {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             // Magic number
             write!(f, "  {:<34} ", "Magic:")?;
-            for n in self.e_ident.iter() {
+            for n in self.ehdr.e_ident.iter() {
                 write!(f, "{:02x} ", n)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
 
             // Class
             // TODO: need verify?
             write!(f, "  {:<34} ", "Class:")?;
-            match self.e_ident[4] {
+            match self.ehdr.e_ident[4] {
                 1 => {
-                    write!(
+                    writeln!(
                         f,
-                        "{}32\n",
-                        std::str::from_utf8(&self.e_ident[1..4]).unwrap()
+                        "{}32",
+                        std::str::from_utf8(&self.ehdr.e_ident[1..4]).unwrap()
                     )?;
                 }
                 2 => {
-                    write!(
+                    writeln!(
                         f,
-                        "{}64\n",
-                        std::str::from_utf8(&self.e_ident[1..4]).unwrap()
+                        "{}64",
+                        std::str::from_utf8(&self.ehdr.e_ident[1..4]).unwrap()
                     )?;
                 }
                 _ => {}
@@ -532,113 +1128,109 @@ pub mod elf {
             // Data
             // TODO: using enums to optimize match arms.
             write!(f, "  {:<34} ", "Data:")?;
-            match self.e_ident[5] {
+            match self.ehdr.e_ident[5] {
                 1 => {
-                    write!(f, "2's complement, little endian\n")?;
+                    writeln!(f, "2's complement, little endian")?;
                 }
                 2 => {
-                    write!(f, "2's complement, big endian\n")?;
+                    writeln!(f, "2's complement, big endian")?;
                 }
                 _ => {}
             }
 
             // Version
-            write!(f, "  {:<34} {}\n", "Version:", self.e_ident[6])?;
+            writeln!(f, "  {:<34} {}", "Version:", self.ehdr.e_ident[6])?;
 
             // OS/ABI
             write!(f, "  {:<34} ", "OS/ABI:")?;
-            if let Some(&elf_osabi) = ELFOSABI.get(&self.e_ident[7]) {
-                write!(f, "{}\n", elf_osabi)?;
+            if let Some(&elf_osabi) = ELFOSABI.get(&self.ehdr.e_ident[7]) {
+                writeln!(f, "{}", elf_osabi)?;
             } else {
-                write!(f, "\n")?;
+                writeln!(f)?;
             }
 
             // ABI Version
-            write!(f, "  {:<34} {}\n", "ABI Version:", self.e_ident[8])?;
+            writeln!(f, "  {:<34} {}", "ABI Version:", self.ehdr.e_ident[8])?;
 
             // Type
             write!(f, "  {:<34} ", "Type:")?;
-            if let Some(&elf_type) = ELFTYPE.get(&self.e_type) {
-                write!(f, "{}\n", elf_type)?;
+            if let Some(&elf_type) = ELFTYPE.get(&self.ehdr.e_type) {
+                writeln!(f, "{}", elf_type)?;
             } else {
-                write!(f, "\n")?;
+                writeln!(f)?;
             }
 
             // Machine
-            write!(
+            writeln!(
                 f,
-                "  {:<34} {}\n",
-                "Machine:", EM_ARRAY[self.e_ident[8] as usize]
+                "  {:<34} {}",
+                "Machine:",
+                machine_name(self.ehdr.e_machine)
             )?;
 
             // Entry point address
-            write!(f, "  {:<34} {:#x}\n", "Entry point address:", self.e_entry)?;
+            writeln!(
+                f,
+                "  {:<34} {:#x}",
+                "Entry point address:", self.ehdr.e_entry
+            )?;
 
             // Start of program headers
-            write!(
+            writeln!(
                 f,
-                "  {:<34} {} (bytes into file)\n",
-                "Start of program headers:", self.e_phoff
+                "  {:<34} {} (bytes into file)",
+                "Start of program headers:", self.ehdr.e_phoff
             )?;
 
             // Start of section headers
-            write!(
+            writeln!(
                 f,
-                "  {:<34} {} (bytes into file)\n",
-                "Start of section headers:", self.e_shoff
+                "  {:<34} {} (bytes into file)",
+                "Start of section headers:", self.ehdr.e_shoff
             )?;
 
             // Flags
-            write!(f, "  {:<34} {:#x}\n", "Flags:", self.e_flags)?;
+            writeln!(f, "  {:<34} {:#x}", "Flags:", self.ehdr.e_flags)?;
 
             // Size of this header
-            write!(
+            writeln!(
                 f,
-                "  {:<34} {} (bytes)\n",
-                "Size of this header:", self.e_ehsize
+                "  {:<34} {} (bytes)",
+                "Size of this header:", self.ehdr.e_ehsize
             )?;
 
             // Size of program headers
-            write!(
+            writeln!(
                 f,
-                "  {:<34} {} (bytes)\n",
-                "Size of program headers:", self.e_phentsize
+                "  {:<34} {} (bytes)",
+                "Size of program headers:", self.ehdr.e_phentsize
             )?;
 
             // Number of program headers
-            write!(
-                f,
-                "  {:<34} {}\n",
-                "Number of program headers:", self.e_phnum
-            )?;
+            write!(f, "  {:<34} ", "Number of program headers:")?;
+            write_count(f, self.ehdr.e_phnum, self.phnum)?;
 
             // Size of section headers
-            write!(
+            writeln!(
                 f,
-                "  {:<34} {} (bytes)\n",
-                "Size of section headers:", self.e_shensize
+                "  {:<34} {} (bytes)",
+                "Size of section headers:", self.ehdr.e_shensize
             )?;
 
             // Number of section headers
-            write!(
-                f,
-                "  {:<34} {}\n",
-                "Number of section headers:", self.e_shnum
-            )?;
+            write!(f, "  {:<34} ", "Number of section headers:")?;
+            write_count(f, self.ehdr.e_shnum, self.shnum)?;
 
             // Section header string table index
-            write!(
-                f,
-                "  {:<34} {}\n",
-                "Section header string table index:", self.e_shstrndx
-            )?;
+            write!(f, "  {:<34} ", "Section header string table index:")?;
+            write_count(f, self.ehdr.e_shstrndx, self.shstrndx)?;
 
-            write!(f, "\n")
+            writeln!(f)
         }
     }
 
     lazy_static! {
-        static ref ELF_PH_TYPE: HashMap<u32, &'static str> = {
+        pub(crate) static ref ELF_PH_TYPE: HashMap<u32, &'static str> = {
             let mut m = HashMap::new();
             m.insert(0x0, "NULL"); /*  Program header table entry unused  */
             m.insert(0x1, "LOAD"); /*  Loadable program segment  */
@@ -665,40 +1257,48 @@ pub mod elf {
         };
     }
 
-    const PF_X: u8 = 1 << 0; /* Segment is executable */
-    const PF_W: u8 = 1 << 1; /* Segment is writable */
-    const PF_R: u8 = 1 << 2; /* Segment is readable */
+    pub const PN_XNUM: u16 = 0xffff; /* Extended numbering: e_phnum is in sh_info of section 0 */
+
+    pub const PT_LOAD: u32 = 1;
+    pub const PT_DYNAMIC: u32 = 2;
+    pub const PT_INTERP: u32 = 3;
+    pub const PT_NOTE: u32 = 4;
+    pub const PT_PHDR: u32 = 6;
+    pub const PT_GNU_EH_FRAME: u32 = 0x6474e550;
+
+    pub const PF_X: u8 = 1 << 0; /* Segment is executable */
+    pub const PF_W: u8 = 1 << 1; /* Segment is writable */
+    pub const PF_R: u8 = 1 << 2; /* Segment is readable */
 
     #[derive(Debug, Clone, Copy)]
-    #[repr(C, packed)]
-    struct Elf64Phdr {
-        p_type: u32,
-        p_flags: u32,
-        p_offset: u64,
-        p_vaddr: u64,
-        p_paddr: u64,
-        p_filesz: u64,
-        p_memsz: u64,
-        p_align: u64,
+    #[repr(C)]
+    pub struct Elf64Phdr {
+        pub p_type: u32,
+        pub p_flags: u32,
+        pub p_offset: u64,
+        pub p_vaddr: u64,
+        pub p_paddr: u64,
+        pub p_filesz: u64,
+        pub p_memsz: u64,
+        pub p_align: u64,
     }
 
     impl Elf64Phdr {
-        fn from_file(f: &mut fs::File, off: u64) -> io::Result<Elf64Phdr> {
-            let mut buf = [0; 56];
-
-            f.seek(io::SeekFrom::Start(off))?;
-            f.read_exact(&mut buf)?;
-
-            let (_head, body, _tail) = unsafe { buf.align_to::<Elf64Phdr>() };
-
-            let elf64_phdr = body[0];
+        fn from_bytes(data: &[u8], off: usize) -> io::Result<Elf64Phdr> {
+            read_struct(data, off)
+        }
 
-            Ok(elf64_phdr)
+        /// The segment flags as letters, like "RX".
+        pub fn flags_str(&self) -> String {
+            [(PF_R, 'R'), (PF_W, 'W'), (PF_X, 'X')]
+                .iter()
+                .filter(|(flag, _)| self.p_flags & *flag as u32 != 0)
+                .map(|(_, c)| *c)
+                .collect()
         }
     }
 
     impl Display for Elf64Phdr {
-        #[allow(unaligned_references)]
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             if let Some(elf_ph_type) = ELF_PH_TYPE.get(&self.p_type) {
                 write!(f, "  {:<16}", elf_ph_type)?;
@@ -706,9 +1306,9 @@ pub mod elf {
                 write!(f, "  {:#015x}", self.p_type)?;
             }
 
-            write!(
+            writeln!(
                 f,
-                "{:#018x} {:#018x} {:#018x}\n",
+                "{:#018x} {:#018x} {:#018x}",
                 self.p_offset, self.p_vaddr, self.p_paddr
             )?;
 
@@ -729,9 +1329,9 @@ pub mod elf {
                 flag_str.push(' ');
             }
 
-            write!(
+            writeln!(
                 f,
-                "  {:<16}{:#018x} {:#018x}  {:<6} {:#x}\n",
+                "  {:<16}{:#018x} {:#018x}  {:<6} {:#x}",
                 "", self.p_filesz, self.p_memsz, flag_str, self.p_align
             )
         }
@@ -739,7 +1339,7 @@ pub mod elf {
 
     lazy_static! {
         /* Legal values for sh_type (section type).  */
-        static ref ELF_SH_TYPE: HashMap<u32, &'static str> = {
+        pub(crate) static ref ELF_SH_TYPE: HashMap<u32, &'static str> = {
             let mut m = HashMap::new();
             m.insert(0, "NULL"); /*  Section header table entry unused  */
             m.insert(1, "PROGBITS"); /*  Program data  */
@@ -781,74 +1381,78 @@ pub mod elf {
         };
     }
 
+    pub const SHT_NULL: u32 = 0;
+    pub const SHT_PROGBITS: u32 = 1;
+    pub const SHT_SYMTAB: u32 = 2;
+    pub const SHT_STRTAB: u32 = 3;
+    pub const SHT_RELA: u32 = 4;
+    pub const SHT_HASH: u32 = 5;
+    pub const SHT_DYNAMIC: u32 = 6;
+    pub const SHT_NOTE: u32 = 7;
+    pub const SHT_NOBITS: u32 = 8;
+    pub const SHT_REL: u32 = 9;
+    pub const SHT_DYNSYM: u32 = 11;
+    pub const SHT_GROUP: u32 = 17;
+    pub const SHT_SYMTAB_SHNDX: u32 = 18;
+    pub const SHT_GNU_HASH: u32 = 0x6ffffff6;
+    pub const SHT_GNU_VERDEF: u32 = 0x6ffffffd;
+    pub const SHT_GNU_VERNEED: u32 = 0x6ffffffe;
+    pub const SHT_GNU_VERSYM: u32 = 0x6fffffff;
+
     /* Legal values for sh_flags (section flags).  */
 
-    const SHF_WRITE: u32 = 1 << 0; /* Writable */
-    const SHF_ALLOC: u32 = 1 << 1; /* Occupies memory during execution */
-    const SHF_EXECINSTR: u32 = 1 << 2; /* Executable */
+    pub const SHF_WRITE: u32 = 1 << 0; /* Writable */
+    pub const SHF_ALLOC: u32 = 1 << 1; /* Occupies memory during execution */
+    pub const SHF_EXECINSTR: u32 = 1 << 2; /* Executable */
     const SHF_MERGE: u32 = 1 << 4; /* Might be merged */
     const SHF_STRINGS: u32 = 1 << 5; /* Contains nul-terminated strings */
-    const SHF_INFO_LINK: u32 = 1 << 6; /* `sh_info' contains SHT index */
-    const SHF_LINK_ORDER: u32 = 1 << 7; /* Preserve order after combining */
+    pub const SHF_INFO_LINK: u32 = 1 << 6; /* `sh_info' contains SHT index */
+    pub const SHF_LINK_ORDER: u32 = 1 << 7; /* Preserve order after combining */
     const SHF_OS_NONCONFORMING: u32 = 1 << 8; /* Non-standard OS specific handling required */
     const SHF_GROUP: u32 = 1 << 9; /* Section is member of a group.  */
-    const SHF_TLS: u32 = 1 << 10; /* Section hold thread-local data.  */
-    const SHF_COMPRESSED: u32 = 1 << 11; /* Section with compressed data. */
+    pub const SHF_TLS: u32 = 1 << 10; /* Section hold thread-local data.  */
+    pub const SHF_COMPRESSED: u32 = 1 << 11; /* Section with compressed data. */
     const SHF_MASKOS: u32 = 0x0ff00000; /* OS-specific.  */
     const SHF_MASKPROC: u32 = 0xf0000000; /* Processor-specific */
     const SHF_ORDERED: u32 = 1 << 30; /* Special ordering requirement (Solaris).  */
     const SHF_EXCLUDE: u32 = 1 << 31; /* Section is excluded unless referenced or allocated (Solaris).*/
 
-    #[derive(Debug, Clone, Copy)]
-    #[repr(C, packed)]
-    struct Elf64Shdr {
-        sh_name: u32,
-        sh_type: u32,
-        sh_flags: u64,
-        sh_addr: u64,
-        sh_offset: u64,
-        sh_size: u64,
-        sh_link: u32,
-        sh_info: u32,
-        sh_addralign: u64,
-        sh_entsize: u64,
+    /* Compression algorithms of SHF_COMPRESSED sections. */
+    pub const ELFCOMPRESS_ZLIB: u32 = 1; /* ZLIB/DEFLATE algorithm.  */
+    pub const ELFCOMPRESS_ZSTD: u32 = 2; /* Zstandard algorithm.  */
+
+    /// The header at the start of the data of an SHF_COMPRESSED section.
+    #[derive(Debug, Clone, Copy, Default)]
+    #[repr(C)]
+    pub struct Elf64Chdr {
+        pub ch_type: u32,
+        pub ch_reserved: u32,
+        pub ch_size: u64,
+        pub ch_addralign: u64,
     }
 
-    impl Elf64Shdr {
-        fn from_file(f: &mut fs::File, off: u64) -> io::Result<Elf64Shdr> {
-            let mut buf = [0; 64];
-
-            f.seek(io::SeekFrom::Start(off))?;
-            f.read_exact(&mut buf)?;
-
-            let (_head, body, _tail) = unsafe { buf.align_to::<Elf64Shdr>() };
-
-            let elf64_shdr = body[0];
-
-            Ok(elf64_shdr)
-        }
+    #[derive(Debug, Clone, Copy, Default)]
+    #[repr(C)]
+    pub struct Elf64Shdr {
+        pub sh_name: u32,
+        pub sh_type: u32,
+        pub sh_flags: u64,
+        pub sh_addr: u64,
+        pub sh_offset: u64,
+        pub sh_size: u64,
+        pub sh_link: u32,
+        pub sh_info: u32,
+        pub sh_addralign: u64,
+        pub sh_entsize: u64,
     }
 
-    impl Display for Elf64Shdr {
-        #[allow(unaligned_references)]
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            unsafe {
-                write!(
-                    f,
-                    "  [{:<2}] {:<17} ",
-                    SH_INDEX,
-                    read_str(self.sh_name as usize)
-                )?;
-            }
-
-            if let Some(elf_sh_type) = ELF_SH_TYPE.get(&self.sh_type) {
-                write!(f, "{:<17} ", elf_sh_type)?;
-            } else {
-                write!(f, "{:<17} ", "")?;
-            }
-
-            write!(f, "{:016x}  {:08x}\n", self.sh_addr, self.sh_offset)?;
+    impl Elf64Shdr {
+        fn from_bytes(data: &[u8], off: usize) -> io::Result<Elf64Shdr> {
+            read_struct(data, off)
+        }
 
+        /// The section flags as letters, like readelf shows them.
+        pub fn flags_str(&self) -> String {
             let mut flag_str = String::new();
             if (self.sh_flags & 0x1) == 1 {
                 flag_str.push('W');
@@ -887,9 +1491,27 @@ pub mod elf {
                 flag_str.push('E');
             }
 
-            write!(
+            flag_str
+        }
+    }
+
+    impl Display for Elf64Shdr {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            // Index and name are written by Elf::to_str, which owns the
+            // section header string table.
+            if let Some(elf_sh_type) = ELF_SH_TYPE.get(&self.sh_type) {
+                write!(f, "{:<17} ", elf_sh_type)?;
+            } else {
+                write!(f, "{:<17} ", "")?;
+            }
+
+            writeln!(f, "{:016x}  {:08x}", self.sh_addr, self.sh_offset)?;
+
+            let flag_str = self.flags_str();
+
+            writeln!(
                 f,
-                "  {:<4} {:016x}  {:016x} {:<6} {:<5} {:<5} {:<7}\n",
+                "  {:<4} {:016x}  {:016x} {:<6} {:<5} {:<5} {:<7}",
                 "",
                 self.sh_size,
                 self.sh_entsize,
@@ -900,4 +1522,270 @@ pub mod elf {
             )
         }
     }
+
+    lazy_static! {
+        /* Legal values for ST_TYPE subfield of st_info (symbol type).  */
+        pub(crate) static ref ELF_ST_TYPE: HashMap<u8, &'static str> = {
+            let mut m = HashMap::new();
+            m.insert(0, "NOTYPE"); /*  Symbol type is unspecified  */
+            m.insert(1, "OBJECT"); /*  Symbol is a data object  */
+            m.insert(2, "FUNC"); /*  Symbol is a code object  */
+            m.insert(3, "SECTION"); /*  Symbol associated with a section  */
+            m.insert(4, "FILE"); /*  Symbol's name is file name  */
+            m.insert(5, "COMMON"); /*  Symbol is a common data object  */
+            m.insert(6, "TLS"); /*  Symbol is thread-local data object  */
+            m.insert(10, "IFUNC"); /*  Symbol is indirect code object  */
+            m
+        };
+        /* Legal values for ST_BIND subfield of st_info (symbol binding).  */
+        pub(crate) static ref ELF_ST_BIND: HashMap<u8, &'static str> = {
+            let mut m = HashMap::new();
+            m.insert(0, "LOCAL"); /*  Local symbol  */
+            m.insert(1, "GLOBAL"); /*  Global symbol  */
+            m.insert(2, "WEAK"); /*  Weak symbol  */
+            m.insert(10, "UNIQUE"); /*  Unique symbol  */
+            m
+        };
+        /* Symbol visibility specification encoded in the st_other field.  */
+        pub(crate) static ref ELF_ST_VISIBILITY: HashMap<u8, &'static str> = {
+            let mut m = HashMap::new();
+            m.insert(0, "DEFAULT"); /*  Default symbol visibility rules  */
+            m.insert(1, "INTERNAL"); /*  Processor specific hidden class  */
+            m.insert(2, "HIDDEN"); /*  Sym unavailable in other modules  */
+            m.insert(3, "PROTECTED"); /*  Not preemptible, not exported  */
+            m
+        };
+    }
+
+    pub const STT_OBJECT: u8 = 1;
+    pub const STT_FUNC: u8 = 2;
+    pub const STT_SECTION: u8 = 3;
+    pub const STT_FILE: u8 = 4;
+    pub const STT_TLS: u8 = 6;
+    pub const STT_GNU_IFUNC: u8 = 10;
+
+    pub const STB_LOCAL: u8 = 0; /* Local symbol */
+    pub const STB_GLOBAL: u8 = 1; /* Global symbol */
+    pub const STB_WEAK: u8 = 2; /* Weak symbol */
+    pub const STB_GNU_UNIQUE: u8 = 10; /* Unique symbol.  */
+
+    pub const STV_DEFAULT: u8 = 0; /* Default symbol visibility rules */
+    pub const STV_PROTECTED: u8 = 3; /* Not preemptible, not exported */
+
+    pub const SHN_UNDEF: u16 = 0; /* Undefined section */
+    pub const SHN_LORESERVE: u16 = 0xff00; /* Start of reserved indices */
+    pub const SHN_ABS: u16 = 0xfff1; /* Associated symbol is absolute */
+    pub const SHN_COMMON: u16 = 0xfff2; /* Associated symbol is common */
+    pub const SHN_XINDEX: u16 = 0xffff; /* Index is in extra table.  */
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(C)]
+    pub struct Elf64Sym {
+        pub st_name: u32,
+        pub st_info: u8,
+        pub st_other: u8,
+        pub st_shndx: u16,
+        pub st_value: u64,
+        pub st_size: u64,
+    }
+
+    pub struct Symbol {
+        pub name: String,
+        pub sym: Elf64Sym,
+        shndx: u32,
+    }
+
+    impl Symbol {
+        pub fn value(&self) -> u64 {
+            self.sym.st_value
+        }
+
+        pub fn size(&self) -> u64 {
+            self.sym.st_size
+        }
+
+        pub fn kind(&self) -> u8 {
+            self.sym.st_info & 0xf
+        }
+
+        pub fn bind(&self) -> u8 {
+            self.sym.st_info >> 4
+        }
+
+        /// The section index of the symbol, taken from SHT_SYMTAB_SHNDX
+        /// when st_shndx is SHN_XINDEX.
+        pub fn shndx(&self) -> u32 {
+            self.shndx
+        }
+
+        pub fn is_defined(&self) -> bool {
+            self.sym.st_shndx != SHN_UNDEF
+        }
+
+        fn contains(&self, addr: u64) -> bool {
+            if !self.is_defined() || (self.kind() != STT_FUNC && self.kind() != STT_OBJECT) {
+                return false;
+            }
+
+            let end = self.value().saturating_add(self.size().max(1));
+            self.value() <= addr && addr < end
+        }
+    }
+
+    impl Display for Symbol {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{:016x} {:>5} ", self.sym.st_value, self.sym.st_size)?;
+
+            let kind = self.kind();
+            match ELF_ST_TYPE.get(&kind) {
+                Some(name) => write!(f, "{:<7} ", name)?,
+                None => write!(f, "{:<7} ", kind)?,
+            }
+
+            let bind = self.bind();
+            match ELF_ST_BIND.get(&bind) {
+                Some(name) => write!(f, "{:<6} ", name)?,
+                None => write!(f, "{:<6} ", bind)?,
+            }
+
+            let vis = self.sym.st_other & 0x3;
+            write!(f, "{:<8} ", ELF_ST_VISIBILITY[&vis])?;
+
+            match self.sym.st_shndx {
+                SHN_UNDEF => write!(f, "{:>3} ", "UND")?,
+                SHN_ABS => write!(f, "{:>3} ", "ABS")?,
+                SHN_COMMON => write!(f, "{:>3} ", "COM")?,
+                _ => write!(f, "{:>3} ", self.shndx)?,
+            }
+
+            // The alternate form shows the name demangled.
+            match f.alternate() {
+                true => writeln!(f, "{}", crate::demangle::demangle(&self.name)),
+                false => writeln!(f, "{}", self.name),
+            }
+        }
+    }
+
+    pub struct SymbolTable {
+        pub name: String,
+        pub symbols: Vec<Symbol>,
+    }
+
+    lazy_static! {
+        /* Legal values for d_tag (dynamic entry type).  */
+        pub(crate) static ref ELF_DT_TAG: HashMap<i64, &'static str> = {
+            let mut m = HashMap::new();
+            m.insert(0, "NULL"); /*  Marks end of dynamic section  */
+            m.insert(1, "NEEDED"); /*  Name of needed library  */
+            m.insert(2, "PLTRELSZ"); /*  Size in bytes of PLT relocs  */
+            m.insert(3, "PLTGOT"); /*  Processor defined value  */
+            m.insert(4, "HASH"); /*  Address of symbol hash table  */
+            m.insert(5, "STRTAB"); /*  Address of string table  */
+            m.insert(6, "SYMTAB"); /*  Address of symbol table  */
+            m.insert(7, "RELA"); /*  Address of Rela relocs  */
+            m.insert(8, "RELASZ"); /*  Total size of Rela relocs  */
+            m.insert(9, "RELAENT"); /*  Size of one Rela reloc  */
+            m.insert(10, "STRSZ"); /*  Size of string table  */
+            m.insert(11, "SYMENT"); /*  Size of one symbol table entry  */
+            m.insert(12, "INIT"); /*  Address of init function  */
+            m.insert(13, "FINI"); /*  Address of termination function  */
+            m.insert(14, "SONAME"); /*  Name of shared object  */
+            m.insert(15, "RPATH"); /*  Library search path (deprecated)  */
+            m.insert(16, "SYMBOLIC"); /*  Start symbol search here  */
+            m.insert(17, "REL"); /*  Address of Rel relocs  */
+            m.insert(18, "RELSZ"); /*  Total size of Rel relocs  */
+            m.insert(19, "RELENT"); /*  Size of one Rel reloc  */
+            m.insert(20, "PLTREL"); /*  Type of reloc in PLT  */
+            m.insert(21, "DEBUG"); /*  For debugging; unspecified  */
+            m.insert(22, "TEXTREL"); /*  Reloc might modify .text  */
+            m.insert(23, "JMPREL"); /*  Address of PLT relocs  */
+            m.insert(24, "BIND_NOW"); /*  Process relocations of object  */
+            m.insert(25, "INIT_ARRAY"); /*  Array with addresses of init fct  */
+            m.insert(26, "FINI_ARRAY"); /*  Array with addresses of fini fct  */
+            m.insert(27, "INIT_ARRAYSZ"); /*  Size in bytes of DT_INIT_ARRAY  */
+            m.insert(28, "FINI_ARRAYSZ"); /*  Size in bytes of DT_FINI_ARRAY  */
+            m.insert(29, "RUNPATH"); /*  Library search path  */
+            m.insert(30, "FLAGS"); /*  Flags for the object being loaded  */
+            m.insert(32, "PREINIT_ARRAY"); /*  Array with addresses of preinit fct */
+            m.insert(33, "PREINIT_ARRAYSZ"); /*  size in bytes of DT_PREINIT_ARRAY  */
+            m.insert(34, "SYMTAB_SHNDX"); /*  Address of SYMTAB_SHNDX section  */
+            m.insert(35, "RELRSZ"); /*  Total size of RELR relative relocations  */
+            m.insert(36, "RELR"); /*  Address of RELR relative relocations  */
+            m.insert(37, "RELRENT"); /*  Size of one RELR relative relocaction  */
+            m.insert(0x6ffffef5, "GNU_HASH"); /*  GNU-style hash table.  */
+            m.insert(0x6ffffff0, "VERSYM"); /*  Symbol version table  */
+            m.insert(0x6ffffff9, "RELACOUNT"); /*  Count of RELATIVE relocs  */
+            m.insert(0x6ffffffa, "RELCOUNT"); /*  Count of RELATIVE relocs  */
+            m.insert(0x6ffffffb, "FLAGS_1"); /*  State flags, see DF_1_* below.  */
+            m.insert(0x6ffffffc, "VERDEF"); /*  Address of version definition table */
+            m.insert(0x6ffffffd, "VERDEFNUM"); /*  Number of version definitions */
+            m.insert(0x6ffffffe, "VERNEED"); /*  Address of table with needed versions */
+            m.insert(0x6fffffff, "VERNEEDNUM"); /*  Number of needed versions */
+            m
+        };
+    }
+
+    pub const DT_NULL: i64 = 0;
+    pub const DT_NEEDED: i64 = 1;
+    pub const DT_PLTGOT: i64 = 3;
+    pub const DT_HASH: i64 = 4;
+    pub const DT_STRTAB: i64 = 5;
+    pub const DT_SYMTAB: i64 = 6;
+    pub const DT_RELA: i64 = 7;
+    pub const DT_STRSZ: i64 = 10;
+    pub const DT_INIT: i64 = 12;
+    pub const DT_FINI: i64 = 13;
+    pub const DT_SONAME: i64 = 14;
+    pub const DT_RPATH: i64 = 15;
+    pub const DT_REL: i64 = 17;
+    pub const DT_DEBUG: i64 = 21;
+    pub const DT_JMPREL: i64 = 23;
+    pub const DT_INIT_ARRAY: i64 = 25;
+    pub const DT_FINI_ARRAY: i64 = 26;
+    pub const DT_RUNPATH: i64 = 29;
+    pub const DT_PREINIT_ARRAY: i64 = 32;
+    pub const DT_GNU_HASH: i64 = 0x6ffffef5;
+    pub const DT_VERSYM: i64 = 0x6ffffff0;
+    pub const DT_FLAGS_1: i64 = 0x6ffffffb;
+    pub const DT_VERDEF: i64 = 0x6ffffffc;
+    pub const DT_VERDEFNUM: i64 = 0x6ffffffd;
+    pub const DT_VERNEED: i64 = 0x6ffffffe;
+    pub const DT_VERNEEDNUM: i64 = 0x6fffffff;
+
+    pub const DF_1_NODEFLIB: u64 = 0x00000800; /* Ignore default lib search path.  */
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(C)]
+    pub struct Elf64Dyn {
+        pub d_tag: i64,
+        pub d_val: u64,
+    }
+
+    pub const EM_X86_64: u16 = 62; /* AMD x86-64 architecture */
+    pub const EM_AARCH64: u16 = 183; /* ARM AARCH64 */
+
+    /* Legal values for note segment descriptor types for core files. */
+    pub const NT_PRSTATUS: u32 = 1; /* Contains copy of prstatus struct */
+    pub const NT_FPREGSET: u32 = 2; /* Contains copy of fpregset struct */
+    pub const NT_PRPSINFO: u32 = 3; /* Contains copy of prpsinfo struct */
+    pub const NT_AUXV: u32 = 6; /* Contains copy of auxv array */
+    pub const NT_SIGINFO: u32 = 0x53494749; /* Contains copy of siginfo_t */
+    pub const NT_FILE: u32 = 0x46494c45; /* Contains information about mapped files */
+
+    /* Note types of the "GNU" owner. */
+    pub const NT_GNU_BUILD_ID: u32 = 3; /* Unique build ID bitstring */
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(C)]
+    pub struct Elf64Nhdr {
+        pub n_namesz: u32,
+        pub n_descsz: u32,
+        pub n_type: u32,
+    }
+
+    pub struct Note {
+        pub name: String,
+        pub n_type: u32,
+        pub desc: Vec<u8>,
+    }
 }
@@ -0,0 +1,371 @@
+use core::fmt::{self, Display};
+use std::io::{self, Write};
+use std::mem;
+
+use crate::elf::{self, Elf, Elf64Ehdr, Elf64Phdr, Elf64Shdr, Elf64Sym};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+struct Linter<'a> {
+    elf: &'a Elf,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn error(&mut self, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+
+    fn file_size(&self) -> u64 {
+        self.elf.data().len() as u64
+    }
+
+    fn check_header(&mut self) {
+        let ehdr = *self.elf.header();
+        let phnum = self.elf.program_headers().len();
+        let shnum = self.elf.section_headers().len();
+        let shstrndx = self.elf.shstrndx();
+
+        if ehdr.e_ident[6] != 1 || ehdr.e_version != 1 {
+            self.warning(format!(
+                "unknown ELF version {} (ident {})",
+                ehdr.e_version, ehdr.e_ident[6]
+            ));
+        }
+
+        if ehdr.e_ehsize as usize != mem::size_of::<Elf64Ehdr>() {
+            self.warning(format!("e_ehsize is {}, expected 64", ehdr.e_ehsize));
+        }
+
+        if phnum > 0 && ehdr.e_phentsize as usize != mem::size_of::<Elf64Phdr>() {
+            self.warning(format!("e_phentsize is {}, expected 56", ehdr.e_phentsize));
+        }
+
+        if shnum > 0 && ehdr.e_shensize as usize != mem::size_of::<Elf64Shdr>() {
+            self.warning(format!("e_shentsize is {}, expected 64", ehdr.e_shensize));
+        }
+
+        if shstrndx >= shnum && shstrndx != 0 {
+            self.error(format!(
+                "e_shstrndx {} is out of range ({} sections)",
+                shstrndx, shnum
+            ));
+        } else if let Some(shdr) = self.elf.section_headers().get(shstrndx) {
+            if shstrndx != 0 && shdr.sh_type != elf::SHT_STRTAB {
+                self.error(format!(
+                    "e_shstrndx {} does not refer to a string table",
+                    shstrndx
+                ));
+            }
+        }
+
+        // Extended numbering is only valid when the counts overflow.
+        if ehdr.e_shnum == 0 && shnum > 0 && shnum < elf::SHN_LORESERVE as usize {
+            self.warning(format!(
+                "e_shnum is 0 but the {} sections would fit in it",
+                shnum
+            ));
+        }
+        if ehdr.e_shstrndx == elf::SHN_XINDEX && shstrndx < elf::SHN_LORESERVE as usize {
+            self.warning(format!(
+                "e_shstrndx is SHN_XINDEX but index {} would fit in it",
+                shstrndx
+            ));
+        }
+    }
+
+    fn check_sections(&mut self) {
+        let shdrs = self.elf.section_headers();
+        // A bad e_shstrndx is reported once by check_header.
+        let shstrtab_size = shdrs
+            .get(self.elf.shstrndx())
+            .filter(|shdr| shdr.sh_type == elf::SHT_STRTAB)
+            .map(|shdr| shdr.sh_size);
+
+        for (i, shdr) in shdrs.iter().enumerate() {
+            if i == 0 && shdr.sh_type == elf::SHT_NULL {
+                continue;
+            }
+
+            let name = self.elf.section_name(shdr);
+
+            if shdr.sh_name != 0 && shstrtab_size.is_some_and(|size| shdr.sh_name as u64 >= size) {
+                self.error(format!(
+                    "section [{}] has bad name index {:#x}",
+                    i, shdr.sh_name
+                ));
+            }
+
+            if shdr.sh_type != elf::SHT_NOBITS
+                && shdr.sh_offset.saturating_add(shdr.sh_size) > self.file_size()
+            {
+                self.error(format!(
+                    "section [{}] '{}' ({:#x}..{:#x}) extends past end of file ({:#x})",
+                    i,
+                    name,
+                    shdr.sh_offset,
+                    shdr.sh_offset.saturating_add(shdr.sh_size),
+                    self.file_size()
+                ));
+            }
+
+            if shdr.sh_addralign > 1 {
+                if !shdr.sh_addralign.is_power_of_two() {
+                    self.warning(format!(
+                        "section [{}] '{}' alignment {:#x} is not a power of two",
+                        i, name, shdr.sh_addralign
+                    ));
+                } else if shdr.sh_addr % shdr.sh_addralign != 0 {
+                    self.warning(format!(
+                        "section [{}] '{}' address {:#x} is not aligned to {:#x}",
+                        i, name, shdr.sh_addr, shdr.sh_addralign
+                    ));
+                }
+            }
+
+            if let Some(entsize) = expected_entsize(shdr.sh_type) {
+                if shdr.sh_entsize != entsize {
+                    self.error(format!(
+                        "section [{}] '{}' has entsize {}, expected {}",
+                        i, name, shdr.sh_entsize, entsize
+                    ));
+                } else if shdr.sh_type != elf::SHT_NOBITS && shdr.sh_size % entsize != 0 {
+                    self.error(format!(
+                        "section [{}] '{}' size {:#x} is not a multiple of its entsize {}",
+                        i, name, shdr.sh_size, entsize
+                    ));
+                }
+            }
+
+            if has_link(shdr.sh_type) && shdr.sh_link as usize >= shdrs.len() {
+                self.error(format!(
+                    "section [{}] '{}' links to nonexistent section {}",
+                    i, name, shdr.sh_link
+                ));
+            }
+        }
+    }
+
+    fn check_symbols(&mut self) {
+        let shdrs = self.elf.section_headers();
+
+        for (i, shdr) in shdrs.iter().enumerate() {
+            if shdr.sh_type != elf::SHT_SYMTAB && shdr.sh_type != elf::SHT_DYNSYM {
+                continue;
+            }
+
+            let name = self.elf.section_name(shdr);
+            let strtab_size = shdrs.get(shdr.sh_link as usize).map_or(0, |s| s.sh_size);
+            let table = self
+                .elf
+                .symbol_tables()
+                .iter()
+                .find(|table| table.name == name);
+            let mut bad_names = 0;
+            let mut bad_shndx = 0;
+
+            for sym in table.iter().flat_map(|table| table.symbols.iter()) {
+                if sym.sym.st_name as u64 >= strtab_size && sym.sym.st_name != 0 {
+                    bad_names += 1;
+                }
+
+                let raw = sym.sym.st_shndx;
+                let special = raw >= elf::SHN_LORESERVE && raw != elf::SHN_XINDEX;
+                if raw != elf::SHN_UNDEF && !special && sym.shndx() as usize >= shdrs.len() {
+                    bad_shndx += 1;
+                }
+            }
+
+            if bad_names > 0 {
+                self.error(format!(
+                    "section [{}] '{}' has {} symbols with bad string indices",
+                    i, name, bad_names
+                ));
+            }
+            if bad_shndx > 0 {
+                self.error(format!(
+                    "section [{}] '{}' has {} symbols with bad section indices",
+                    i, name, bad_shndx
+                ));
+            }
+        }
+    }
+
+    fn check_segments(&mut self) {
+        let phdrs = self.elf.program_headers();
+        let mut loads: Vec<(usize, &Elf64Phdr)> = Vec::new();
+
+        for (i, phdr) in phdrs.iter().enumerate() {
+            if phdr.p_offset.saturating_add(phdr.p_filesz) > self.file_size() {
+                self.error(format!(
+                    "segment [{}] ({:#x}..{:#x}) extends past end of file ({:#x})",
+                    i,
+                    phdr.p_offset,
+                    phdr.p_offset.saturating_add(phdr.p_filesz),
+                    self.file_size()
+                ));
+            }
+
+            if phdr.p_type != elf::PT_LOAD {
+                continue;
+            }
+
+            if phdr.p_filesz > phdr.p_memsz {
+                self.error(format!(
+                    "segment [{}] p_filesz {:#x} exceeds p_memsz {:#x}",
+                    i, phdr.p_filesz, phdr.p_memsz
+                ));
+            }
+
+            if phdr.p_align > 1 {
+                if !phdr.p_align.is_power_of_two() {
+                    self.error(format!(
+                        "segment [{}] alignment {:#x} is not a power of two",
+                        i, phdr.p_align
+                    ));
+                } else if phdr.p_offset % phdr.p_align != phdr.p_vaddr % phdr.p_align {
+                    self.error(format!(
+                        "segment [{}] p_offset {:#x} and p_vaddr {:#x} are not congruent modulo p_align {:#x}",
+                        i, phdr.p_offset, phdr.p_vaddr, phdr.p_align
+                    ));
+                }
+            }
+
+            if let Some((_, prev)) = loads.last() {
+                if phdr.p_vaddr < prev.p_vaddr {
+                    self.warning(format!(
+                        "segment [{}] is not sorted by p_vaddr among LOAD segments",
+                        i
+                    ));
+                }
+            }
+
+            for (j, other) in loads.iter() {
+                let end = phdr.p_vaddr.saturating_add(phdr.p_memsz);
+                let other_end = other.p_vaddr.saturating_add(other.p_memsz);
+
+                if phdr.p_vaddr < other_end && other.p_vaddr < end {
+                    self.error(format!(
+                        "LOAD segments [{}] and [{}] overlap ({:#x}..{:#x} and {:#x}..{:#x})",
+                        j, i, other.p_vaddr, other_end, phdr.p_vaddr, end
+                    ));
+                }
+            }
+
+            loads.push((i, phdr));
+        }
+
+        let ehdr = self.elf.header();
+        if (ehdr.e_type == elf::ET_EXEC || ehdr.e_type == elf::ET_DYN) && ehdr.e_entry != 0 {
+            let entry = ehdr.e_entry;
+            let executable = loads.iter().any(|(_, phdr)| {
+                phdr.p_flags & elf::PF_X as u32 != 0
+                    && phdr.p_vaddr <= entry
+                    && entry < phdr.p_vaddr.saturating_add(phdr.p_memsz)
+            });
+
+            if !executable {
+                self.warning(format!(
+                    "entry point {:#x} is not inside an executable LOAD segment",
+                    entry
+                ));
+            }
+        }
+    }
+}
+
+fn expected_entsize(sh_type: u32) -> Option<u64> {
+    match sh_type {
+        elf::SHT_SYMTAB | elf::SHT_DYNSYM => Some(mem::size_of::<Elf64Sym>() as u64),
+        elf::SHT_RELA => Some(24),
+        elf::SHT_REL | elf::SHT_DYNAMIC => Some(16),
+        elf::SHT_GNU_VERSYM => Some(2),
+        elf::SHT_SYMTAB_SHNDX => Some(4),
+        _ => None,
+    }
+}
+
+pub(crate) fn has_link(sh_type: u32) -> bool {
+    matches!(
+        sh_type,
+        elf::SHT_SYMTAB
+            | elf::SHT_DYNSYM
+            | elf::SHT_REL
+            | elf::SHT_RELA
+            | elf::SHT_HASH
+            | elf::SHT_GNU_HASH
+            | elf::SHT_DYNAMIC
+            | elf::SHT_GNU_VERSYM
+            | elf::SHT_GNU_VERDEF
+            | elf::SHT_GNU_VERNEED
+            | elf::SHT_SYMTAB_SHNDX
+    )
+}
+
+impl Elf {
+    /// Check the headers for inconsistencies a loader or linker would trip
+    /// over. Diagnostics are ordered by the header they concern.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut linter = Linter {
+            elf: self,
+            diagnostics: Vec::new(),
+        };
+
+        linter.check_header();
+        linter.check_segments();
+        linter.check_sections();
+        linter.check_symbols();
+
+        linter.diagnostics
+    }
+}
+
+/// Print diagnostics followed by a count, returning the number of errors.
+pub fn to_str(diagnostics: &[Diagnostic], buf: &mut dyn Write) -> io::Result<usize> {
+    buf.write_fmt(format_args!("Lint:\n"))?;
+
+    for diag in diagnostics {
+        buf.write_fmt(format_args!("  {}\n", diag))?;
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    buf.write_fmt(format_args!(
+        "  {} error(s), {} warning(s)\n",
+        errors,
+        diagnostics.len() - errors
+    ))?;
+
+    Ok(errors)
+}
@@ -1,14 +1,25 @@
-use std::io::{self, BufWriter, Write};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+use std::{process, thread};
 
-use clap::Parser;
-use rself::elf;
+use clap::{Parser, Subcommand};
+use rself::{
+    abi, archive, backtrace, batch, bind, convert, coredump, demangle, deps, diff, edit, elf,
+    ldcache, lint, memory, nm, procfs, size, strip, writer,
+};
 
 #[derive(Parser, Debug)]
 #[clap(name = "rself")]
 #[clap(author = "chengzhycn <chengzhycn@gmail.com>")]
 #[clap(version = "0.1.0")]
 #[clap(about = "A tool for parsing ELF file.", long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Display the ELF file header
     #[clap(short = 'h', long)]
     file_header: bool,
@@ -21,36 +32,1242 @@ struct Args {
     #[clap(short = 'S', long)]
     section_headers: bool,
 
-    /// Equivalent to: -h -p -s
+    /// Display the symbol tables, including MiniDebugInfo (.gnu_debugdata)
+    #[clap(short = 's', long = "syms")]
+    symbols: bool,
+
+    /// Display the dynamic section
+    #[clap(short = 'd', long)]
+    dynamic: bool,
+
+    /// Demangle C++ and Rust symbol names
+    #[clap(short = 'C', long)]
+    demangle: bool,
+
+    /// Display the threads, signal, command line and mapped files of a
+    /// core dump
+    #[clap(long)]
+    core: bool,
+
+    /// Display the symbol index of an archive
+    #[clap(short = 'c', long)]
+    archive_index: bool,
+
+    /// Check the headers for inconsistencies and report diagnostics
+    #[clap(long)]
+    lint: bool,
+
+    /// Equivalent to: -h -l -S -s -d, and --core for core dumps
     #[clap(short, long)]
     all: bool,
 
+    /// Print the symbol covering each given address
+    #[clap(long, value_name = "ADDR", parse(try_from_str = parse_addr))]
+    symbolize: Vec<u64>,
+
+    /// Dump LEN bytes of memory at virtual address ADDR (hex), read through
+    /// the loadable segments and, for core files, the mapped files
+    #[clap(long, value_name = "ADDR:LEN", parse(try_from_str = parse_range))]
+    dump_vaddr: Vec<(u64, usize)>,
+
+    /// Scan a directory recursively, skipping files that are not ELF
+    /// objects or archives
+    #[clap(short = 'r', long, value_name = "DIR")]
+    recursive: Vec<String>,
+
+    /// Parse the files on a pool of worker threads and finish with a
+    /// summary of machine types, PIE and stripped ratios and interpreters
+    #[clap(long)]
+    batch: bool,
+
+    /// Number of worker threads for --batch [default: number of CPUs]
+    #[clap(short = 'j', long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Inspect the executable and shared objects mapped by a running
+    /// process, as they are in memory rather than on disk
+    #[clap(long, value_name = "PID")]
+    pid: Option<u32>,
+
+    /// Dump the vDSO the kernel maps into every process
+    #[clap(long)]
+    vdso: bool,
+
+    /// Save a copy of the vDSO to FILE, implies --vdso
+    #[clap(long, value_name = "FILE")]
+    save_vdso: Option<String>,
+
+    /// Print the libraries in an ld.so.cache file, by default
+    /// /etc/ld.so.cache, like ldconfig -p
+    #[clap(long, value_name = "FILE", min_values = 0, require_equals = true)]
+    print_ldcache: Option<Option<String>>,
+
+    /// elf-file(s)
+    #[clap(required_unless_present_any = &["recursive", "pid", "vdso", "save-vdso", "print-ldcache"])]
+    files: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Change the interpreter, search path, soname, needed libraries or
+    /// section contents
+    Edit(EditArgs),
+    /// Remove debugging information, symbols or other sections
+    Strip(StripArgs),
+    /// Flatten the loadable segments into a raw binary, Intel HEX or
+    /// S-record file
+    Convert(ConvertArgs),
+    /// Unwind and symbolize the stack of each thread in a core dump
+    Backtrace(BacktraceArgs),
+    /// Resolve the shared library dependency tree without running the
+    /// dynamic loader
+    Deps(DepsArgs),
+    /// List the symbols of object files, like nm
+    Nm(NmArgs),
+    /// Print section sizes and totals, like size, or break the file and
+    /// memory size down by section, segment or symbol
+    Size(SizeArgs),
+    /// Compare the headers, sections, symbols and dynamic entries of two
+    /// files, exiting with 1 if they differ and 2 on errors
+    Diff(DiffArgs),
+    /// Compare the exported interface of two versions of a shared library,
+    /// exiting with 4 if it changed compatibly and 12 if objects built
+    /// against the old one may break
+    AbiDiff(AbiDiffArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct EditArgs {
+    /// Set the program interpreter (PT_INTERP)
+    #[clap(long, value_name = "PATH")]
+    set_interpreter: Option<String>,
+
+    /// Set DT_RPATH, removing DT_RUNPATH
+    #[clap(long, value_name = "PATH", conflicts_with_all = &["set-runpath", "remove-rpath"])]
+    set_rpath: Option<String>,
+
+    /// Set DT_RUNPATH, removing DT_RPATH
+    #[clap(long, value_name = "PATH", conflicts_with = "remove-rpath")]
+    set_runpath: Option<String>,
+
+    /// Remove DT_RPATH and DT_RUNPATH
+    #[clap(long)]
+    remove_rpath: bool,
+
+    /// Set DT_SONAME
+    #[clap(long, value_name = "NAME")]
+    set_soname: Option<String>,
+
+    /// Add a DT_NEEDED entry
+    #[clap(long, value_name = "LIB")]
+    add_needed: Vec<String>,
+
+    /// Remove a DT_NEEDED entry
+    #[clap(long, value_name = "LIB")]
+    remove_needed: Vec<String>,
+
+    /// Append a non-allocated section with the contents of FILE
+    #[clap(long, value_name = "NAME=FILE", parse(try_from_str = parse_section_file))]
+    add_section: Vec<(String, String)>,
+
+    /// Replace the contents of a section with those of FILE
+    #[clap(long, value_name = "NAME=FILE", parse(try_from_str = parse_section_file))]
+    update_section: Vec<(String, String)>,
+
+    /// Save the contents of a section to FILE
+    #[clap(long, value_name = "NAME=FILE", parse(try_from_str = parse_section_file))]
+    dump_section: Vec<(String, String)>,
+
+    /// Write the result to FILE instead of modifying the input in place
+    #[clap(short = 'o', long, value_name = "FILE")]
+    output: Option<String>,
+
     /// elf-file
+    file: String,
+}
+
+impl EditArgs {
+    // Whether anything beyond --dump-section was asked for.
+    fn modifies(&self) -> bool {
+        self.set_interpreter.is_some()
+            || self.set_rpath.is_some()
+            || self.set_runpath.is_some()
+            || self.remove_rpath
+            || self.set_soname.is_some()
+            || !self.add_needed.is_empty()
+            || !self.remove_needed.is_empty()
+            || !self.add_section.is_empty()
+            || !self.update_section.is_empty()
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct StripArgs {
+    /// Remove debugging sections only
+    #[clap(short = 'g', long)]
+    strip_debug: bool,
+
+    /// Remove debugging sections and the symbol table [default]
+    #[clap(short = 's', long)]
+    strip_all: bool,
+
+    /// Keep only the sections a separate debug file needs
+    #[clap(long)]
+    only_keep_debug: bool,
+
+    /// Remove the sections matching a pattern ('*' and '?' are wildcards)
+    #[clap(short = 'R', long, value_name = "GLOB")]
+    remove_section: Vec<String>,
+
+    /// Write the result to FILE instead of modifying the input in place
+    #[clap(short = 'o', long, value_name = "FILE")]
+    output: Option<String>,
+
+    /// elf-file
+    file: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct ConvertArgs {
+    /// Output format: binary, ihex or srec
+    #[clap(short = 'O', long, value_name = "FORMAT", parse(try_from_str = parse_format))]
+    output_format: convert::Format,
+
+    /// Fill the gaps between segments with BYTE, also in ihex and srec
+    /// output [default for binary: 0]
+    #[clap(long, value_name = "BYTE", parse(try_from_str = parse_byte))]
+    gap_fill: Option<u8>,
+
+    /// Leave out the bytes below this load address, in decimal or 0x hex
+    #[clap(long, value_name = "ADDR", parse(try_from_str = parse_number))]
+    start_address: Option<u64>,
+
+    /// Leave out the bytes at and above this load address, in decimal or
+    /// 0x hex
+    #[clap(long, value_name = "ADDR", parse(try_from_str = parse_number))]
+    stop_address: Option<u64>,
+
+    /// Output file
+    #[clap(short = 'o', long, value_name = "FILE")]
+    output: String,
+
+    /// elf-file
+    file: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct BacktraceArgs {
+    /// The executable that dumped core, if not at its recorded path
+    #[clap(long, value_name = "FILE")]
+    exe: Option<String>,
+
+    /// Look for the mapped libraries below DIR
+    #[clap(long, value_name = "DIR")]
+    sysroot: Option<String>,
+
+    /// Demangle C++ and Rust symbol names
+    #[clap(short = 'C', long)]
+    demangle: bool,
+
+    /// core-file
+    core: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct DepsArgs {
+    /// Resolve libraries in the file system below DIR
+    #[clap(long, value_name = "DIR")]
+    sysroot: Option<String>,
+
+    /// What $LIB in search paths stands for, e.g. lib64 or
+    /// lib/x86_64-linux-gnu; by default the directory of the dynamic loader
+    #[clap(long, value_name = "DIR")]
+    lib: Option<String>,
+
+    /// Bind the undefined dynamic symbols of all objects and report those
+    /// left unresolved or interposed, like ldd -r
+    #[clap(short = 'r', long)]
+    check_symbols: bool,
+
+    /// Demangle C++ and Rust symbol names
+    #[clap(short = 'C', long)]
+    demangle: bool,
+
+    /// Report the DT_NEEDED libraries of the file that none of its symbol
+    /// references bind to, like ldd -u
+    #[clap(short = 'u', long)]
+    unused: bool,
+
+    /// elf-file
+    file: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct NmArgs {
+    /// Output format: bsd, posix or sysv
+    #[clap(short = 'f', long, value_name = "FORMAT", default_value = "bsd",
+           parse(try_from_str = parse_nm_format))]
+    format: nm::Format,
+
+    /// Use the POSIX output format, same as --format=posix
+    #[clap(short = 'P', long)]
+    portability: bool,
+
+    /// List the dynamic symbols (.dynsym) instead of .symtab
+    #[clap(short = 'D', long)]
+    dynamic: bool,
+
+    /// List only the defined symbols
+    #[clap(long, conflicts_with = "undefined-only")]
+    defined_only: bool,
+
+    /// List only the undefined symbols
+    #[clap(short = 'u', long)]
+    undefined_only: bool,
+
+    /// Sort by address rather than by name
+    #[clap(short = 'n', long)]
+    numeric_sort: bool,
+
+    /// Sort by size, listing only the symbols that have one
+    #[clap(long, conflicts_with = "numeric-sort")]
+    size_sort: bool,
+
+    /// Keep the symbols in symbol table order
+    #[clap(short = 'p', long, conflicts_with_all = &["numeric-sort", "size-sort"])]
+    no_sort: bool,
+
+    /// Reverse the sort order
+    #[clap(short = 'r', long)]
+    reverse_sort: bool,
+
+    /// Print the size of defined symbols in BSD format
+    #[clap(short = 'S', long)]
+    print_size: bool,
+
+    /// Demangle C++ and Rust symbol names
+    #[clap(short = 'C', long)]
+    demangle: bool,
+
+    /// elf-file(s) or archive(s)
+    #[clap(required = true)]
+    files: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct SizeArgs {
+    /// Output format: berkeley or sysv
+    #[clap(long, value_name = "FORMAT", default_value = "berkeley",
+           parse(try_from_str = parse_size_format))]
+    format: size::Format,
+
+    /// Use the SysV output format, same as --format=sysv
+    #[clap(short = 'A', conflicts_with = "berkeley")]
+    sysv: bool,
+
+    /// Use the Berkeley output format, same as --format=berkeley
+    #[clap(short = 'B')]
+    berkeley: bool,
+
+    /// Print sizes in radix 8, 10 or 16
+    #[clap(long, value_name = "RADIX", default_value = "10",
+           possible_values = &["8", "10", "16"])]
+    radix: u32,
+
+    /// Print sizes in octal, same as --radix=8
+    #[clap(short = 'o', conflicts_with_all = &["decimal", "hex"])]
+    octal: bool,
+
+    /// Print sizes in decimal, same as --radix=10
+    #[clap(short = 'd', conflicts_with = "hex")]
+    decimal: bool,
+
+    /// Print sizes in hex, same as --radix=16
+    #[clap(short = 'x')]
+    hex: bool,
+
+    /// Print the totals of all files, in Berkeley format
+    #[clap(short = 't', long)]
+    totals: bool,
+
+    /// Break the file and VM sizes down by sections, segments or symbols
+    /// instead
+    #[clap(long, value_name = "SOURCE", parse(try_from_str = parse_size_source))]
+    breakdown: Option<size::Source>,
+
+    /// Show the N largest rows of a breakdown, summing up the rest
+    #[clap(short = 'n', long, value_name = "N", default_value = "20")]
+    top: usize,
+
+    /// Demangle C++ and Rust symbol names
+    #[clap(short = 'C', long)]
+    demangle: bool,
+
+    /// elf-file(s) or archive(s)
     #[clap(required = true)]
-    file: Option<String>,
+    files: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// Print the differences as JSON
+    #[clap(long)]
+    json: bool,
+
+    /// Also report changes of addresses and file offsets alone
+    #[clap(short = 'a', long)]
+    addresses: bool,
+
+    /// The old elf-file
+    old: String,
+
+    /// The new elf-file
+    new: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct AbiDiffArgs {
+    /// Demangle C++ and Rust symbol names
+    #[clap(short = 'C', long)]
+    demangle: bool,
+
+    /// The old library
+    old: String,
+
+    /// The new library
+    new: String,
+}
+
+fn parse_size_format(s: &str) -> Result<size::Format, String> {
+    size::Format::from_name(s).ok_or_else(|| format!("unknown format '{}'", s))
+}
+
+fn parse_size_source(s: &str) -> Result<size::Source, String> {
+    size::Source::from_name(s).ok_or_else(|| format!("unknown breakdown '{}'", s))
+}
+
+fn parse_nm_format(s: &str) -> Result<nm::Format, String> {
+    nm::Format::from_name(s).ok_or_else(|| format!("unknown format '{}'", s))
+}
+
+fn parse_format(s: &str) -> Result<convert::Format, String> {
+    convert::Format::from_name(s).ok_or_else(|| format!("unknown format '{}'", s))
+}
+
+fn parse_byte(s: &str) -> Result<u8, ParseIntError> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+fn parse_section_file(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, file)) if !name.is_empty() && !file.is_empty() => {
+            Ok((name.to_string(), file.to_string()))
+        }
+        _ => Err(format!("expected NAME=FILE, got '{}'", s)),
+    }
+}
+
+fn parse_range(s: &str) -> Result<(u64, usize), String> {
+    let (addr, len) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected ADDR:LEN, got '{}'", s))?;
+    let addr = parse_addr(addr).map_err(|err| err.to_string())?;
+    let len = match len.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => len.parse(),
+    }
+    .map_err(|err| err.to_string())?;
+
+    Ok((addr, len))
+}
+
+// Plain digits are decimal, as objcopy reads them.
+fn parse_number(s: &str) -> Result<u64, ParseIntError> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
+
+fn parse_addr(s: &str) -> Result<u64, ParseIntError> {
+    u64::from_str_radix(s.trim_start_matches("0x"), 16)
 }
 
 fn main() -> io::Result<()> {
     let args = Args::parse();
+
+    if let Some(command) = args.command.as_ref() {
+        let result = match command {
+            Command::Edit(edit) => run_edit(edit),
+            Command::Strip(strip) => run_strip(strip),
+            Command::Convert(convert) => run_convert(convert),
+            Command::Backtrace(backtrace) => run_backtrace(backtrace),
+            Command::Deps(deps) => run_deps(deps),
+            Command::Nm(nm) => run_nm(nm),
+            Command::Size(size) => run_size(size),
+            Command::Diff(diff) => run_diff(diff),
+            Command::AbiDiff(abi_diff) => run_abi_diff(abi_diff),
+        };
+
+        if let Err(err) = result {
+            eprintln!("rself: {}", err);
+            // diff exits with 1 when the files differ, like diff(1).
+            process::exit(match command {
+                Command::Diff(_) => 2,
+                _ => 1,
+            });
+        }
+        return Ok(());
+    }
+
     let mut options = elf::Options {
         file_header: args.file_header,
         program_headers: args.program_headers,
         section_headers: args.section_headers,
+        symbols: args.symbols,
+        dynamic: args.dynamic,
+        demangle: args.demangle,
     };
 
     if args.all {
         options.file_header = true;
         options.program_headers = true;
         options.section_headers = true;
+        options.symbols = true;
+        options.dynamic = true;
+    }
+
+    let mut files: Vec<PathBuf> = args.files.iter().map(PathBuf::from).collect();
+    for dir in args.recursive.iter() {
+        walk(Path::new(dir), &mut files);
     }
 
-    if let Some(file) = args.file.as_deref() {
-        let mut buffer = BufWriter::new(io::stdout());
-        let mut elf = elf::Elf::new(file, options);
+    // Like GNU readelf, only name the file when there is more than one.
+    let banner = files.len() > 1 || !args.recursive.is_empty();
+    let mut buffer = BufWriter::new(io::stdout());
+    let mut stats = batch::Stats::default();
+    let mut result = Ok(());
+
+    let mut emit = |report: Report| {
+        if result.is_ok() {
+            result = buffer
+                .write_all(&report.output)
+                .and_then(|_| buffer.flush());
+        }
+
+        for err in report.errors.iter() {
+            eprintln!("rself: {}", err);
+            stats.add_error();
+        }
+        for summary in report.summaries.iter() {
+            stats.add(summary);
+        }
+    };
+
+    if let Some(path) = args.print_ldcache.as_ref() {
+        emit(process_ldcache(
+            path.as_deref().unwrap_or("/etc/ld.so.cache"),
+        ));
+    }
+    if args.vdso || args.save_vdso.is_some() {
+        emit(process_vdso(&args, options));
+    }
+    if let Some(pid) = args.pid {
+        emit(process_pid(pid, &args, options));
+    }
+
+    let work = |file: &PathBuf| process_file(file, &args, options, banner);
+
+    if args.batch {
+        let jobs = args
+            .jobs
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        batch::run_ordered(&files, jobs, work, emit);
+    } else {
+        files.iter().map(work).for_each(&mut emit);
+    }
 
-        elf.to_str(&mut buffer)?;
+    result?;
+
+    if args.batch {
+        buffer.write_fmt(format_args!("\n"))?;
+        stats.to_str(&mut buffer)?;
         buffer.flush()?;
     }
 
+    if stats.has_errors() {
+        process::exit(1);
+    }
+
     Ok(())
 }
+
+// Everything produced for one input file, so that batch mode can render
+// files concurrently and still print them in order.
+#[derive(Default)]
+struct Report {
+    output: Vec<u8>,
+    errors: Vec<String>,
+    summaries: Vec<batch::Summary>,
+}
+
+fn process_file(file: &Path, args: &Args, options: elf::Options, banner: bool) -> Report {
+    let mut report = Report::default();
+
+    if let Err(err) = render_file(file, args, options, banner, &mut report) {
+        report.errors.push(format!("{}: {}", file.display(), err));
+    }
+
+    report
+}
+
+fn process_ldcache(path: &str) -> Report {
+    let mut report = Report::default();
+
+    let result = fs::read(path)
+        .and_then(|data| ldcache::LdCache::from_bytes(&data))
+        .and_then(|cache| cache.to_str(path, &mut report.output));
+    if let Err(err) = result {
+        report.errors.push(format!("{}: {}", path, err));
+    }
+
+    report
+}
+
+fn process_vdso(args: &Args, options: elf::Options) -> Report {
+    let mut report = Report::default();
+
+    if let Err(err) = render_vdso(args, options, &mut report) {
+        report.errors.push(format!("[vdso]: {}", err));
+    }
+
+    report
+}
+
+fn render_vdso(args: &Args, options: elf::Options, report: &mut Report) -> io::Result<()> {
+    let data = procfs::read_vdso()?;
+    if let Some(path) = args.save_vdso.as_ref() {
+        fs::write(path, &data)?;
+    }
+
+    let mut elf = elf::Elf::from_bytes(data, options)?;
+    dump(&mut elf, args, "[vdso]", report)?;
+    report.summaries.push(batch::Summary::new(&elf));
+
+    Ok(())
+}
+
+fn process_pid(pid: u32, args: &Args, options: elf::Options) -> Report {
+    let mut report = Report::default();
+
+    if let Err(err) = render_pid(pid, args, options, &mut report) {
+        report.errors.push(format!("pid {}: {}", pid, err));
+    }
+
+    report
+}
+
+fn render_pid(pid: u32, args: &Args, options: elf::Options, report: &mut Report) -> io::Result<()> {
+    let mut process = procfs::Process::new(pid, options)?;
+    process.to_str(&mut report.output)?;
+
+    // Only name the objects there is something to show for.
+    for object in process.objects.iter_mut() {
+        let len = report.output.len();
+        report.output.write_fmt(format_args!(
+            "\nFile: {} (pid {}, base {:#x})\n",
+            object.path, pid, object.base
+        ))?;
+        let banner_end = report.output.len();
+
+        dump(&mut object.elf, args, &object.path, report)?;
+        // The dumps end in a newline even when none was asked for.
+        if report.output.len() <= banner_end + 1 {
+            report.output.truncate(len);
+        }
+        report.summaries.push(batch::Summary::new(&object.elf));
+    }
+
+    Ok(())
+}
+
+fn render_file(
+    file: &Path,
+    args: &Args,
+    options: elf::Options,
+    banner: bool,
+    report: &mut Report,
+) -> io::Result<()> {
+    if is_archive(file) {
+        let ar = archive::Archive::open(file)?;
+
+        if args.archive_index {
+            ar.to_str(&mut report.output)?;
+        }
+
+        for member in ar.members() {
+            report.output.write_fmt(format_args!(
+                "\nFile: {}({})\n",
+                file.display(),
+                member.name
+            ))?;
+
+            let data = match member.data.as_ref() {
+                Ok(data) => data.clone(),
+                Err(err) => {
+                    report
+                        .errors
+                        .push(format!("{}({}): {}", file.display(), member.name, err));
+                    continue;
+                }
+            };
+            match elf::Elf::from_bytes(data, options) {
+                Ok(mut elf) => {
+                    let name = format!("{}({})", file.display(), member.name);
+                    dump(&mut elf, args, &name, report)?;
+                    report.summaries.push(batch::Summary::new(&elf));
+                }
+                Err(err) => {
+                    report
+                        .errors
+                        .push(format!("{}({}): {}", file.display(), member.name, err))
+                }
+            }
+        }
+    } else {
+        let mut elf = elf::Elf::from_bytes(fs::read(file)?, options)?;
+
+        if banner {
+            report
+                .output
+                .write_fmt(format_args!("\nFile: {}\n", file.display()))?;
+        }
+
+        dump(&mut elf, args, &file.display().to_string(), report)?;
+        report.summaries.push(batch::Summary::new(&elf));
+    }
+
+    Ok(())
+}
+
+// Collect the ELF objects and archives below `dir` in a stable order.
+// Symbolic links are not followed so a sysroot's library aliases are only
+// reported once.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir).and_then(|rd| rd.collect::<io::Result<Vec<_>>>()) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("rself: {}: {}", dir.display(), err);
+            return;
+        }
+    };
+
+    let mut entries: Vec<_> = entries
+        .into_iter()
+        .filter_map(|entry| Some((entry.path(), entry.file_type().ok()?)))
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (path, file_type) in entries {
+        if file_type.is_dir() {
+            walk(&path, files);
+        } else if file_type.is_file() && is_object(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn read_magic(path: &Path) -> Option<[u8; 8]> {
+    let mut magic = [0; 8];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .ok()
+        .map(|_| magic)
+}
+
+fn is_object(path: &Path) -> bool {
+    read_magic(path).is_some_and(|magic| elf::is_elf(&magic) || archive::is_archive(&magic))
+}
+
+fn is_archive<P: AsRef<Path>>(path: P) -> bool {
+    read_magic(path.as_ref()).is_some_and(|magic| archive::is_archive(&magic))
+}
+
+fn dump(elf: &mut elf::Elf, args: &Args, name: &str, report: &mut Report) -> io::Result<()> {
+    let buffer = &mut report.output;
+
+    elf.to_str(buffer)?;
+
+    if args.core || (args.all && elf.e_type() == elf::ET_CORE) {
+        coredump::Core::new(elf)?.to_str(buffer)?;
+    }
+
+    for addr in args.symbolize.iter() {
+        match elf.lookup_symbol(*addr) {
+            Some(sym) => buffer.write_fmt(format_args!(
+                "{:#018x}: {}+{:#x}\n",
+                addr,
+                match args.demangle {
+                    true => demangle::demangle(&sym.name),
+                    false => sym.name.clone(),
+                },
+                addr - sym.value()
+            ))?,
+            None => buffer.write_fmt(format_args!("{:#018x}: ??\n", addr))?,
+        }
+    }
+
+    for &(addr, len) in args.dump_vaddr.iter() {
+        match elf.read_vaddr(addr, len) {
+            Ok(bytes) => {
+                buffer.write_fmt(format_args!(
+                    "\nHex dump of virtual address {:#x} ({} bytes):\n",
+                    addr, len
+                ))?;
+                memory::hexdump(addr, &bytes, buffer)?;
+            }
+            Err(err) => report.errors.push(format!("{}: {}", name, err)),
+        }
+    }
+
+    if args.lint {
+        let errors = lint::to_str(&elf.validate(), buffer)?;
+        if errors > 0 {
+            report
+                .errors
+                .push(format!("{}: {} lint error(s)", name, errors));
+        }
+    }
+
+    Ok(())
+}
+
+fn run_edit(args: &EditArgs) -> io::Result<()> {
+    let elf = elf::Elf::from_bytes(fs::read(&args.file)?, elf::Options::default())?;
+
+    for (name, file) in args.dump_section.iter() {
+        let shdr = elf.section_by_name(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no section named '{}'", name),
+            )
+        })?;
+        if shdr.sh_type == elf::SHT_NOBITS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("section '{}' has no contents", name),
+            ));
+        }
+        fs::write(file, elf.section_data(shdr))?;
+    }
+
+    if !args.modifies() {
+        return Ok(());
+    }
+
+    let mut editor = edit::Editor::new(&elf)?;
+
+    for name in args.remove_needed.iter() {
+        editor.remove_needed(name)?;
+    }
+    for name in args.add_needed.iter() {
+        editor.add_needed(name)?;
+    }
+    if args.remove_rpath {
+        editor.remove_rpath()?;
+    }
+    if let Some(path) = args.set_rpath.as_ref() {
+        editor.set_rpath(path)?;
+    }
+    if let Some(path) = args.set_runpath.as_ref() {
+        editor.set_runpath(path)?;
+    }
+    if let Some(name) = args.set_soname.as_ref() {
+        editor.set_soname(name)?;
+    }
+    if let Some(path) = args.set_interpreter.as_ref() {
+        editor.set_interpreter(path)?;
+    }
+
+    let mut writer = editor.finish()?;
+    for (name, file) in args.update_section.iter() {
+        writer.update_section(name, fs::read(file)?)?;
+    }
+    for (name, file) in args.add_section.iter() {
+        writer.add_section(name, fs::read(file)?)?;
+    }
+
+    let output = args.output.as_ref().unwrap_or(&args.file);
+    write_file(output, &writer.to_bytes()?, &args.file)
+}
+
+fn run_strip(args: &StripArgs) -> io::Result<()> {
+    let elf = elf::Elf::from_bytes(fs::read(&args.file)?, elf::Options::default())?;
+    let mut options = strip::StripOptions {
+        strip_debug: args.strip_debug,
+        strip_all: args.strip_all,
+        only_keep_debug: args.only_keep_debug,
+        remove_sections: args.remove_section.clone(),
+    };
+
+    // Like strip(1), remove everything unless told otherwise.
+    if !options.strip_debug && !options.only_keep_debug && options.remove_sections.is_empty() {
+        options.strip_all = true;
+    }
+
+    let mut writer = writer::ElfWriter::new(&elf)?;
+    writer.strip(&options)?;
+
+    let output = args.output.as_ref().unwrap_or(&args.file);
+    write_file(output, &writer.to_bytes()?, &args.file)
+}
+
+fn run_convert(args: &ConvertArgs) -> io::Result<()> {
+    let elf = elf::Elf::from_bytes(fs::read(&args.file)?, elf::Options::default())?;
+    let mut image = convert::Image::new(&elf)?;
+    let mut out = Vec::new();
+
+    image.restrict(args.start_address, args.stop_address)?;
+    if let Some(fill) = args.gap_fill {
+        image.fill_gaps(fill);
+    }
+
+    match args.output_format {
+        convert::Format::Binary => image.write_binary(args.gap_fill.unwrap_or(0), &mut out)?,
+        convert::Format::Ihex => image.write_ihex(&mut out)?,
+        convert::Format::Srec => {
+            let name = Path::new(&args.output)
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+            image.write_srec(&name, &mut out)?
+        }
+    }
+
+    fs::write(&args.output, out)
+}
+
+fn run_backtrace(args: &BacktraceArgs) -> io::Result<()> {
+    let mut elf = elf::Elf::from_bytes(fs::read(&args.core)?, elf::Options::default())?;
+    let core = coredump::Core::new(&elf)?;
+
+    // The executable is the file mapped at the entry point.
+    let exe_path = core
+        .auxv_value(coredump::AT_ENTRY)
+        .and_then(|entry| core.file_at(entry))
+        .map(|file| file.path.clone());
+
+    // Warn when the executable given is not the one that dumped core, as
+    // the backtrace would then be nonsense.
+    if let (Some(exe), Some(exe_path)) = (args.exe.as_ref(), exe_path.as_ref()) {
+        let start = core
+            .files
+            .iter()
+            .find(|file| &file.path == exe_path && file.offset == 0)
+            .map(|file| file.start);
+        let dumped = start.and_then(|start| elf.dumped_build_id(start));
+        let given = elf::Elf::from_bytes(fs::read(exe)?, elf::Options::default())?.build_id();
+        if let (Some(dumped), Some(given)) = (dumped, given) {
+            if dumped != given {
+                eprintln!(
+                    "rself: warning: {} has build ID {}, but the core was dumped by {}",
+                    exe, given, dumped
+                );
+            }
+        }
+    }
+
+    elf.load_mappings(|path| {
+        if let (Some(exe), Some(exe_path)) = (args.exe.as_ref(), exe_path.as_ref()) {
+            if path == exe_path {
+                return PathBuf::from(exe);
+            }
+        }
+        match args.sysroot.as_ref() {
+            Some(sysroot) => Path::new(sysroot).join(path.trim_start_matches('/')),
+            None => PathBuf::from(path),
+        }
+    });
+
+    let unwinder = backtrace::Unwinder::new(&elf)?;
+    let mut out = BufWriter::new(io::stdout());
+    unwinder.to_str(&core, args.demangle, &mut out)?;
+    out.flush()
+}
+
+fn run_deps(args: &DepsArgs) -> io::Result<()> {
+    let sysroot = args.sysroot.as_ref().map(fs::canonicalize).transpose()?;
+    let search = deps::SearchPath::new(sysroot.as_deref(), args.lib.as_deref());
+    let deps = deps::Dependencies::resolve(Path::new(&args.file), &search)?;
+
+    let mut out = BufWriter::new(io::stdout());
+    deps.to_str(&mut out)?;
+
+    let mut unresolved = 0;
+    let mut unused = 0;
+    if args.check_symbols || args.unused {
+        let bindings = bind::Bindings::new(&deps);
+        if args.check_symbols {
+            bindings.to_str(&deps, args.demangle, &mut out)?;
+            unresolved = bindings.unresolved().count();
+        }
+        if args.unused {
+            let usage = bindings.needed_usage(&deps, 0);
+            bind::write_usage(&usage, &deps, 0, &mut out)?;
+            unused = usage
+                .iter()
+                .filter(|needed| needed.object.is_some() && needed.symbols == 0)
+                .count();
+        }
+    }
+    out.flush()?;
+
+    let missing = deps.missing();
+    if !missing.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("not found: {}", missing.join(", ")),
+        ));
+    }
+    if unresolved > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} unresolved symbol(s)", unresolved),
+        ));
+    }
+    if unused > 0 {
+        return Err(io::Error::other(format!(
+            "{} unused direct dependenc(ies)",
+            unused
+        )));
+    }
+
+    Ok(())
+}
+
+fn run_nm(args: &NmArgs) -> io::Result<()> {
+    let options = nm::Options {
+        format: match args.portability {
+            true => nm::Format::Posix,
+            false => args.format,
+        },
+        sort: match (args.numeric_sort, args.size_sort, args.no_sort) {
+            (true, _, _) => nm::Sort::Numeric,
+            (_, true, _) => nm::Sort::Size,
+            (_, _, true) => nm::Sort::None,
+            _ => nm::Sort::Name,
+        },
+        reverse: args.reverse_sort,
+        dynamic: args.dynamic,
+        defined_only: args.defined_only,
+        undefined_only: args.undefined_only,
+        print_size: args.print_size,
+        demangle: args.demangle,
+    };
+    let banner = args.files.len() > 1;
+    let mut out = BufWriter::new(io::stdout());
+
+    let mut unreadable = 0;
+
+    for file in args.files.iter() {
+        let archive = is_archive(file);
+        let objects = read_objects(file, &mut unreadable);
+
+        if archive && banner && options.format == nm::Format::Bsd {
+            out.write_fmt(format_args!("\n{}:\n", file))?;
+        }
+        for (member, elf) in objects.iter() {
+            let (title, banner) = match (member, options.format) {
+                (Some(member), nm::Format::Bsd) => (member.clone(), true),
+                (Some(member), _) => (format!("{}[{}]", file, member), true),
+                (None, _) => (file.clone(), banner),
+            };
+            match elf.nm_entries(&options) {
+                Some(entries) => nm::write_entries(&entries, &title, banner, &options, &mut out)?,
+                None => eprintln!("rself: {}: no symbols", title),
+            }
+        }
+    }
+
+    out.flush()?;
+    unreadable_members(unreadable)
+}
+
+// The ELF objects of `file`, or of its members by name if it is an
+// archive. Those that cannot be read or are no ELF files are reported and
+// counted in `unreadable`, and the rest still processed, like GNU nm and
+// size do.
+fn read_objects(file: &str, unreadable: &mut usize) -> Vec<(Option<String>, elf::Elf)> {
+    let mut objects = Vec::new();
+    let mut report = |name: String, err: io::Error| {
+        eprintln!("rself: {}: {}", name, err);
+        *unreadable += 1;
+    };
+
+    if !is_archive(file) {
+        match fs::read(file).and_then(|data| elf::Elf::from_bytes(data, elf::Options::default())) {
+            Ok(elf) => objects.push((None, elf)),
+            Err(err) => report(file.to_string(), err),
+        }
+        return objects;
+    }
+
+    let ar = match archive::Archive::open(file) {
+        Ok(ar) => ar,
+        Err(err) => {
+            report(file.to_string(), err);
+            return objects;
+        }
+    };
+    for member in ar.members() {
+        let elf = match member.data.as_ref() {
+            Ok(data) => elf::Elf::from_bytes(data.clone(), elf::Options::default()),
+            Err(err) => Err(io::Error::new(err.kind(), err.to_string())),
+        };
+        match elf {
+            Ok(elf) => objects.push((Some(member.name.clone()), elf)),
+            Err(err) => report(format!("{}({})", file, member.name), err),
+        }
+    }
+
+    objects
+}
+
+// Fail once every file was processed if some were skipped.
+fn unreadable_members(count: usize) -> io::Result<()> {
+    match count {
+        0 => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "{} file(s) or archive member(s) could not be read",
+            count
+        ))),
+    }
+}
+
+fn run_size(args: &SizeArgs) -> io::Result<()> {
+    let format = match (args.sysv, args.berkeley) {
+        (true, _) => size::Format::Sysv,
+        (_, true) => size::Format::Berkeley,
+        _ => args.format,
+    };
+    let radix = match (args.octal, args.decimal, args.hex) {
+        (true, _, _) => 8,
+        (_, true, _) => 10,
+        (_, _, true) => 16,
+        _ => args.radix,
+    };
+    let banner = args.files.len() > 1;
+    let mut totals = size::Berkeley::default();
+    let mut out = BufWriter::new(io::stdout());
+
+    if args.breakdown.is_none() && format == size::Format::Berkeley {
+        size::Berkeley::write_header(radix, &mut out)?;
+    }
+
+    let mut unreadable = 0;
+
+    for file in args.files.iter() {
+        // Archive members are named after the archive they come from.
+        let objects: Vec<(String, Option<&String>, elf::Elf)> = read_objects(file, &mut unreadable)
+            .into_iter()
+            .map(|(member, elf)| match member {
+                Some(member) => (member, Some(file), elf),
+                None => (file.clone(), None, elf),
+            })
+            .collect();
+
+        for (name, archive, elf) in objects.iter() {
+            if let Some(source) = args.breakdown {
+                if banner || archive.is_some() {
+                    out.write_fmt(format_args!("\n{}:\n", name))?;
+                }
+                size::Breakdown::new(elf, source, args.demangle).to_str(args.top, &mut out)?;
+                continue;
+            }
+
+            match (format, archive) {
+                (size::Format::Berkeley, Some(archive)) => {
+                    let title = format!("{} (ex {})", name, archive);
+                    size::Berkeley::new(elf).to_str(&title, radix, &mut out)?
+                }
+                (size::Format::Berkeley, None) => {
+                    size::Berkeley::new(elf).to_str(name, radix, &mut out)?
+                }
+                (size::Format::Sysv, archive) => {
+                    size::write_sysv(elf, name, archive.map(String::as_str), radix, &mut out)?
+                }
+            }
+            totals.add(&size::Berkeley::new(elf));
+        }
+    }
+
+    if args.totals && args.breakdown.is_none() && format == size::Format::Berkeley {
+        totals.to_str("(TOTALS)", radix, &mut out)?;
+    }
+
+    out.flush()?;
+    unreadable_members(unreadable)
+}
+
+// Prefix an error with the file it is about.
+fn with_path(path: &str, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path, err))
+}
+
+// Read and parse one of two files to compare, naming it in errors.
+fn read_elf(path: &str) -> io::Result<elf::Elf> {
+    fs::read(path)
+        .and_then(|data| elf::Elf::from_bytes(data, elf::Options::default()))
+        .map_err(|err| with_path(path, err))
+}
+
+fn run_diff(args: &DiffArgs) -> io::Result<()> {
+    let old = read_elf(&args.old)?;
+    let new = read_elf(&args.new)?;
+    let diff = diff::Diff::new(&args.old, &old, &args.new, &new, args.addresses);
+
+    let mut out = BufWriter::new(io::stdout());
+    match args.json {
+        true => diff.to_json(&mut out)?,
+        false => diff.to_str(&mut out)?,
+    }
+    out.flush()?;
+
+    // Like cmp(1), differing files are no error and print nothing more.
+    match diff.is_empty() {
+        true => Ok(()),
+        false => process::exit(1),
+    }
+}
+
+fn run_abi_diff(args: &AbiDiffArgs) -> io::Result<()> {
+    let old = read_elf(&args.old)?;
+    let new = read_elf(&args.new)?;
+    let old_abi = abi::Interface::new(&old).map_err(|err| with_path(&args.old, err))?;
+    let new_abi = abi::Interface::new(&new).map_err(|err| with_path(&args.new, err))?;
+    let diff = abi::AbiDiff::new(&old_abi, &new_abi);
+
+    let mut out = BufWriter::new(io::stdout());
+    diff.to_str(&args.old, &args.new, args.demangle, &mut out)?;
+    out.flush()?;
+
+    match diff.exit_code() {
+        0 => Ok(()),
+        code => process::exit(code),
+    }
+}
+
+// Write through a temporary file and rename it into place, so that a
+// running executable can be replaced, keeping the permissions of `like`.
+fn write_file(path: &str, data: &[u8], like: &str) -> io::Result<()> {
+    let tmp = format!("{}.rself-{}", path, process::id());
+
+    fs::write(&tmp, data)
+        .and_then(|_| fs::set_permissions(&tmp, fs::metadata(like)?.permissions()))
+        .and_then(|_| fs::rename(&tmp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use crate::coredump::Core;
+use crate::elf::{self, read_struct, Elf, Elf64Ehdr, Elf64Phdr};
+
+/// A file-backed mapping of a core's process, with the file contents when
+/// the file could be read.
+pub struct Mapping {
+    pub start: u64,
+    pub end: u64,
+    pub offset: u64,
+    pub path: String,
+    data: Option<Arc<Vec<u8>>>,
+}
+
+impl Mapping {
+    /// The contents of the whole mapped file, if it could be read.
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_ref().map(|data| data.as_slice())
+    }
+}
+
+fn unmapped(addr: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("address {:#x} is not mapped", addr),
+    )
+}
+
+impl Elf {
+    /// Read `len` bytes of the memory image at virtual address `addr`,
+    /// through the PT_LOAD segments. The part of a segment past `p_filesz`
+    /// reads as zeros, except in core files where file-backed mappings
+    /// the kernel did not dump are read from the original files named by
+    /// NT_FILE, when they are available locally.
+    pub fn read_vaddr(&self, addr: u64, len: usize) -> io::Result<Vec<u8>> {
+        let end = addr.checked_add(len as u64).ok_or_else(|| unmapped(addr))?;
+
+        // The segments covering the range, checked before anything is
+        // allocated for it.
+        let mut spans = Vec::new();
+        let mut cur = addr;
+        while cur < end {
+            let phdr = self
+                .program_headers()
+                .iter()
+                .filter(|phdr| phdr.p_type == elf::PT_LOAD)
+                .find(|phdr| phdr.p_vaddr <= cur && cur - phdr.p_vaddr < phdr.p_memsz)
+                .ok_or_else(|| unmapped(cur))?;
+            let seg_end = end.min(phdr.p_vaddr.saturating_add(phdr.p_memsz));
+            spans.push((phdr, cur, seg_end));
+            cur = seg_end;
+        }
+
+        let mut out = Vec::with_capacity(len);
+        for (phdr, mut cur, seg_end) in spans {
+            let file_end = seg_end.min(phdr.p_vaddr.saturating_add(phdr.p_filesz));
+
+            if cur < file_end {
+                let start = (phdr.p_offset + (cur - phdr.p_vaddr)) as usize;
+                let bytes = self
+                    .data()
+                    .get(start..start + (file_end - cur) as usize)
+                    .ok_or_else(|| unmapped(cur))?;
+                out.extend_from_slice(bytes);
+                cur = file_end;
+            }
+
+            if cur < seg_end {
+                self.read_unsaved(cur, seg_end, &mut out);
+            }
+        }
+
+        Ok(out)
+    }
+
+    // The bytes at `addr` the file itself holds, not those of mapped files.
+    fn read_saved(&self, addr: u64, len: u64) -> Option<&[u8]> {
+        let phdr = self
+            .program_headers()
+            .iter()
+            .filter(|phdr| phdr.p_type == elf::PT_LOAD)
+            .find(|phdr| phdr.p_vaddr <= addr && addr - phdr.p_vaddr < phdr.p_filesz)?;
+        if len > phdr.p_filesz - (addr - phdr.p_vaddr) {
+            return None;
+        }
+
+        let start = (phdr.p_offset + (addr - phdr.p_vaddr)) as usize;
+        self.data().get(start..start + len as usize)
+    }
+
+    /// The GNU build ID of the object mapped at `start` in a core file,
+    /// from the headers and notes the kernel dumped of it, which tells
+    /// whether a local copy of the file is the one that ran.
+    pub fn dumped_build_id(&self, start: u64) -> Option<String> {
+        let page = self.read_saved(start, mem::size_of::<Elf64Ehdr>() as u64)?;
+        if !elf::is_elf(page) {
+            return None;
+        }
+        let ehdr: Elf64Ehdr = read_struct(page, 0).ok()?;
+
+        let size = ehdr.e_phnum as u64 * mem::size_of::<Elf64Phdr>() as u64;
+        let table = self.read_saved(start.checked_add(ehdr.e_phoff)?, size)?;
+        let phdrs: Vec<Elf64Phdr> = (0..ehdr.e_phnum as usize)
+            .filter_map(|i| read_struct(table, i * mem::size_of::<Elf64Phdr>()).ok())
+            .collect();
+        let first = phdrs
+            .iter()
+            .find(|phdr| phdr.p_type == elf::PT_LOAD && phdr.p_offset == 0)?;
+        let bias = start.wrapping_sub(first.p_vaddr & !0xfff);
+
+        let mut notes = Vec::new();
+        for phdr in phdrs.iter().filter(|phdr| phdr.p_type == elf::PT_NOTE) {
+            if let Some(bytes) = self.read_saved(bias.wrapping_add(phdr.p_vaddr), phdr.p_filesz) {
+                elf::read_notes(bytes, phdr.p_align, &mut notes);
+            }
+        }
+
+        notes
+            .into_iter()
+            .find(|note| note.name == "GNU" && note.n_type == elf::NT_GNU_BUILD_ID)
+            .map(|note| note.desc.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    // Memory in `start..end` that the file itself holds no bytes for.
+    fn read_unsaved(&self, start: u64, end: u64, out: &mut Vec<u8>) {
+        let mut cur = start;
+
+        if self.e_type() == elf::ET_CORE {
+            while cur < end {
+                let found = self.mappings().iter().find_map(|m| match &m.data {
+                    Some(data) if m.start <= cur && cur < m.end => Some((m, data)),
+                    _ => None,
+                });
+                let (mapping, data) = match found {
+                    Some(found) => found,
+                    None => break,
+                };
+
+                let stop = end.min(mapping.end);
+                let off = (mapping.offset + (cur - mapping.start)) as usize;
+                let want = (stop - cur) as usize;
+                let bytes = data.get(off..).unwrap_or(&[]);
+                let bytes = &bytes[..want.min(bytes.len())];
+
+                out.extend_from_slice(bytes);
+                out.resize(out.len() + want - bytes.len(), 0);
+                cur = stop;
+            }
+        }
+
+        out.resize(out.len() + (end - cur) as usize, 0);
+    }
+
+    /// The file-backed mappings of a core file, see `read_vaddr`. Their
+    /// files are read from the paths the core names, unless
+    /// `load_mappings` was told otherwise.
+    pub fn mappings(&self) -> &[Mapping] {
+        self.mappings
+            .get_or_init(|| self.read_mappings(|path| Path::new(path).to_path_buf()))
+    }
+
+    /// Read the file of each mapped path from where `resolve` says, e.g.
+    /// below a sysroot, replacing the mappings loaded before.
+    pub fn load_mappings<F>(&mut self, resolve: F)
+    where
+        F: Fn(&str) -> PathBuf,
+    {
+        self.mappings = OnceLock::from(self.read_mappings(resolve));
+    }
+
+    fn read_mappings<F>(&self, resolve: F) -> Vec<Mapping>
+    where
+        F: Fn(&str) -> PathBuf,
+    {
+        let core = match Core::new(self) {
+            Ok(core) => core,
+            Err(_) => return Vec::new(),
+        };
+        let mut files: HashMap<String, Option<Arc<Vec<u8>>>> = HashMap::new();
+
+        core.files
+            .into_iter()
+            .map(|file| {
+                let data = files
+                    .entry(file.path.clone())
+                    .or_insert_with(|| fs::read(resolve(&file.path)).ok().map(Arc::new))
+                    .clone();
+
+                Mapping {
+                    start: file.start,
+                    end: file.end,
+                    offset: file.offset,
+                    path: file.path,
+                    data,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Print `bytes` as found at `addr`, in the layout of `readelf -x`.
+pub fn hexdump(addr: u64, bytes: &[u8], buf: &mut dyn Write) -> io::Result<()> {
+    for (i, line) in bytes.chunks(16).enumerate() {
+        buf.write_fmt(format_args!("  {:#010x} ", addr + i as u64 * 16))?;
+
+        for j in 0..16 {
+            match line.get(j) {
+                Some(b) => buf.write_fmt(format_args!("{:02x}", b))?,
+                None => buf.write_fmt(format_args!("  "))?,
+            }
+            if j % 4 == 3 {
+                buf.write_fmt(format_args!(" "))?;
+            }
+        }
+
+        let text: String = line
+            .iter()
+            .map(|&b| match b {
+                0x20..=0x7e => b as char,
+                _ => '.',
+            })
+            .collect();
+        buf.write_fmt(format_args!("{}\n", text))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,436 @@
+use std::cmp::Ordering;
+use std::io::{self, Write};
+
+use crate::elf::{self, Elf, Symbol};
+
+/// Output formats of `nm -f`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Bsd,
+    Posix,
+    Sysv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "bsd" => Some(Format::Bsd),
+            "posix" => Some(Format::Posix),
+            "sysv" => Some(Format::Sysv),
+            _ => None,
+        }
+    }
+}
+
+/// The order symbols are listed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sort {
+    Name,
+    Numeric,
+    /// By size, leaving out the symbols without one.
+    Size,
+    None,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    pub format: Format,
+    pub sort: Sort,
+    pub reverse: bool,
+    /// List .dynsym rather than .symtab.
+    pub dynamic: bool,
+    pub defined_only: bool,
+    pub undefined_only: bool,
+    /// Print the size of defined symbols in BSD format.
+    pub print_size: bool,
+    pub demangle: bool,
+}
+
+/// A symbol as nm lists it.
+pub struct Entry {
+    pub name: String,
+    pub value: u64,
+    pub size: u64,
+    /// The nm type letter.
+    pub letter: char,
+    pub kind: u8,
+    /// The defining section, or *UND*, *ABS* or *COM*.
+    pub section: String,
+    pub defined: bool,
+}
+
+impl Elf {
+    /// The nm type letter of `symbol`: what kind of section defines it,
+    /// lowercase for local symbols.
+    pub fn symbol_letter(&self, symbol: &Symbol) -> char {
+        let bind = symbol.bind();
+        let object = symbol.kind() == elf::STT_OBJECT;
+
+        if !symbol.is_defined() {
+            return match (bind, object) {
+                (elf::STB_WEAK, true) => 'v',
+                (elf::STB_WEAK, false) => 'w',
+                _ => 'U',
+            };
+        }
+        if symbol.kind() == elf::STT_GNU_IFUNC {
+            return 'i';
+        }
+        match (bind, object) {
+            (elf::STB_WEAK, true) => return 'V',
+            (elf::STB_WEAK, false) => return 'W',
+            (elf::STB_GNU_UNIQUE, _) => return 'u',
+            _ => {}
+        }
+
+        let letter = match symbol.sym.st_shndx {
+            elf::SHN_ABS => 'a',
+            elf::SHN_COMMON => 'c',
+            _ => match self.section_headers().get(symbol.shndx() as usize) {
+                Some(shdr) if shdr.sh_flags as u32 & elf::SHF_ALLOC == 0 => {
+                    match self.section_name(shdr).starts_with(".debug") {
+                        true => 'N',
+                        false => 'n',
+                    }
+                }
+                Some(shdr) if shdr.sh_type == elf::SHT_NOBITS => 'b',
+                Some(shdr) if shdr.sh_flags as u32 & elf::SHF_EXECINSTR != 0 => 't',
+                Some(shdr) if shdr.sh_flags as u32 & elf::SHF_WRITE != 0 => 'd',
+                Some(_) => 'r',
+                None => '?',
+            },
+        };
+
+        match bind {
+            elf::STB_LOCAL => letter,
+            _ => letter.to_ascii_uppercase(),
+        }
+    }
+
+    // The name of the section a symbol is defined in, as nm -f sysv shows
+    // it.
+    fn symbol_section(&self, symbol: &Symbol) -> String {
+        match symbol.sym.st_shndx {
+            elf::SHN_UNDEF => String::from("*UND*"),
+            elf::SHN_ABS => String::from("*ABS*"),
+            elf::SHN_COMMON => String::from("*COM*"),
+            _ => self
+                .section_headers()
+                .get(symbol.shndx() as usize)
+                .map_or(String::new(), |shdr| self.section_name(shdr)),
+        }
+    }
+
+    /// The symbols nm lists, filtered and sorted as `options` ask, or None
+    /// if the file has no symbol table to list them from. Dynamic symbols
+    /// are named with their version.
+    pub fn nm_entries(&self, options: &Options) -> Option<Vec<Entry>> {
+        let symbols: Vec<(&Symbol, String)> = match options.dynamic {
+            true => self
+                .dynamic_symbols()
+                .into_iter()
+                .map(|sym| {
+                    // Version definitions are symbols named after
+                    // themselves.
+                    let name = match sym.version {
+                        Some(version) if version.name == sym.symbol.name => version.name,
+                        // Only definitions of the object's own versions
+                        // are default ones: a copy-relocated variable is
+                        // defined here with the version it needs.
+                        Some(version)
+                            if sym.symbol.is_defined()
+                                && version.file.is_none()
+                                && !version.hidden =>
+                        {
+                            format!("{}@@{}", sym.symbol.name, version.name)
+                        }
+                        Some(version) => format!("{}@{}", sym.symbol.name, version.name),
+                        None => sym.symbol.name.clone(),
+                    };
+                    (sym.symbol, name)
+                })
+                .collect(),
+            false => self
+                .section_headers()
+                .iter()
+                .find(|shdr| shdr.sh_type == elf::SHT_SYMTAB)
+                .and_then(|shdr| {
+                    let name = self.section_name(shdr);
+                    self.symbol_tables().iter().find(|table| table.name == name)
+                })
+                .map_or_else(Vec::new, |table| {
+                    table
+                        .symbols
+                        .iter()
+                        .map(|symbol| (symbol, symbol.name.clone()))
+                        .collect()
+                }),
+        };
+
+        // Section and file symbols are only of interest to debuggers.
+        let mut entries: Vec<Entry> = symbols
+            .into_iter()
+            .filter(|(symbol, _)| {
+                !symbol.name.is_empty()
+                    && symbol.kind() != elf::STT_SECTION
+                    && symbol.kind() != elf::STT_FILE
+            })
+            .map(|(symbol, name)| Entry {
+                name,
+                value: symbol.value(),
+                size: symbol.size(),
+                letter: self.symbol_letter(symbol),
+                kind: symbol.kind(),
+                section: self.symbol_section(symbol),
+                defined: symbol.is_defined(),
+            })
+            .collect();
+        if entries.is_empty() {
+            return None;
+        }
+
+        entries.retain(|entry| match entry.defined {
+            true => !options.undefined_only && (options.sort != Sort::Size || entry.size != 0),
+            false => !options.defined_only && options.sort != Sort::Size,
+        });
+
+        // Versions of the same symbol stay in symbol table order.
+        let base = |entry: &Entry| entry.name.split('@').next().unwrap_or("").to_string();
+        let by_name = |a: &Entry, b: &Entry| base(a).cmp(&base(b));
+        let compare = |a: &Entry, b: &Entry| match options.sort {
+            Sort::Name => by_name(a, b),
+            // Undefined symbols have no value and come first.
+            Sort::Numeric => (a.defined, a.value)
+                .cmp(&(b.defined, b.value))
+                .then_with(|| by_name(a, b)),
+            Sort::Size => a.size.cmp(&b.size).then_with(|| by_name(a, b)),
+            Sort::None => Ordering::Equal,
+        };
+        match options.reverse {
+            true => entries.sort_by(|a, b| compare(b, a)),
+            false => entries.sort_by(compare),
+        }
+
+        Some(entries)
+    }
+}
+
+/// Print `entries` in the format of `options`. `title` names the file or
+/// archive member they belong to, and is shown if `banner` is set or the
+/// format always shows it.
+pub fn write_entries(
+    entries: &[Entry],
+    title: &str,
+    banner: bool,
+    options: &Options,
+    buf: &mut dyn Write,
+) -> io::Result<()> {
+    match options.format {
+        Format::Bsd if banner => buf.write_fmt(format_args!("\n{}:\n", title))?,
+        Format::Posix if banner => buf.write_fmt(format_args!("{}:\n", title))?,
+        Format::Sysv => buf.write_fmt(format_args!(
+            "\n\nSymbols from {}:\n\n{:<22}{:<16}{:<13}{:<13}{:<17}{:<6}{}\n\n",
+            title, "Name", "Value", "Class", "Type", "Size", "Line", "Section"
+        ))?,
+        _ => {}
+    }
+
+    for entry in entries.iter() {
+        match options.format {
+            Format::Bsd => write_bsd(entry, options, buf)?,
+            Format::Posix => write_posix(entry, options, buf)?,
+            Format::Sysv => write_sysv(entry, options, buf)?,
+        }
+    }
+
+    Ok(())
+}
+
+// The name of an entry as printed, demangled if asked to. Symbols are
+// sorted by their mangled names, and a version is kept as it is.
+fn display_name(entry: &Entry, options: &Options) -> String {
+    match (options.demangle, entry.name.find('@')) {
+        (false, _) => entry.name.clone(),
+        (true, Some(at)) => format!(
+            "{}{}",
+            crate::demangle::demangle(&entry.name[..at]),
+            &entry.name[at..]
+        ),
+        (true, None) => crate::demangle::demangle(&entry.name),
+    }
+}
+
+fn write_bsd(entry: &Entry, options: &Options, buf: &mut dyn Write) -> io::Result<()> {
+    // Sorting by size shows the size in place of the value.
+    let value = match options.sort {
+        Sort::Size if !options.print_size => entry.size,
+        _ => entry.value,
+    };
+
+    match (entry.defined, options.print_size && entry.size != 0) {
+        (false, _) => buf.write_fmt(format_args!("{:16}", ""))?,
+        (true, false) => buf.write_fmt(format_args!("{:016x}", value))?,
+        (true, true) => buf.write_fmt(format_args!("{:016x} {:016x}", value, entry.size))?,
+    }
+    buf.write_fmt(format_args!(
+        " {} {}\n",
+        entry.letter,
+        display_name(entry, options)
+    ))
+}
+
+fn write_posix(entry: &Entry, options: &Options, buf: &mut dyn Write) -> io::Result<()> {
+    buf.write_fmt(format_args!(
+        "{} {} ",
+        display_name(entry, options),
+        entry.letter
+    ))?;
+
+    match (entry.defined, entry.size) {
+        (false, _) => buf.write_fmt(format_args!("{:8}\n", "")),
+        (true, 0) => buf.write_fmt(format_args!("{:x} \n", entry.value)),
+        (true, size) => buf.write_fmt(format_args!("{:x} {:x}\n", entry.value, size)),
+    }
+}
+
+fn write_sysv(entry: &Entry, options: &Options, buf: &mut dyn Write) -> io::Result<()> {
+    let value = match entry.defined {
+        true => format!("{:016x}", entry.value),
+        false => String::new(),
+    };
+    let size = match entry.size {
+        0 => String::new(),
+        size => format!("{:016x}", size),
+    };
+    let kind = elf::ELF_ST_TYPE
+        .get(&entry.kind)
+        .map_or_else(|| entry.kind.to_string(), |name| name.to_string());
+
+    buf.write_fmt(format_args!(
+        "{:<20}|{:16}|   {}  |{:>18}|{:16}|{:5}|{}\n",
+        display_name(entry, options),
+        value,
+        entry.letter,
+        kind,
+        size,
+        "",
+        entry.section
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::Scratch;
+
+    fn options(dynamic: bool) -> Options {
+        Options {
+            format: Format::Bsd,
+            sort: Sort::Name,
+            reverse: false,
+            dynamic,
+            defined_only: false,
+            undefined_only: false,
+            print_size: false,
+            demangle: false,
+        }
+    }
+
+    fn letters(elf: &Elf, dynamic: bool) -> Vec<(String, char)> {
+        elf.nm_entries(&options(dynamic))
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.letter))
+            .collect()
+    }
+
+    #[test]
+    fn type_letters() {
+        let scratch = Scratch::new("nm-letters");
+        scratch.write(
+            "t.c",
+            "int data = 1;\nint bss = 0;\nint common;\nconst int rodata = 2;\n\
+             static int local_data = 3;\nstatic int local_text(void) { return local_data; }\n\
+             int text(void) { return local_text() + rodata; }\n\
+             __attribute__((weak)) int weak_text(void) { return 0; }\n\
+             __attribute__((weak)) int weak_data = 4;\n\
+             extern int undefined(void);\nextern int weak_undefined(void) __attribute__((weak));\n\
+             static int impl(void) { return 0; }\n\
+             static void *resolve(void) { return impl; }\n\
+             int ifunc(void) __attribute__((ifunc(\"resolve\")));\n\
+             int use(void) { return undefined() + weak_undefined(); }\n",
+        );
+        let elf = Elf::from_bytes(
+            scratch.cc("t.o", &["-c", "-fcommon", "t.c"]),
+            elf::Options::default(),
+        )
+        .unwrap();
+        let letters = letters(&elf, false);
+
+        for (name, letter) in [
+            ("data", 'D'),
+            ("bss", 'B'),
+            ("common", 'C'),
+            ("rodata", 'R'),
+            ("local_data", 'd'),
+            ("local_text", 't'),
+            ("text", 'T'),
+            ("weak_text", 'W'),
+            ("weak_data", 'V'),
+            ("undefined", 'U'),
+            ("weak_undefined", 'w'),
+            ("ifunc", 'i'),
+        ] {
+            assert!(
+                letters.contains(&(name.to_string(), letter)),
+                "{} is not {} in {:?}",
+                name,
+                letter,
+                letters
+            );
+        }
+    }
+
+    #[test]
+    fn version_suffixes() {
+        let scratch = Scratch::new("nm-versions");
+        scratch.write(
+            "v.c",
+            "int var = 1;\nint old_f(void) { return 1; }\nint new_f(void) { return 2; }\n\
+             __asm__(\".symver old_f, f@V1\");\n__asm__(\".symver new_f, f@@V2\");\n",
+        );
+        scratch.write(
+            "v.map",
+            "V1 { global: var; f; local: *; };\nV2 { global: f; } V1;\n",
+        );
+        scratch.write(
+            "m.c",
+            "extern int var;\nint f(void);\nint main(void) { return var + f(); }\n",
+        );
+        let lib = Elf::from_bytes(
+            scratch.cc(
+                "libv.so",
+                &["-shared", "-fPIC", "-Wl,--version-script=v.map", "v.c"],
+            ),
+            elf::Options::default(),
+        )
+        .unwrap();
+        let exe = Elf::from_bytes(
+            scratch.cc("m", &["-no-pie", "m.c", "-L.", "-lv"]),
+            elf::Options::default(),
+        )
+        .unwrap();
+
+        let lib = letters(&lib, true);
+        for entry in [("V1", 'A'), ("var@@V1", 'D'), ("f@V1", 'T'), ("f@@V2", 'T')] {
+            assert!(lib.contains(&(entry.0.to_string(), entry.1)), "{:?}", lib);
+        }
+
+        // The copy of var is defined in the executable, but with the
+        // version it needs from the library.
+        let exe = letters(&exe, true);
+        for entry in [("var@V1", 'B'), ("f@V2", 'U')] {
+            assert!(exe.contains(&(entry.0.to_string(), entry.1)), "{:?}", exe);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::coredump;
+use crate::elf::{self, Elf, Options};
+
+/// A line of `/proc/<pid>/maps`.
+pub struct MapEntry {
+    pub start: u64,
+    pub end: u64,
+    pub perms: String,
+    pub offset: u64,
+    pub inode: u64,
+    /// The mapped file, or a pseudo-path like `[stack]`, or empty for
+    /// anonymous memory.
+    pub path: String,
+}
+
+/// An ELF object mapped into a live process.
+pub struct LoadedObject {
+    pub path: String,
+    pub start: u64,
+    pub end: u64,
+    /// What the object's addresses are relocated by.
+    pub base: u64,
+    /// Whether the file was deleted or replaced since it was mapped; its
+    /// contents are still those of the mapped file.
+    pub deleted: bool,
+    pub elf: Elf,
+}
+
+/// The objects a running process has mapped, read through
+/// `/proc/<pid>/map_files` so that files replaced on disk since are seen
+/// as the process sees them.
+pub struct Process {
+    pub pid: u32,
+    pub exe: Option<String>,
+    pub objects: Vec<LoadedObject>,
+}
+
+const DELETED: &str = " (deleted)";
+
+fn parse_hex(s: &str) -> io::Result<u64> {
+    u64::from_str_radix(s, 16).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn read_maps(pid: u32) -> io::Result<Vec<MapEntry>> {
+    let maps = fs::read_to_string(format!("/proc/{}/maps", pid))?;
+
+    maps.lines()
+        .map(|line| {
+            let mut fields = line.splitn(6, ' ');
+            let mut next = || fields.next().unwrap_or("");
+            let (range, perms, offset, _dev, inode) = (next(), next(), next(), next(), next());
+            let path = next().trim_start();
+            let (start, end) = range.split_once('-').ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("bad maps line '{}'", line),
+                )
+            })?;
+
+            Ok(MapEntry {
+                start: parse_hex(start)?,
+                end: parse_hex(end)?,
+                perms: perms.to_string(),
+                offset: parse_hex(offset)?,
+                inode: inode.parse().unwrap_or(0),
+                path: path.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Copy the vDSO the kernel maps into this process out of memory. It is
+/// found through AT_SYSINFO_EHDR and sized by its `[vdso]` mapping.
+pub fn read_vdso() -> io::Result<Vec<u8>> {
+    let auxv = coredump::parse_auxv(&fs::read("/proc/self/auxv")?);
+    let addr = auxv
+        .iter()
+        .find(|&&(a_type, _)| a_type == coredump::AT_SYSINFO_EHDR)
+        .map(|&(_, value)| value)
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no vDSO in the auxiliary vector")
+        })?;
+
+    let maps = read_maps(std::process::id())?;
+    let entry = maps
+        .iter()
+        .find(|entry| entry.start == addr)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("vDSO address {:#x} is not mapped", addr),
+            )
+        })?;
+
+    let mut data = vec![0; (entry.end - entry.start) as usize];
+    let mut mem = File::open("/proc/self/mem")?;
+    mem.seek(SeekFrom::Start(addr))?;
+    mem.read_exact(&mut data)?;
+
+    Ok(data)
+}
+
+impl Process {
+    /// Parse the objects mapped by process `pid`, with `options` for their
+    /// dumps. Mapped files that are not ELF objects are left out.
+    pub fn new(pid: u32, options: Options) -> io::Result<Process> {
+        let maps = read_maps(pid)?;
+        let exe = fs::read_link(format!("/proc/{}/exe", pid))
+            .ok()
+            .map(|path| path.to_string_lossy().into_owned());
+        let mut objects: Vec<LoadedObject> = Vec::new();
+
+        for entry in maps.iter().filter(|entry| entry.inode != 0) {
+            let path = entry.path.strip_suffix(DELETED).unwrap_or(&entry.path);
+            if let Some(object) = objects.iter_mut().find(|object| object.path == path) {
+                object.start = object.start.min(entry.start);
+                object.end = object.end.max(entry.end);
+                continue;
+            }
+            if entry.offset != 0 {
+                continue;
+            }
+
+            // The map_files link opens the mapped file even when its path
+            // now names another one, or none at all.
+            let link = format!("/proc/{}/map_files/{:x}-{:x}", pid, entry.start, entry.end);
+            let data = match fs::read(link).or_else(|_| fs::read(path)) {
+                Ok(data) if elf::is_elf(&data) => data,
+                _ => continue,
+            };
+            let elf = match Elf::from_bytes(data, options) {
+                Ok(elf) => elf,
+                Err(_) => continue,
+            };
+
+            let first = elf
+                .program_headers()
+                .iter()
+                .find(|phdr| phdr.p_type == elf::PT_LOAD && phdr.p_offset == 0);
+            let base = match first {
+                Some(phdr) => entry.start.wrapping_sub(phdr.p_vaddr & !0xfff),
+                None => entry.start,
+            };
+
+            objects.push(LoadedObject {
+                path: path.to_string(),
+                start: entry.start,
+                end: entry.end,
+                base,
+                deleted: entry.path.ends_with(DELETED),
+                elf,
+            });
+        }
+
+        Ok(Process { pid, exe, objects })
+    }
+
+    pub fn to_str(&self, buf: &mut dyn Write) -> io::Result<()> {
+        buf.write_fmt(format_args!("Process {}", self.pid))?;
+        if let Some(exe) = self.exe.as_ref() {
+            buf.write_fmt(format_args!(" ({})", exe))?;
+        }
+        buf.write_fmt(format_args!(":\n"))?;
+        buf.write_fmt(format_args!(
+            "  {:<14} {:<14} {:<14} {:<40} {}\n",
+            "Start", "End", "Load base", "Build ID", "Path"
+        ))?;
+
+        for object in self.objects.iter() {
+            buf.write_fmt(format_args!(
+                "  {:#014x} {:#014x} {:#014x} {:<40} {}",
+                object.start,
+                object.end,
+                object.base,
+                object.elf.build_id().unwrap_or_else(|| "-".to_string()),
+                object.path
+            ))?;
+            if object.deleted {
+                buf.write_fmt(format_args!("{}", DELETED))?;
+            }
+            buf.write_fmt(format_args!("\n"))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::mem;
+
+use crate::elf::{self, Elf, Elf64Ehdr, Elf64Phdr, Elf64Shdr};
+
+/// Output formats of `size`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Berkeley,
+    Sysv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "berkeley" => Some(Format::Berkeley),
+            "sysv" => Some(Format::Sysv),
+            _ => None,
+        }
+    }
+}
+
+/// What a size breakdown attributes the bytes of a file to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Sections,
+    Segments,
+    Symbols,
+}
+
+impl Source {
+    pub fn from_name(name: &str) -> Option<Source> {
+        match name {
+            "sections" => Some(Source::Sections),
+            "segments" => Some(Source::Segments),
+            "symbols" => Some(Source::Symbols),
+            _ => None,
+        }
+    }
+}
+
+// A number in radix 8, 10 or 16, with a "0" or "0x" prefix.
+fn radix_str(value: u64, radix: u32) -> String {
+    match radix {
+        8 => format!("0{:o}", value),
+        16 => format!("{:#x}", value),
+        _ => value.to_string(),
+    }
+}
+
+/// The sizes of the allocated sections by kind, as `size -B` adds them up:
+/// code and read-only data, writable data, and zero-initialized data.
+#[derive(Clone, Copy, Default)]
+pub struct Berkeley {
+    pub text: u64,
+    pub data: u64,
+    pub bss: u64,
+}
+
+impl Berkeley {
+    pub fn new(elf: &Elf) -> Berkeley {
+        let mut sizes = Berkeley::default();
+
+        for shdr in elf.section_headers().iter() {
+            let flags = shdr.sh_flags as u32;
+            if flags & elf::SHF_ALLOC == 0 {
+                continue;
+            }
+
+            if flags & elf::SHF_EXECINSTR != 0 || flags & elf::SHF_WRITE == 0 {
+                sizes.text += shdr.sh_size;
+            } else if shdr.sh_type != elf::SHT_NOBITS {
+                sizes.data += shdr.sh_size;
+            } else {
+                sizes.bss += shdr.sh_size;
+            }
+        }
+
+        sizes
+    }
+
+    pub fn add(&mut self, other: &Berkeley) {
+        self.text += other.text;
+        self.data += other.data;
+        self.bss += other.bss;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.text + self.data + self.bss
+    }
+
+    /// Print the table header. The total is shown in octal rather than
+    /// decimal for radix 8.
+    pub fn write_header(radix: u32, buf: &mut dyn Write) -> io::Result<()> {
+        buf.write_fmt(format_args!(
+            "{:>7}\t{:>7}\t{:>7}\t{:>7}\t{:>7}\t{}\n",
+            "text",
+            "data",
+            "bss",
+            if radix == 8 { "oct" } else { "dec" },
+            "hex",
+            "filename"
+        ))
+    }
+
+    /// Print a line of the table, the total in decimal, or octal for radix
+    /// 8, and hex, and the rest in `radix`.
+    pub fn to_str(&self, name: &str, radix: u32, buf: &mut dyn Write) -> io::Result<()> {
+        let total = match radix {
+            8 => format!("{:o}", self.total()),
+            _ => self.total().to_string(),
+        };
+
+        buf.write_fmt(format_args!(
+            "{:>7}\t{:>7}\t{:>7}\t{:>7}\t{:>7x}\t{}\n",
+            radix_str(self.text, radix),
+            radix_str(self.data, radix),
+            radix_str(self.bss, radix),
+            total,
+            self.total(),
+            name
+        ))
+    }
+}
+
+/// Print the size and address of each section, like `size -A`, naming
+/// the archive the object comes from if any. Symbol
+/// and string tables and the relocations of a relocatable file are left
+/// out, they are not sections to the linker.
+pub fn write_sysv(
+    elf: &Elf,
+    name: &str,
+    archive: Option<&str>,
+    radix: u32,
+    buf: &mut dyn Write,
+) -> io::Result<()> {
+    let shdrs: Vec<&Elf64Shdr> = elf
+        .section_headers()
+        .iter()
+        .filter(|shdr| match shdr.sh_type {
+            elf::SHT_NULL | elf::SHT_SYMTAB | elf::SHT_SYMTAB_SHNDX | elf::SHT_GROUP => false,
+            elf::SHT_STRTAB | elf::SHT_REL | elf::SHT_RELA => {
+                shdr.sh_flags as u32 & elf::SHF_ALLOC != 0
+            }
+            _ => true,
+        })
+        .collect();
+    let sections: Vec<(String, String, String)> = shdrs
+        .iter()
+        .map(|shdr| {
+            (
+                elf.section_name(shdr),
+                radix_str(shdr.sh_size, radix),
+                radix_str(shdr.sh_addr, radix),
+            )
+        })
+        .collect();
+    let total: u64 = shdrs.iter().map(|shdr| shdr.sh_size).sum();
+    let total = radix_str(total, radix);
+
+    let name_width = sections
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .chain([7])
+        .max()
+        .unwrap_or(0);
+    let size_width = sections
+        .iter()
+        .map(|(_, size, _)| size.len())
+        .chain([4, total.len()])
+        .max()
+        .unwrap_or(0);
+    let addr_width = sections
+        .iter()
+        .map(|(_, _, addr)| addr.len())
+        .chain([4])
+        .max()
+        .unwrap_or(0);
+
+    buf.write_fmt(format_args!("{}  ", name))?;
+    if let Some(archive) = archive {
+        buf.write_fmt(format_args!(" (ex {})", archive))?;
+    }
+    buf.write_fmt(format_args!(":\n"))?;
+    buf.write_fmt(format_args!(
+        "{:<3$}   {:>4$}   {:>5$}\n",
+        "section", "size", "addr", name_width, size_width, addr_width
+    ))?;
+    for (name, size, addr) in sections.iter() {
+        buf.write_fmt(format_args!(
+            "{:<3$}   {:>4$}   {:>5$}\n",
+            name, size, addr, name_width, size_width, addr_width
+        ))?;
+    }
+    buf.write_fmt(format_args!(
+        "{:<2$}   {:>3$}\n\n\n",
+        "Total", total, name_width, size_width
+    ))
+}
+
+/// Bytes of the file and of the memory image attributed to one thing.
+pub struct Row {
+    pub name: String,
+    pub file_size: u64,
+    pub vm_size: u64,
+}
+
+/// Where the bytes of a file go, in the file and once loaded, in the
+/// manner of bloaty. Bytes of a loadable segment the source attributes to
+/// nothing show up as "[LOAD #n padding]", and those no segment maps as
+/// "[Unmapped]", so that the rows add up to the totals.
+pub struct Breakdown {
+    pub rows: Vec<Row>,
+    pub file_size: u64,
+    pub vm_size: u64,
+}
+
+// The file offsets and sizes of the ELF header and the header tables.
+fn header_ranges(elf: &Elf) -> [(u64, u64); 3] {
+    let ehdr = elf.header();
+    [
+        (0, mem::size_of::<Elf64Ehdr>() as u64),
+        (ehdr.e_phoff, mem::size_of_val(elf.program_headers()) as u64),
+        (ehdr.e_shoff, mem::size_of_val(elf.section_headers()) as u64),
+    ]
+}
+
+// How many bytes two ranges, given by start and size, have in common.
+fn overlap((start, size): (u64, u64), (other_start, other_size): (u64, u64)) -> u64 {
+    let end = start
+        .saturating_add(size)
+        .min(other_start.saturating_add(other_size));
+    end.saturating_sub(start.max(other_start))
+}
+
+// The loadable segments, with their index among the program headers.
+fn loads(elf: &Elf) -> Vec<(usize, &Elf64Phdr)> {
+    elf.program_headers()
+        .iter()
+        .enumerate()
+        .filter(|(_, phdr)| phdr.p_type == elf::PT_LOAD)
+        .collect()
+}
+
+// The headers, which take memory too when a segment maps them, as the
+// first one usually does.
+fn headers_row(elf: &Elf) -> Row {
+    let ranges = header_ranges(elf);
+    let mapped = loads(elf)
+        .iter()
+        .flat_map(|(_, phdr)| {
+            ranges
+                .iter()
+                .map(move |range| overlap(*range, (phdr.p_offset, phdr.p_filesz)))
+        })
+        .sum();
+
+    Row {
+        name: String::from("[ELF Headers]"),
+        file_size: ranges.iter().map(|(_, size)| size).sum(),
+        vm_size: mapped,
+    }
+}
+
+// What a loadable segment holds besides the headers and sections in it:
+// alignment gaps between them, and whatever the linker put there.
+fn padding_row(elf: &Elf, index: usize, phdr: &Elf64Phdr) -> Row {
+    let file_range = (phdr.p_offset, phdr.p_filesz);
+    let headers: u64 = header_ranges(elf)
+        .iter()
+        .map(|range| overlap(*range, file_range))
+        .sum();
+    let mut file_used = headers;
+    let mut vm_used = headers;
+
+    for shdr in elf.section_headers() {
+        let (file_size, vm_size) = section_sizes(shdr);
+        file_used += overlap((shdr.sh_offset, file_size), file_range);
+        // .tbss is only a template size, it takes no room in the segment.
+        if shdr.sh_type == elf::SHT_NOBITS && shdr.sh_flags as u32 & elf::SHF_TLS != 0 {
+            continue;
+        }
+        vm_used += overlap((shdr.sh_addr, vm_size), (phdr.p_vaddr, phdr.p_memsz));
+    }
+
+    Row {
+        name: format!("[LOAD #{} padding]", index),
+        file_size: phdr.p_filesz.saturating_sub(file_used),
+        vm_size: phdr.p_memsz.saturating_sub(vm_used),
+    }
+}
+
+// The sizes of a section in the file and in memory.
+fn section_sizes(shdr: &Elf64Shdr) -> (u64, u64) {
+    let file_size = match shdr.sh_type {
+        elf::SHT_NOBITS => 0,
+        _ => shdr.sh_size,
+    };
+    let vm_size = match shdr.sh_flags as u32 & elf::SHF_ALLOC {
+        0 => 0,
+        _ => shdr.sh_size,
+    };
+
+    (file_size, vm_size)
+}
+
+impl Breakdown {
+    /// Attribute the bytes of `elf` to its sections, loadable segments or
+    /// symbols, with symbol names demangled if `demangle` is set.
+    pub fn new(elf: &Elf, source: Source, demangle: bool) -> Breakdown {
+        let loads = loads(elf);
+        // Relocatable files have no segments, what they will take once
+        // loaded is the size of their allocated sections.
+        let vm_size = match loads.is_empty() {
+            true => elf
+                .section_headers()
+                .iter()
+                .map(|s| section_sizes(s).1)
+                .sum(),
+            false => loads.iter().map(|(_, phdr)| phdr.p_memsz).sum(),
+        };
+
+        let mut rows = match source {
+            Source::Sections => Breakdown::sections(elf),
+            Source::Segments => loads
+                .iter()
+                .map(|(i, phdr)| Row {
+                    name: format!("LOAD #{} [{}]", i, phdr.flags_str()),
+                    file_size: phdr.p_filesz,
+                    vm_size: phdr.p_memsz,
+                })
+                .collect(),
+            Source::Symbols => Breakdown::symbols(elf, demangle),
+        };
+        if source != Source::Segments {
+            rows.extend(loads.iter().map(|(i, phdr)| padding_row(elf, *i, phdr)));
+        }
+
+        let mut breakdown = Breakdown {
+            rows: Vec::new(),
+            file_size: elf.data().len() as u64,
+            vm_size,
+        };
+        let file_used: u64 = rows.iter().map(|row| row.file_size).sum();
+        let vm_used: u64 = rows.iter().map(|row| row.vm_size).sum();
+        rows.push(Row {
+            name: String::from("[Unmapped]"),
+            file_size: breakdown.file_size.saturating_sub(file_used),
+            vm_size: breakdown.vm_size.saturating_sub(vm_used),
+        });
+
+        // The largest first, by whichever of its sizes is larger.
+        rows.retain(|row| row.file_size != 0 || row.vm_size != 0);
+        rows.sort_by(|a, b| {
+            b.file_size
+                .max(b.vm_size)
+                .cmp(&a.file_size.max(a.vm_size))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        breakdown.rows = rows;
+
+        breakdown
+    }
+
+    fn sections(elf: &Elf) -> Vec<Row> {
+        let mut rows: Vec<Row> = elf
+            .section_headers()
+            .iter()
+            .filter(|shdr| shdr.sh_type != elf::SHT_NULL)
+            .map(|shdr| {
+                let (file_size, vm_size) = section_sizes(shdr);
+                Row {
+                    name: elf.section_name(shdr),
+                    file_size,
+                    vm_size,
+                }
+            })
+            .collect();
+
+        rows.push(headers_row(elf));
+
+        rows
+    }
+
+    // The sized symbols of .symtab, or of .dynsym if the file is stripped,
+    // and what is left of each section outside them.
+    fn symbols(elf: &Elf, demangle: bool) -> Vec<Row> {
+        let tables = elf.symbol_tables();
+        let table = tables
+            .iter()
+            .find(|table| table.name == ".symtab")
+            .or_else(|| tables.iter().find(|table| table.name == ".dynsym"));
+        let shdrs = elf.section_headers();
+
+        let mut rows: Vec<Row> = Vec::new();
+        let mut covered = vec![0; shdrs.len()];
+        // Aliases of the same bytes are counted once, under the first name.
+        let mut seen = HashSet::new();
+
+        for symbol in table.iter().flat_map(|table| table.symbols.iter()) {
+            let index = symbol.shndx() as usize;
+            let shdr = match shdrs.get(index) {
+                Some(shdr) if symbol.is_defined() && symbol.size() != 0 => shdr,
+                _ => continue,
+            };
+            if !seen.insert((index, symbol.value())) {
+                continue;
+            }
+
+            let (file_size, vm_size) = section_sizes(shdr);
+            covered[index] += symbol.size();
+            rows.push(Row {
+                name: match demangle {
+                    true => crate::demangle::demangle(&symbol.name),
+                    false => symbol.name.clone(),
+                },
+                file_size: symbol.size().min(file_size),
+                vm_size: symbol.size().min(vm_size),
+            });
+        }
+
+        for (shdr, covered) in shdrs.iter().zip(covered) {
+            if shdr.sh_type == elf::SHT_NULL {
+                continue;
+            }
+            let (file_size, vm_size) = section_sizes(shdr);
+            rows.push(Row {
+                name: format!("[section {}]", elf.section_name(shdr)),
+                file_size: file_size.saturating_sub(covered),
+                vm_size: vm_size.saturating_sub(covered),
+            });
+        }
+
+        rows.push(headers_row(elf));
+
+        rows
+    }
+
+    /// Print the `top` largest rows, the others summed up in one, and the
+    /// totals.
+    pub fn to_str(&self, top: usize, buf: &mut dyn Write) -> io::Result<()> {
+        buf.write_fmt(format_args!("    FILE SIZE        VM SIZE    \n"))?;
+        buf.write_fmt(format_args!(" --------------  -------------- \n"))?;
+
+        for row in self.rows.iter().take(top) {
+            self.write_row(&row.name, row.file_size, row.vm_size, buf)?;
+        }
+        if self.rows.len() > top {
+            let others = &self.rows[top..];
+            self.write_row(
+                &format!("[{} Others]", others.len()),
+                others.iter().map(|row| row.file_size).sum(),
+                others.iter().map(|row| row.vm_size).sum(),
+                buf,
+            )?;
+        }
+
+        self.write_row("TOTAL", self.file_size, self.vm_size, buf)
+    }
+
+    fn write_row(
+        &self,
+        name: &str,
+        file_size: u64,
+        vm_size: u64,
+        buf: &mut dyn Write,
+    ) -> io::Result<()> {
+        buf.write_fmt(format_args!(
+            " {:>6} {:>7} {:>6} {:>7}    {}\n",
+            percent(file_size, self.file_size),
+            human_size(file_size),
+            percent(vm_size, self.vm_size),
+            human_size(vm_size),
+            name
+        ))
+    }
+}
+
+fn percent(part: u64, total: u64) -> String {
+    match total {
+        0 => String::from("0.0%"),
+        _ => format!("{:.1}%", part as f64 * 100.0 / total as f64),
+    }
+}
+
+// A size in bytes below 1 KiB, and otherwise to three significant digits
+// in binary units, like "12.3Ki".
+fn human_size(size: u64) -> String {
+    let mut value = size as f64;
+    let mut unit = "";
+
+    for next in ["Ki", "Mi", "Gi", "Ti"] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+
+    match (unit, value) {
+        ("", _) => size.to_string(),
+        (_, v) if v < 10.0 => format!("{:.2}{}", v, unit),
+        (_, v) if v < 100.0 => format!("{:.1}{}", v, unit),
+        (_, v) => format!("{:.0}{}", v, unit),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::Scratch;
+
+    #[test]
+    fn mapped_headers_and_padding() {
+        let scratch = Scratch::new("size-breakdown");
+        scratch.write("t.c", "int main(void) { return 0; }\n");
+        let data = scratch.cc("t", &["-pie", "-fPIE", "t.c"]);
+        let elf = Elf::from_bytes(data, elf::Options::default()).unwrap();
+
+        let breakdown = Breakdown::new(&elf, Source::Sections, false);
+        let row = |name: &str| breakdown.rows.iter().find(|row| row.name == name);
+        let headers = row("[ELF Headers]").unwrap();
+        assert_eq!(
+            headers.vm_size,
+            (mem::size_of::<Elf64Ehdr>() + mem::size_of_val(elf.program_headers())) as u64
+        );
+        assert!(row("[Unmapped]").is_none_or(|row| row.vm_size == 0));
+        assert!(breakdown
+            .rows
+            .iter()
+            .any(|row| row.name.ends_with(" padding]")));
+
+        let file_size: u64 = breakdown.rows.iter().map(|row| row.file_size).sum();
+        let vm_size: u64 = breakdown.rows.iter().map(|row| row.vm_size).sum();
+        assert_eq!(file_size, breakdown.file_size);
+        assert_eq!(vm_size, breakdown.vm_size);
+    }
+}
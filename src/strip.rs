@@ -0,0 +1,132 @@
+use std::io;
+
+use crate::elf;
+use crate::writer::{ElfWriter, Section};
+
+/// What `ElfWriter::strip` removes, mirroring the options of GNU strip.
+#[derive(Clone, Default)]
+pub struct StripOptions {
+    /// Remove the debugging sections.
+    pub strip_debug: bool,
+    /// Remove the debugging sections and the symbol table.
+    pub strip_all: bool,
+    /// Keep only what a separate debug file needs: the contents of
+    /// allocated sections are dropped, their headers are kept.
+    pub only_keep_debug: bool,
+    /// Remove the sections whose name matches one of these patterns, where
+    /// '*' matches any run of characters and '?' any single one.
+    pub remove_sections: Vec<String>,
+}
+
+pub fn is_debug_section(name: &str) -> bool {
+    name.starts_with(".debug")
+        || name.starts_with(".zdebug")
+        || name.starts_with(".gnu.debuglto_")
+        || name.starts_with(".stab")
+        || name == ".line"
+        || name == ".gdb_index"
+}
+
+/// Match `name` against a shell-style pattern made of '*' and '?'.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[u8], name: &[u8]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some((b'*', rest)) => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+            Some((b'?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+            Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+        }
+    }
+
+    matches(pattern.as_bytes(), name.as_bytes())
+}
+
+impl ElfWriter {
+    /// Remove the sections `options` selects and pack what is left, so the
+    /// file shrinks by the space they took.
+    pub fn strip(&mut self, options: &StripOptions) -> io::Result<()> {
+        if options.only_keep_debug {
+            self.keep_debug();
+        }
+
+        // A relocatable object still needs the symbols its relocations
+        // refer to, so its symbol table is kept.
+        let symtabs: Vec<usize> = self
+            .sections
+            .iter()
+            .enumerate()
+            .filter(|(i, s)| {
+                s.header.sh_type == elf::SHT_SYMTAB
+                    && !self.sections.iter().any(|r| {
+                        (r.header.sh_type == elf::SHT_REL || r.header.sh_type == elf::SHT_RELA)
+                            && r.header.sh_link as usize == *i
+                            && !is_debug_section(&r.name)
+                    })
+            })
+            .map(|(i, _)| i)
+            .collect();
+        let strtabs: Vec<usize> = symtabs
+            .iter()
+            .map(|&i| self.sections[i].header.sh_link as usize)
+            .filter(|&i| i != self.shstrndx)
+            .collect();
+
+        let remove = |i: usize, section: &Section| {
+            let debug = options.strip_debug || options.strip_all;
+
+            (debug && is_debug_section(&section.name))
+                || (options.strip_all
+                    && (symtabs.contains(&i)
+                        || strtabs.contains(&i)
+                        || section.header.sh_type == elf::SHT_SYMTAB_SHNDX
+                            && symtabs.contains(&(section.header.sh_link as usize))))
+                || options
+                    .remove_sections
+                    .iter()
+                    .any(|pattern| glob_match(pattern, &section.name))
+        };
+
+        self.remove_sections(remove)?;
+        self.pack()
+    }
+
+    // Turn the contents of allocated sections into NOBITS placeholders, and
+    // the segments mapping them into empty ones. Notes stay, debuggers use
+    // the build ID to match the debug file.
+    fn keep_debug(&mut self) {
+        for section in self.sections.iter_mut() {
+            let header = &mut section.header;
+            if header.sh_flags & elf::SHF_ALLOC as u64 != 0
+                && header.sh_type != elf::SHT_NOTE
+                && header.sh_type != elf::SHT_NOBITS
+            {
+                header.sh_type = elf::SHT_NOBITS;
+                section.data.clear();
+            }
+        }
+
+        // Each segment now only covers the headers and notes at its start.
+        let phdrs_end =
+            self.header.e_phoff + (self.segments.len() * self.header.e_phentsize as usize) as u64;
+        for phdr in self.segments.iter_mut() {
+            let end = phdr.p_offset + phdr.p_filesz;
+            let kept = self
+                .sections
+                .iter()
+                .filter(|s| s.header.sh_flags & elf::SHF_ALLOC as u64 != 0)
+                .filter(|s| s.header.sh_type != elf::SHT_NOBITS)
+                .map(|s| (s.header.sh_offset, s.header.sh_offset + s.header.sh_size))
+                .chain([(self.header.e_phoff, phdrs_end)])
+                .filter(|&(start, stop)| phdr.p_offset <= start && start < end && stop <= end)
+                .map(|(_, stop)| stop - phdr.p_offset)
+                .max();
+
+            phdr.p_filesz = kept.unwrap_or(0);
+            if phdr.p_filesz == 0 {
+                // Nothing is left to map; keep the offset congruent with
+                // the address but inside the much smaller file.
+                phdr.p_offset %= phdr.p_align.max(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::mem;
+
+use crate::elf::{self, read_struct, str_at, Elf, Elf64Shdr, Symbol};
+
+/// The version a dynamic symbol is defined with or asks for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolVersion {
+    pub name: String,
+    /// For a version needed from another library, by an undefined symbol
+    /// or a copy-relocated variable, the library it is needed from.
+    pub file: Option<String>,
+    /// Only references naming the version bind to it, not unversioned ones.
+    pub hidden: bool,
+}
+
+/// A symbol of .dynsym with its version from .gnu.version.
+pub struct DynamicSymbol<'a> {
+    pub symbol: &'a Symbol,
+    pub version: Option<SymbolVersion>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Elf64Verdef {
+    vd_version: u16,
+    vd_flags: u16,
+    vd_ndx: u16,
+    vd_cnt: u16,
+    vd_hash: u32,
+    vd_aux: u32,
+    vd_next: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Elf64Verdaux {
+    vda_name: u32,
+    vda_next: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub(crate) struct Elf64Verneed {
+    pub(crate) vn_version: u16,
+    pub(crate) vn_cnt: u16,
+    pub(crate) vn_file: u32,
+    pub(crate) vn_aux: u32,
+    pub(crate) vn_next: u32,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub(crate) struct Elf64Vernaux {
+    pub(crate) vna_hash: u32,
+    pub(crate) vna_flags: u16,
+    pub(crate) vna_other: u16,
+    pub(crate) vna_name: u32,
+    pub(crate) vna_next: u32,
+}
+
+pub const VER_NDX_LOCAL: u16 = 0; /* Symbol is local.  */
+pub const VER_NDX_GLOBAL: u16 = 1; /* Symbol is global.  */
+pub const VERSYM_HIDDEN: u16 = 0x8000; /* Symbol is not the default version.  */
+pub const VER_FLG_BASE: u16 = 0x1; /* Version definition of file itself */
+
+impl Elf {
+    fn section_by_type(&self, sh_type: u32) -> Option<&Elf64Shdr> {
+        self.section_headers()
+            .iter()
+            .find(|shdr| shdr.sh_type == sh_type)
+    }
+
+    fn linked_strtab(&self, shdr: &Elf64Shdr) -> &[u8] {
+        self.section_headers()
+            .get(shdr.sh_link as usize)
+            .map_or(&[], |link| self.section_data(link))
+    }
+
+    // The versions .gnu.version_d defines, with their index. The base
+    // definition, which names the file itself, is left out.
+    fn verdefs(&self) -> Vec<(u16, String)> {
+        let mut defs = Vec::new();
+
+        if let Some(shdr) = self.section_by_type(elf::SHT_GNU_VERDEF) {
+            let data = self.section_data(shdr);
+            let strtab = self.linked_strtab(shdr);
+            let mut off = 0;
+
+            for _ in 0..shdr.sh_info {
+                let def: Elf64Verdef = match read_struct(data, off) {
+                    Ok(def) => def,
+                    Err(_) => break,
+                };
+                let aux = read_struct::<Elf64Verdaux>(data, off + def.vd_aux as usize);
+                if let (Ok(aux), 0) = (aux, def.vd_flags & VER_FLG_BASE) {
+                    defs.push((def.vd_ndx, str_at(strtab, aux.vda_name as usize)));
+                }
+                if def.vd_next == 0 {
+                    break;
+                }
+                off += def.vd_next as usize;
+            }
+        }
+
+        defs
+    }
+
+    // The versions of .gnu.version_d and .gnu.version_r by their index.
+    fn version_names(&self) -> HashMap<u16, (String, Option<String>)> {
+        let mut names: HashMap<u16, (String, Option<String>)> = self
+            .verdefs()
+            .into_iter()
+            .map(|(ndx, name)| (ndx, (name, None)))
+            .collect();
+
+        if let Some(shdr) = self.section_by_type(elf::SHT_GNU_VERNEED) {
+            let data = self.section_data(shdr);
+            let strtab = self.linked_strtab(shdr);
+            let mut off = 0;
+
+            for _ in 0..shdr.sh_info {
+                let need: Elf64Verneed = match read_struct(data, off) {
+                    Ok(need) => need,
+                    Err(_) => break,
+                };
+                let file = str_at(strtab, need.vn_file as usize);
+                let mut aux_off = off + need.vn_aux as usize;
+
+                for _ in 0..need.vn_cnt {
+                    let aux: Elf64Vernaux = match read_struct(data, aux_off) {
+                        Ok(aux) => aux,
+                        Err(_) => break,
+                    };
+                    names.insert(
+                        aux.vna_other,
+                        (str_at(strtab, aux.vna_name as usize), Some(file.clone())),
+                    );
+                    if aux.vna_next == 0 {
+                        break;
+                    }
+                    aux_off += aux.vna_next as usize;
+                }
+
+                if need.vn_next == 0 {
+                    break;
+                }
+                off += need.vn_next as usize;
+            }
+        }
+
+        names
+    }
+
+    /// The symbols of .dynsym with their versions. Symbols of objects
+    /// without .gnu.version, and those bound to the base version, have
+    /// none.
+    pub fn dynamic_symbols(&self) -> Vec<DynamicSymbol<'_>> {
+        let dynsym = self
+            .section_headers()
+            .iter()
+            .position(|shdr| shdr.sh_type == elf::SHT_DYNSYM);
+        let table = match dynsym.and_then(|index| {
+            let name = self.section_name(&self.section_headers()[index]);
+            self.symbol_tables().iter().find(|table| table.name == name)
+        }) {
+            Some(table) => table,
+            None => return Vec::new(),
+        };
+
+        let versym = self
+            .section_by_type(elf::SHT_GNU_VERSYM)
+            .map_or(&[][..], |shdr| self.section_data(shdr));
+        let names = self.version_names();
+
+        table
+            .symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| {
+                let ndx = read_struct::<u16>(versym, i * mem::size_of::<u16>()).unwrap_or(0);
+                let version =
+                    names
+                        .get(&(ndx & !VERSYM_HIDDEN))
+                        .map(|(name, file)| SymbolVersion {
+                            name: name.clone(),
+                            file: file.clone(),
+                            hidden: ndx & VERSYM_HIDDEN != 0,
+                        });
+
+                DynamicSymbol { symbol, version }
+            })
+            .collect()
+    }
+
+    /// The names of the versions the object defines, such as the nodes of
+    /// a linker version script.
+    pub fn defined_versions(&self) -> Vec<String> {
+        self.verdefs().into_iter().map(|(_, name)| name).collect()
+    }
+
+    /// Whether the object defines symbol versions, so that unversioned
+    /// references only bind to default versions. Objects that merely need
+    /// versions from others, with a .gnu.version but no .gnu.version_d,
+    /// define their symbols unversioned.
+    pub fn has_symbol_versions(&self) -> bool {
+        !self.verdefs().is_empty()
+    }
+}
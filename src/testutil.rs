@@ -0,0 +1,42 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+/// A scratch directory test inputs are compiled in, removed when dropped.
+pub struct Scratch {
+    pub dir: PathBuf,
+}
+
+impl Scratch {
+    pub fn new(name: &str) -> Scratch {
+        let dir = env::temp_dir().join(format!("rself-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        Scratch { dir }
+    }
+
+    pub fn write(&self, name: &str, contents: &str) {
+        fs::write(self.dir.join(name), contents).unwrap();
+    }
+
+    /// Run the C compiler in the directory with `args` and return the
+    /// contents of `output`.
+    pub fn cc(&self, output: &str, args: &[&str]) -> Vec<u8> {
+        let status = Command::new("cc")
+            .current_dir(&self.dir)
+            .args(args)
+            .arg("-o")
+            .arg(output)
+            .status()
+            .unwrap();
+        assert!(status.success(), "cc {:?} failed", args);
+
+        fs::read(self.dir.join(output)).unwrap()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
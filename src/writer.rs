@@ -0,0 +1,734 @@
+use std::fs;
+use std::io;
+use std::mem;
+use std::slice;
+
+use crate::elf::{self, read_struct, Elf, Elf64Ehdr, Elf64Phdr, Elf64Shdr, Elf64Sym};
+use crate::lint::has_link;
+
+/// A section header together with its name and contents. The name is only
+/// written to .shstrtab when it differs from the one `sh_name` points at.
+#[derive(Clone)]
+pub struct Section {
+    pub name: String,
+    pub header: Elf64Shdr,
+    pub data: Vec<u8>,
+}
+
+/// An editable copy of an ELF file.
+///
+/// The original image is kept as the backdrop the headers and section
+/// contents are written over, so bytes not covered by any header (padding,
+/// or the contents of stripped section headers) survive and serializing an
+/// unmodified file reproduces it exactly.
+pub struct ElfWriter {
+    pub header: Elf64Ehdr,
+    pub segments: Vec<Elf64Phdr>,
+    pub sections: Vec<Section>,
+    pub shstrndx: usize,
+    image: Vec<u8>,
+}
+
+// The on-disk structures have no padding, so their bytes are their
+// serialized form.
+pub(crate) fn as_bytes<T: Copy>(v: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(v as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// The offset just past `len` bytes at `off`.
+fn span_end(off: u64, len: u64) -> io::Result<u64> {
+    off.checked_add(len)
+        .ok_or_else(|| invalid(format!("range {:#x}+{:#x} overflows", off, len)))
+}
+
+// The offset just past a table of `count` entries of `entsize` bytes.
+fn table_end(off: u64, count: usize, entsize: u16) -> io::Result<u64> {
+    span_end(off, count as u64 * entsize as u64)
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    match align {
+        0 | 1 => value,
+        _ => value.div_ceil(align) * align,
+    }
+}
+
+fn is_reloc(sh_type: u32) -> bool {
+    sh_type == elf::SHT_REL || sh_type == elf::SHT_RELA
+}
+
+const SYM_SIZE: usize = mem::size_of::<Elf64Sym>();
+
+fn str_at(table: &[u8], off: usize) -> &[u8] {
+    let table = table.get(off..).unwrap_or(&[]);
+    let end = table
+        .iter()
+        .position(|&c| c == b'\0')
+        .unwrap_or(table.len());
+
+    &table[..end]
+}
+
+impl ElfWriter {
+    /// Copy `elf` for editing. Sections, segments and header tables whose
+    /// data lies outside the file are rejected, as they cannot be written
+    /// back as they were.
+    pub fn new(elf: &Elf) -> io::Result<ElfWriter> {
+        let len = elf.data().len() as u64;
+        let header = elf.header();
+        let tables = [
+            (
+                header.e_phoff,
+                elf.program_headers().len(),
+                header.e_phentsize,
+                "program",
+            ),
+            (
+                header.e_shoff,
+                elf.section_headers().len(),
+                header.e_shensize,
+                "section",
+            ),
+        ];
+        for (off, count, entsize, what) in tables {
+            if count != 0 && table_end(off, count, entsize)? > len {
+                return Err(invalid(format!(
+                    "{} header table lies outside the file",
+                    what
+                )));
+            }
+        }
+        for shdr in elf.section_headers() {
+            if shdr.sh_type != elf::SHT_NOBITS
+                && shdr.sh_type != elf::SHT_NULL
+                && span_end(shdr.sh_offset, shdr.sh_size)? > len
+            {
+                return Err(invalid(format!(
+                    "section '{}' lies outside the file",
+                    elf.section_name(shdr)
+                )));
+            }
+        }
+        for phdr in elf.program_headers() {
+            if span_end(phdr.p_offset, phdr.p_filesz)? > len {
+                return Err(invalid(format!(
+                    "segment at {:#x} lies outside the file",
+                    phdr.p_offset
+                )));
+            }
+        }
+
+        let sections = elf
+            .section_headers()
+            .iter()
+            .map(|shdr| Section {
+                name: elf.section_name(shdr),
+                header: *shdr,
+                data: elf.section_data(shdr).to_vec(),
+            })
+            .collect();
+
+        Ok(ElfWriter {
+            header: *header,
+            segments: elf.program_headers().to_vec(),
+            sections,
+            shstrndx: elf.shstrndx(),
+            image: elf.data().to_vec(),
+        })
+    }
+
+    pub fn section_index(&self, name: &str) -> Option<usize> {
+        self.sections.iter().position(|s| s.name == name)
+    }
+
+    /// Make every section's `sh_name` point at its `name`, appending the
+    /// names that are missing from the section header string table.
+    pub fn update_shstrtab(&mut self) {
+        if self.shstrndx == 0 || self.shstrndx >= self.sections.len() {
+            return;
+        }
+
+        let mut table = self.sections[self.shstrndx].data.clone();
+
+        for section in self.sections.iter_mut() {
+            let off = section.header.sh_name as usize;
+            if str_at(&table, off) == section.name.as_bytes() {
+                continue;
+            }
+
+            section.header.sh_name = table.len() as u32;
+            table.extend_from_slice(section.name.as_bytes());
+            table.push(b'\0');
+        }
+
+        let shstrtab = &mut self.sections[self.shstrndx];
+        shstrtab.header.sh_size = table.len() as u64;
+        shstrtab.data = table;
+    }
+
+    /// Offset just past the last byte described by any header.
+    pub fn end_offset(&self) -> io::Result<u64> {
+        let mut end = table_end(
+            self.header.e_phoff,
+            self.segments.len(),
+            self.header.e_phentsize,
+        )?;
+        end = end.max(table_end(
+            self.header.e_shoff,
+            self.sections.len(),
+            self.header.e_shensize,
+        )?);
+        for s in self
+            .sections
+            .iter()
+            .filter(|s| s.header.sh_type != elf::SHT_NOBITS)
+        {
+            end = end.max(span_end(s.header.sh_offset, s.data.len() as u64)?);
+        }
+        for p in self.segments.iter().filter(|p| p.p_filesz != 0) {
+            end = end.max(span_end(p.p_offset, p.p_filesz)?);
+        }
+
+        Ok(end)
+    }
+
+    /// Append a non-allocated section holding `data`, e.g. a signature or a
+    /// resource blob. Sections that no segment covers are packed again to
+    /// make room for it and for its name.
+    pub fn add_section(&mut self, name: &str, data: Vec<u8>) -> io::Result<()> {
+        if self.section_index(name).is_some() {
+            return Err(invalid(format!("section '{}' already exists", name)));
+        }
+        if self.shstrndx == 0 || self.shstrndx >= self.sections.len() {
+            return Err(invalid("no section header string table".to_string()));
+        }
+
+        let header = Elf64Shdr {
+            sh_type: elf::SHT_PROGBITS,
+            sh_offset: self.end_offset()?,
+            sh_size: data.len() as u64,
+            sh_addralign: 1,
+            ..Elf64Shdr::default()
+        };
+        self.sections.push(Section {
+            name: name.to_string(),
+            header,
+            data,
+        });
+
+        self.update_shstrtab();
+        self.pack()
+    }
+
+    /// Replace the contents of section `name`. Allocated sections are part
+    /// of the memory image and must keep their size.
+    pub fn update_section(&mut self, name: &str, data: Vec<u8>) -> io::Result<()> {
+        let i = self
+            .section_index(name)
+            .ok_or_else(|| invalid(format!("no section named '{}'", name)))?;
+        let section = &mut self.sections[i];
+
+        if section.header.sh_type == elf::SHT_NOBITS {
+            return Err(invalid(format!("section '{}' has no contents", name)));
+        }
+        if section.header.sh_flags & elf::SHF_ALLOC as u64 != 0
+            && data.len() as u64 != section.header.sh_size
+        {
+            return Err(invalid(format!(
+                "cannot resize allocated section '{}' ({:#x} bytes) to {:#x} bytes",
+                name,
+                section.header.sh_size,
+                data.len()
+            )));
+        }
+
+        section.header.sh_size = data.len() as u64;
+        section.data = data;
+
+        self.pack()
+    }
+
+    /// Drop the original bytes past `len`, e.g. after sections at the end of
+    /// the file were removed or moved.
+    pub fn truncate(&mut self, len: u64) {
+        self.image.truncate(len as usize);
+    }
+
+    /// Remove the sections `remove` selects, along with the relocations
+    /// that apply to them, and renumber every reference to a section index:
+    /// `sh_link`, `sh_info`, `e_shstrndx`, section groups and symbols.
+    ///
+    /// Symbols defined in a removed section are dropped from .symtab and
+    /// the relocations using it are renumbered to match; .dynsym keeps its
+    /// layout, such symbols become absolute.
+    pub fn remove_sections<F>(&mut self, mut remove: F) -> io::Result<()>
+    where
+        F: FnMut(usize, &Section) -> bool,
+    {
+        let count = self.sections.len();
+        let mut removed: Vec<bool> = self
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(i, s)| i != 0 && remove(i, s))
+            .collect();
+
+        for (i, s) in self.sections.iter().enumerate() {
+            let target = s.header.sh_info as usize;
+            if is_reloc(s.header.sh_type)
+                && s.header.sh_flags & elf::SHF_ALLOC as u64 == 0
+                && target != 0
+                && target < count
+                && removed[target]
+            {
+                removed[i] = true;
+            }
+        }
+
+        if !removed.iter().any(|&r| r) {
+            return Ok(());
+        }
+        if removed.get(self.shstrndx).copied().unwrap_or(false) {
+            return Err(invalid(
+                "cannot remove the section header string table".to_string(),
+            ));
+        }
+
+        let mut map = vec![None; count];
+        let mut next = 0;
+        for (i, &r) in removed.iter().enumerate() {
+            if !r {
+                map[i] = Some(next as u32);
+                next += 1;
+            }
+        }
+
+        for i in 0..count {
+            if removed[i] {
+                continue;
+            }
+
+            match self.sections[i].header.sh_type {
+                elf::SHT_SYMTAB => self.prune_symbols(i, &map, &removed)?,
+                elf::SHT_DYNSYM => self.renumber_symbols(i, &map),
+                elf::SHT_GROUP => {
+                    let section = &mut self.sections[i];
+                    let mut words = section
+                        .data
+                        .chunks_exact(4)
+                        .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]));
+                    let mut data = Vec::with_capacity(section.data.len());
+                    if let Some(flags) = words.next() {
+                        data.extend_from_slice(&flags.to_le_bytes());
+                    }
+                    for index in words {
+                        if let Some(Some(n)) = map.get(index as usize) {
+                            data.extend_from_slice(&n.to_le_bytes());
+                        }
+                    }
+                    section.header.sh_size = data.len() as u64;
+                    section.data = data;
+                }
+                _ => {}
+            }
+        }
+
+        let renumber = |index: u32| map.get(index as usize).copied().flatten().unwrap_or(0);
+        for section in self.sections.iter_mut() {
+            let header = &mut section.header;
+            if has_link(header.sh_type) || header.sh_flags & elf::SHF_LINK_ORDER as u64 != 0 {
+                header.sh_link = renumber(header.sh_link);
+            }
+            if is_reloc(header.sh_type) || header.sh_flags & elf::SHF_INFO_LINK as u64 != 0 {
+                header.sh_info = renumber(header.sh_info);
+            }
+        }
+
+        self.shstrndx = renumber(self.shstrndx as u32) as usize;
+        let mut i = 0;
+        self.sections.retain(|_| {
+            i += 1;
+            !removed[i - 1]
+        });
+
+        Ok(())
+    }
+
+    pub(crate) fn symbols(&self, i: usize) -> Vec<Elf64Sym> {
+        self.sections[i]
+            .data
+            .chunks_exact(SYM_SIZE)
+            .filter_map(|chunk| read_struct::<Elf64Sym>(chunk, 0).ok())
+            .collect()
+    }
+
+    // The extended section indices of symbol table `i`, if it has any.
+    fn xindex_section(&self, i: usize) -> Option<usize> {
+        self.sections.iter().position(|s| {
+            s.header.sh_type == elf::SHT_SYMTAB_SHNDX && s.header.sh_link as usize == i
+        })
+    }
+
+    // Renumber the section indices of a symbol table whose layout must not
+    // change. Symbols of removed sections become absolute.
+    fn renumber_symbols(&mut self, i: usize, map: &[Option<u32>]) {
+        let mut data = Vec::with_capacity(self.sections[i].data.len());
+
+        for mut sym in self.symbols(i) {
+            let shndx = sym.st_shndx;
+            if shndx != elf::SHN_UNDEF && shndx < elf::SHN_LORESERVE {
+                sym.st_shndx = match map.get(shndx as usize).copied().flatten() {
+                    Some(n) => n as u16,
+                    None => elf::SHN_ABS,
+                };
+            }
+            data.extend_from_slice(as_bytes(&sym));
+        }
+
+        self.sections[i].data = data;
+    }
+
+    // Drop the symbols defined in removed sections from symbol table `i`,
+    // renumber the rest and the relocations referring to them.
+    fn prune_symbols(&mut self, i: usize, map: &[Option<u32>], removed: &[bool]) -> io::Result<()> {
+        let symbols = self.symbols(i);
+        let xindex_section = self.xindex_section(i);
+        let xindex: Vec<u32> = xindex_section.map_or_else(Vec::new, |x| {
+            self.sections[x]
+                .data
+                .chunks_exact(4)
+                .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+                .collect()
+        });
+
+        let first_global = self.sections[i].header.sh_info as usize;
+        let mut sym_map = vec![None; symbols.len()];
+        let mut data = Vec::with_capacity(self.sections[i].data.len());
+        let mut new_xindex = Vec::with_capacity(xindex.len() * 4);
+        let mut locals = 0;
+
+        for (j, mut sym) in symbols.into_iter().enumerate() {
+            let mut shndx = sym.st_shndx as u32;
+            if sym.st_shndx == elf::SHN_XINDEX {
+                shndx = xindex.get(j).copied().unwrap_or(0);
+            }
+
+            let mut ext = 0;
+            if j != 0
+                && shndx != elf::SHN_UNDEF as u32
+                && (sym.st_shndx == elf::SHN_XINDEX || shndx < elf::SHN_LORESERVE as u32)
+            {
+                match map.get(shndx as usize).copied().flatten() {
+                    None => continue,
+                    Some(n) if n >= elf::SHN_LORESERVE as u32 => {
+                        sym.st_shndx = elf::SHN_XINDEX;
+                        ext = n;
+                    }
+                    Some(n) => sym.st_shndx = n as u16,
+                }
+            }
+
+            if j < first_global {
+                locals += 1;
+            }
+            sym_map[j] = Some((data.len() / SYM_SIZE) as u64);
+            data.extend_from_slice(as_bytes(&sym));
+            new_xindex.extend_from_slice(&ext.to_le_bytes());
+        }
+
+        for (k, section) in self.sections.iter_mut().enumerate() {
+            let header = &section.header;
+            if removed[k] || !is_reloc(header.sh_type) || header.sh_link as usize != i {
+                continue;
+            }
+
+            let entsize = if header.sh_type == elf::SHT_RELA {
+                24
+            } else {
+                16
+            };
+            for entry in section.data.chunks_exact_mut(entsize) {
+                let r_info = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+                let sym = (r_info >> 32) as usize;
+                if sym == 0 {
+                    continue;
+                }
+
+                let new = sym_map.get(sym).copied().flatten().ok_or_else(|| {
+                    invalid(format!(
+                        "relocations in {} refer to a symbol of a removed section",
+                        section.name
+                    ))
+                })?;
+                let r_info = (new << 32) | (r_info & 0xffff_ffff);
+                entry[8..16].copy_from_slice(&r_info.to_le_bytes());
+            }
+        }
+
+        let symtab = &mut self.sections[i];
+        symtab.header.sh_size = data.len() as u64;
+        symtab.header.sh_info = locals;
+        symtab.data = data;
+
+        if let Some(x) = xindex_section {
+            let shndx = &mut self.sections[x];
+            shndx.header.sh_size = new_xindex.len() as u64;
+            shndx.data = new_xindex;
+        }
+
+        Ok(())
+    }
+
+    /// Lay out the sections no segment covers again, packed one after the
+    /// other past the last segment and followed by the section header
+    /// table, so the space of removed or shrunk sections is reclaimed.
+    /// Segment contents keep their offsets.
+    pub fn pack(&mut self) -> io::Result<()> {
+        let segments = &self.segments;
+        let mut spans = Vec::with_capacity(segments.len());
+        for phdr in segments.iter() {
+            spans.push((phdr.p_offset, span_end(phdr.p_offset, phdr.p_filesz)?));
+        }
+        let covered = |off: u64, end: u64| {
+            spans
+                .iter()
+                .any(|&(p_start, p_end)| p_start <= off && end <= p_end)
+        };
+
+        let mut end = self.header.e_ehsize as u64;
+        if !segments.is_empty() {
+            end = end.max(table_end(
+                self.header.e_phoff,
+                segments.len(),
+                self.header.e_phentsize,
+            )?);
+        }
+        for phdr in segments.iter().filter(|p| p.p_filesz != 0) {
+            end = end.max(span_end(phdr.p_offset, phdr.p_filesz)?);
+        }
+        let image_end = end;
+
+        let mut order = Vec::new();
+        for (i, section) in self.sections.iter().enumerate().skip(1) {
+            let off = section.header.sh_offset;
+            if !covered(off, span_end(off, section.data.len() as u64)?) {
+                order.push(i);
+            }
+        }
+        order.sort_by_key(|&i| self.sections[i].header.sh_offset);
+
+        for i in order {
+            let section = &mut self.sections[i];
+            if section.header.sh_type == elf::SHT_NOBITS {
+                section.header.sh_offset = end;
+                continue;
+            }
+
+            let off = align_up(end, section.header.sh_addralign);
+            section.header.sh_offset = off;
+            end = span_end(off, section.data.len() as u64)?;
+        }
+
+        self.header.e_shoff = match self.sections.len() {
+            0 => 0,
+            _ => align_up(end, 8),
+        };
+        self.image.truncate(image_end as usize);
+        Ok(())
+    }
+
+    /// Overwrite the file at `off` with `bytes`, growing it if needed. The
+    /// contents of sections overlapping the range are patched as well, so
+    /// the change survives `to_bytes` whether or not a section covers it.
+    pub fn write_at(&mut self, off: u64, bytes: &[u8]) -> io::Result<()> {
+        let start = off as usize;
+        let end = span_end(off, bytes.len() as u64)? as usize;
+
+        if self.image.len() < end {
+            self.image.resize(end, 0);
+        }
+        self.image[start..end].copy_from_slice(bytes);
+
+        for section in self.sections.iter_mut() {
+            if section.header.sh_type == elf::SHT_NOBITS {
+                continue;
+            }
+
+            let s_start = section.header.sh_offset as usize;
+            let s_end = s_start + section.data.len();
+            let (lo, hi) = (start.max(s_start), end.min(s_end));
+            if lo < hi {
+                section.data[lo - s_start..hi - s_start]
+                    .copy_from_slice(&bytes[lo - start..hi - start]);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut header = self.header;
+        let mut sections = self.sections.clone();
+        let mut out = self.image.clone();
+
+        // Counts that overflow the ELF header move to section header 0.
+        // Fields that already decode to the right value are left alone, so
+        // unusual but valid encodings survive a round trip.
+        let xnum = sections.first().map(|s| s.header);
+        let shnum = sections.len();
+        let phnum = self.segments.len();
+
+        let decoded_shnum = match xnum {
+            Some(shdr0) if header.e_shnum == 0 => shdr0.sh_size as usize,
+            _ => header.e_shnum as usize,
+        };
+        if decoded_shnum != shnum {
+            if shnum >= elf::SHN_LORESERVE as usize {
+                header.e_shnum = 0;
+                sections[0].header.sh_size = shnum as u64;
+            } else {
+                if header.e_shnum == 0 && !sections.is_empty() {
+                    sections[0].header.sh_size = 0;
+                }
+                header.e_shnum = shnum as u16;
+            }
+        }
+
+        let decoded_shstrndx = match xnum {
+            Some(shdr0) if header.e_shstrndx == elf::SHN_XINDEX => shdr0.sh_link as usize,
+            _ => header.e_shstrndx as usize,
+        };
+        if decoded_shstrndx != self.shstrndx {
+            if self.shstrndx >= elf::SHN_LORESERVE as usize {
+                header.e_shstrndx = elf::SHN_XINDEX;
+                sections[0].header.sh_link = self.shstrndx as u32;
+            } else {
+                if header.e_shstrndx == elf::SHN_XINDEX && !sections.is_empty() {
+                    sections[0].header.sh_link = 0;
+                }
+                header.e_shstrndx = self.shstrndx as u16;
+            }
+        }
+
+        let decoded_phnum = match xnum {
+            Some(shdr0) if header.e_phnum == elf::PN_XNUM => shdr0.sh_info as usize,
+            _ => header.e_phnum as usize,
+        };
+        if decoded_phnum != phnum {
+            if phnum >= elf::PN_XNUM as usize && !sections.is_empty() {
+                header.e_phnum = elf::PN_XNUM;
+                sections[0].header.sh_info = phnum as u32;
+            } else {
+                if header.e_phnum == elf::PN_XNUM && !sections.is_empty() {
+                    sections[0].header.sh_info = 0;
+                }
+                header.e_phnum = phnum as u16;
+            }
+        }
+
+        let end = self.end_offset()? as usize;
+        if out.len() < end {
+            out.resize(end, 0);
+        }
+
+        let mut put = |off: usize, bytes: &[u8]| {
+            out[off..off + bytes.len()].copy_from_slice(bytes);
+        };
+
+        put(0, as_bytes(&header));
+
+        for (i, phdr) in self.segments.iter().enumerate() {
+            put(
+                header.e_phoff as usize + i * header.e_phentsize as usize,
+                as_bytes(phdr),
+            );
+        }
+
+        for section in sections.iter() {
+            if section.header.sh_type != elf::SHT_NOBITS {
+                put(section.header.sh_offset as usize, &section.data);
+            }
+        }
+
+        for (i, section) in sections.iter().enumerate() {
+            put(
+                header.e_shoff as usize + i * header.e_shensize as usize,
+                as_bytes(&section.header),
+            );
+        }
+
+        Ok(out)
+    }
+
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_bytes()?)
+    }
+}
+
+impl Elf {
+    /// Serialize the file back to bytes; unmodified, this is the original.
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        ElfWriter::new(self)?.to_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::Options;
+    use crate::testutil::Scratch;
+
+    const SOURCE: &str = "int counter = 1;\nint next(void) { return counter++; }\n\
+                          int main(void) { return next() - 1; }\n";
+
+    // Build SOURCE with the C compiler and `args`, returning the output.
+    fn compile(name: &str, args: &[&str]) -> Vec<u8> {
+        let scratch = Scratch::new(&format!("writer-{}", name));
+        scratch.write("t.c", SOURCE);
+        scratch.cc(name, &[args, &["t.c"]].concat())
+    }
+
+    fn round_trip(data: Vec<u8>) {
+        let elf = Elf::from_bytes(data.clone(), Options::default()).unwrap();
+        assert!(elf.to_bytes().unwrap() == data);
+    }
+
+    #[test]
+    fn round_trips_object() {
+        round_trip(compile("t.o", &["-c", "-g"]));
+    }
+
+    #[test]
+    fn round_trips_pie() {
+        round_trip(compile("t", &["-fPIE", "-pie"]));
+    }
+
+    #[test]
+    fn round_trips_stripped_shared_library() {
+        round_trip(compile("t.so", &["-fPIC", "-shared", "-s"]));
+    }
+
+    #[test]
+    fn rejects_section_outside_file() {
+        let mut data = compile("bad.o", &["-c"]);
+        let elf = Elf::from_bytes(data.clone(), Options::default()).unwrap();
+        let i = elf
+            .section_headers()
+            .iter()
+            .position(|shdr| elf.section_name(shdr) == ".text")
+            .unwrap();
+
+        // Move .text past the end of the file.
+        let shdr = elf.header().e_shoff as usize + i * mem::size_of::<Elf64Shdr>();
+        let off = shdr + mem::offset_of!(Elf64Shdr, sh_offset);
+        let end = data.len() as u64;
+        data[off..off + 8].copy_from_slice(&end.to_le_bytes());
+
+        let elf = Elf::from_bytes(data, Options::default()).unwrap();
+        assert!(ElfWriter::new(&elf).is_err());
+    }
+}